@@ -1,299 +1,1316 @@
-#[derive(Clone)]
-struct TextChange {
-    text: String,
-    buffer_id: usize,
-}
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
 
-// events
-// const E_MOVE: &'static str = "move";
-// const E_TEXT_CHANGE: &'static str = "text_change";
+use crossterm::event::{KeyCode, KeyEvent};
+use log::{error, info};
+use regex::Regex;
 
-#[derive(Eq, PartialEq, Hash)]
-pub struct AppEventKey(u8);
+use crate::{
+    config::{Config, Setting},
+    keymap::{self, Action, Keymaps, MapMode, Step},
+    modes::{git_branch, Cursor, EditorState, Modes, Picked, Picker},
+    renderer::{
+        terminal_size, Backend, CrosstermBackend, Direction, Popup, RendResult, Renderer, Theme,
+        Window, UI,
+    },
+};
 
-impl From<AppEvent> for AppEventKey {
-    fn from(e: AppEvent) -> Self {
-        match e {
-            AppEvent::TextChange(_) => AppEventKey(0),
-            AppEvent::Move(_, _) => AppEventKey(1),
-        }
-    }
-}
+use self::buffer::{Buffer, UndoStep};
+use self::commands::{Args, Registry, WINCMD};
+use self::events::{
+    BufferChanged, BufferOpened, BufferSaved, CursorMoved, EventBus, Flow, ModeChanged,
+    Subscription, WindowResized,
+};
+use self::ex::{Address, Ex, LineRange, Parsed};
+use self::substitute::Substitute;
+use self::tasks::{Done, Tasks};
 
-#[derive(Clone)]
-pub enum AppEvent {
-    TextChange(TextChange),
-    Move(i16, i16),
+/// line shown in place of the modeline until the next key
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Message {
+    Info(String),
+    Error(String),
 }
 
-use std::collections::HashMap;
-
-use crossterm::event::KeyCode;
-use log::info;
-use rust_fsm::StateMachine;
-
-use crate::{
-    experiments::UserMachine,
-    modes::{update_modeline, Ctx, Cursor, Modes},
-    renderer::{RendResult, Renderer, UI},
-};
-
-use self::buffer::Buffer;
+/// What is drawn around and over the windows: the command line or a
+/// message, and a popup
+#[derive(PartialEq, Eq)]
+struct Overlay {
+    prompt: Option<(char, String, usize)>,
+    message: Option<Message>,
+    popup: Option<Popup>,
+}
 
-pub struct App {
-    pub renderer: Renderer,
+pub struct App<B: Backend = CrosstermBackend> {
+    pub renderer: Renderer<B>,
     pub ui: UI,
-    pub user_machine: StateMachine<UserMachine>,
-    pub buffers: Vec<Buffer>,
     pub modes: Modes,
-    pub queue: Vec<AppEvent>,
-    // cbs: HashMap<AppEvent, Vec<Box<dyn FnOnce(&mut Ctx<'_>, &AppEvent)>>>,
-    pub cbs: HashMap<AppEventKey, Vec<fn(&mut Ctx<'_>, &AppEvent)>>,
+    pub events: EventBus,
+    /// active buffer with its cursor, and the mode, as last told to hooks
+    seen_cursor: Option<(usize, Cursor)>,
+    seen_mode: EditorState,
+    /// hooks writing buffers as they change, while `autosave` is set
+    autosave: Vec<Subscription>,
+    config: Config,
+    keymaps: Keymaps,
+    message: Option<Message>,
+    /// `:s` asking to confirm each replacement
+    substitute: Option<Substitute>,
+    /// list shown over the windows, taking keys until closed
+    picker: Option<Picker>,
+    /// commands to search and run one, taking keys until closed
+    palette: Option<Picker>,
+    commands: Registry<B>,
+    tasks: Tasks<B>,
+    /// something changed since the screen was last drawn
+    redraw: bool,
+    quit: bool,
 }
 
 impl Default for App {
     fn default() -> Self {
+        let buff = Buffer::build()
+            .with_text("scratch buffer".to_string())
+            .create();
+
+        Self::with_buffer(buff)
+    }
+}
+
+impl App {
+    /// Open `path` in the first window, see [`Buffer::from_file`]
+    pub fn with_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        let buff = Buffer::from_file(path)?;
+
+        Ok(Self::with_buffer(buff))
+    }
+
+    /// open `buff` on the terminal
+    fn with_buffer(buff: Buffer) -> Self {
+        let (rows, cols) = terminal_size();
+
+        Self::new(CrosstermBackend::new(), rows, cols, buff)
+    }
+}
+
+impl<B: Backend> App<B> {
+    /// Show `buff` in the first window, drawing to `backend` which is `rows`
+    /// by `cols`
+    pub fn new(backend: B, rows: usize, cols: usize, buff: Buffer) -> Self {
         info!("creating app");
+
         let mut app = Self {
-            renderer: Default::default(),
-            buffers: Default::default(),
-            user_machine: Default::default(),
+            renderer: Renderer::new(backend, rows, cols),
             modes: Default::default(),
-            queue: Default::default(),
-            cbs: Default::default(),
-            ui: Default::default(),
+            events: Default::default(),
+            seen_cursor: None,
+            seen_mode: EditorState::Normal,
+            autosave: Vec::new(),
+            ui: UI::new(rows, cols),
+            config: Default::default(),
+            keymaps: Default::default(),
+            message: None,
+            substitute: None,
+            picker: None,
+            palette: None,
+            commands: Default::default(),
+            tasks: Default::default(),
+            redraw: true,
+            quit: false,
         };
 
         info!("created app: {:?}", &app);
 
-        {
-            let buff = Buffer::build()
-                .with_text("scratch buffer".to_string())
-                .with_window(0)
-                .create();
+        app.hooks();
 
-            app.ui.get_active_window().set_buffer(0, &buff);
+        let id = app.add_buffer(buff);
+        app.modes.buff.set_active(id);
+        app.show_buffer(id);
+        app.notify();
 
-            app.buffers.push(buff);
+        app
+    }
+}
+
+impl<B: Backend> App<B> {
+    /// Act on a key, the screen is drawn by [`App::draw`]
+    pub fn handle(&mut self, key: KeyEvent) {
+        let shown = self.overlay();
+        self.message = None;
+
+        if self.picker.is_some() {
+            self.picker_key(key);
+        } else if self.palette.is_some() {
+            self.palette_key(key);
+        } else if self.substitute.is_some() {
+            self.confirm_key(key);
+        } else {
+            self.keymaps.push(key);
+            self.run_keys(false);
+            self.wait_for_keys();
         }
 
-        // {
-        //     // XXX: small hack just to get a first line in
+        self.keys_done(shown);
+    }
 
-        //     let first_line = app.modes.ui.body.get_mut(0).unwrap();
-        //     let buffer_line = &app
-        //         .modes
-        //         .buff
-        //         .buffers
-        //         .get(0)
-        //         .expect("buffer should exist")
-        //         .content;
+    /// Run the jobs whose timers have fired, see [`tasks`]
+    pub fn idle(&mut self) {
+        let due = self.tasks.due();
+        if due.is_empty() {
+            return;
+        }
 
-        //     let buffer_line = buffer_line.get(0).expect("buffer should have text");
+        let shown = self.overlay();
+        for job in due {
+            job(self);
+        }
+        self.keys_done(shown);
+    }
 
-        //     *first_line = buffer_line.clone();
+    /// take on the result of background work
+    pub fn finish(&mut self, done: Done<B>) {
+        let shown = self.overlay();
+        done(self);
+        self.keys_done(shown);
+    }
 
-        //     app.modes.ui.update_cursor(|Cursor { col, row }| Cursor {
-        //         row: *row,
-        //         col: col + buffer_line.len(),
-        //     });
-        // }
+    /// when [`App::idle`] next has work to do
+    pub fn deadline(&self) -> Option<Instant> {
+        self.tasks.deadline()
+    }
 
-        // app.cbs.insert(
-        //     AppEventKey::from(AppEvent::Move(0, 0)),
-        //     vec![update_modeline],
-        // );
+    /// Results of background work to pass to [`App::finish`], given once
+    pub fn results(&mut self) -> Option<tokio::sync::mpsc::UnboundedReceiver<Done<B>>> {
+        self.tasks.take_receiver()
+    }
 
-        app
+    /// Do `work` in the background, then `done` with its result once it is
+    /// passed to [`App::finish`]. Outside of a tokio runtime, as in tests,
+    /// the work is done right away
+    pub fn spawn<T, W, D>(&mut self, work: W, done: D)
+    where
+        T: Send + 'static,
+        W: FnOnce() -> T + Send + 'static,
+        D: FnOnce(&mut App<B>, T) + Send + 'static,
+    {
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                let sender = self.tasks.sender();
+                runtime.spawn_blocking(move || {
+                    let result = work();
+                    // the receiver is gone once the editor has quit
+                    let _ = sender.send(Box::new(move |app| done(app, result)));
+                });
+            }
+            Err(_) => done(self, work()),
+        }
     }
-}
 
-impl App {
-    pub fn handle(&mut self, code: KeyCode) -> RendResult {
-        // app is Idle / Prompt
-        // match (
-        //     self.user_machine.consume(&UserInput::Key(k)),
-        //     self.user_machine.state(),
-        // ) {
-        //     (Ok(Some(UserOutput::Choice(choice))), UserState::Idle) => {
-        //         self.renderer.paint(&choice)?;
-        //     }
-        //     (Ok(_), UserState::Prompt) => {
-        //         self.renderer.prompt()?;
-        //     }
-        //     _ => self.renderer.paint(&k)?,
-        // }
-        match code {
-            KeyCode::Backspace => {
-                self.modes.buff.backspace(&mut self.modes.ui);
-            }
-            KeyCode::Delete => todo!(),
-            KeyCode::Enter => {
-                self.modes.buff.insert_newline(&mut self.modes.ui);
-            }
-            KeyCode::Left => todo!(),
-            KeyCode::Right => todo!(),
-            KeyCode::Up => todo!(),
-            KeyCode::Down => todo!(),
-            KeyCode::Char(k) => {
-                self.modes
-                    .buff
-                    .insert_self(&mut self.modes.ui, &k.to_string(), &mut self.queue);
+    /// draw the screen if anything changed since it was last drawn
+    pub fn draw(&mut self) -> RendResult {
+        match std::mem::take(&mut self.redraw) {
+            true => self.render(),
+            false => Ok(()),
+        }
+    }
+
+    /// Run what the keys typed so far are bound to, see [`keymap`]. The
+    /// start of a longer binding is left pending unless `timed_out`
+    fn run_keys(&mut self, timed_out: bool) {
+        loop {
+            let mode = MapMode::from_state(self.modes.editor.state());
+
+            match self.keymaps.next(mode, timed_out) {
+                Some(Step::Key(key)) => self.editor_key(key),
+                Some(Step::Run(Action::Keys(keys))) => {
+                    keys.into_iter().for_each(|key| self.editor_key(key))
+                }
+                Some(Step::Run(Action::Command { name, arg })) => {
+                    let count = self.modes.editor.take_count();
+                    self.command(&name, count, &arg);
+                }
+                None => break,
             }
-            _ => (),
         }
+    }
 
-        // let ui = self.modes.ui.for_tui();
+    /// Flush the keys held back as the start of a longer binding once
+    /// `timeoutlen` passes without another key
+    fn wait_for_keys(&mut self) {
+        match self.keymaps.pending().is_empty() {
+            true => self.tasks.cancel("timeoutlen"),
+            false => self.tasks.debounce(
+                "timeoutlen",
+                self.keymaps.timeout(),
+                Box::new(|app| app.run_keys(true)),
+            ),
+        }
+    }
 
-        // self.renderer.draw(ui)?;
-        // self.renderer.cursor(&self.modes.ui.cursor)?;
-        let screen = self.ui.screen();
-        self.renderer.render(screen)?;
+    fn editor_key(&mut self, key: KeyEvent) {
+        let window = self.ui.get_active_window();
+        let Modes { buff, editor, .. } = &mut self.modes;
 
-        Ok(())
+        if let Some(command) = editor.handle(key, buff, window) {
+            self.execute(&command);
+        }
+
+        if let Some(e) = self.modes.editor.take_error() {
+            self.message = Some(Message::Error(e));
+        }
+    }
+
+    /// Tell the hooks what the keys changed, and draw the screen again if
+    /// they changed it or what was `shown` outside of the windows
+    fn keys_done(&mut self, shown: Overlay) {
+        if self.notify() {
+            self.redraw_windows();
+        }
+
+        self.highlight_search();
+        if self.overlay() != shown {
+            self.redraw = true;
+        }
+    }
+
+    fn overlay(&self) -> Overlay {
+        Overlay {
+            prompt: self
+                .modes
+                .editor
+                .prompt()
+                .map(|(prompt, line)| (prompt, line.text().to_string(), line.cursor_width())),
+            message: self.message.clone(),
+            popup: self.popup(),
+        }
     }
 
-    pub fn idle(&mut self) -> RendResult {
-        let mut events = Vec::new();
-        events.append(&mut self.queue);
+    /// Emit events for what changed since the last call and run the hooks
+    /// of those and any emitted before, true when the text of a buffer
+    /// changed. The git branch the modeline asks for is looked up in the
+    /// background
+    fn notify(&mut self) -> bool {
+        let changes = self.modes.buff.text_changes();
+        let changed = !changes.is_empty();
+        changes
+            .into_iter()
+            .for_each(|change| self.events.emit(change));
 
-        let mut ctx = Ctx {
-            modes: &mut self.modes,
+        let buffer = self.modes.buff.active();
+        let cursor = *self.modes.buff.get_active_buffer().get_cursor();
+        if self.seen_cursor != Some((buffer, cursor)) {
+            self.seen_cursor = Some((buffer, cursor));
+            self.events.emit(CursorMoved { buffer, cursor });
+            self.redraw = true;
+        }
+
+        let mode = self.modes.editor.state();
+        if mode != self.seen_mode {
+            let from = std::mem::replace(&mut self.seen_mode, mode);
+            self.events.emit(ModeChanged { from, to: mode });
+            self.redraw = true;
+        }
+
+        self.events.dispatch(&mut self.modes);
+
+        if let Some(dir) = self.modes.modeline.take_lookup() {
+            self.spawn(
+                move || {
+                    let branch = git_branch(&dir);
+                    (dir, branch)
+                },
+                |app, (dir, branch)| {
+                    app.modes.modeline.set_branch(&dir, branch);
+                    app.redraw = true;
+                },
+            );
+        }
+
+        changed
+    }
+
+    /// Hooks keeping syntax highlighting and the modeline up to date
+    fn hooks(&mut self) {
+        // parsed first, so later hooks see the highlights of the new text
+        self.events.subscribe(-10, |ctx, change: &BufferChanged| {
+            if let Some(buffer) = ctx.modes.buff.get_mut(change.buffer) {
+                buffer.parse_syntax();
+            }
+            Flow::Continue
+        });
+
+        // the modeline shows the state of the active buffer
+        let buffer = |ctx: &mut events::Ctx, id: usize| {
+            let Modes { buff, modeline, .. } = &mut *ctx.modes;
+            if let Some(buffer) = buff.get(id).filter(|_| id == buff.active()) {
+                modeline.set_buffer(buffer);
+            }
+            Flow::Continue
         };
+        self.events
+            .subscribe(0, move |ctx, change: &BufferChanged| {
+                buffer(ctx, change.buffer)
+            });
+        self.events
+            .subscribe(0, move |ctx, saved: &BufferSaved| buffer(ctx, saved.buffer));
+        self.events.subscribe(0, move |ctx, moved: &CursorMoved| {
+            ctx.modes.modeline.set_cursor(moved.cursor);
+            buffer(ctx, moved.buffer)
+        });
+        self.events.subscribe(0, |ctx, mode: &ModeChanged| {
+            ctx.modes.modeline.set_mode(mode.to);
+            Flow::Continue
+        });
+
+        self.events.subscribe(0, |_, opened: &BufferOpened| {
+            info!("opened buffer {}", opened.buffer);
+            Flow::Continue
+        });
+        self.events.subscribe(0, |_, saved: &BufferSaved| {
+            info!("saved buffer {} to {}", saved.buffer, saved.path.display());
+            Flow::Continue
+        });
+        self.events.subscribe(0, |_, size: &WindowResized| {
+            info!("resized to {} rows {} cols", size.rows, size.cols);
+            Flow::Continue
+        });
+    }
 
-        for e in events {
-            if let Some(cbs) = self.cbs.get(&e.clone().into()) {
-                for cb in cbs {
-                    cb(&mut ctx, &e);
+    /// Write buffers with a file once they change outside insert mode, or
+    /// on leaving insert mode, while `on`
+    fn set_autosave(&mut self, on: bool) {
+        if !on {
+            for subscription in self.autosave.drain(..) {
+                self.events.unsubscribe(subscription);
+            }
+            return;
+        }
+
+        if !self.autosave.is_empty() {
+            return;
+        }
+
+        let changed = self.events.subscribe(10, |ctx, change: &BufferChanged| {
+            if ctx.modes.editor.state() != EditorState::Insert {
+                autosave(ctx, change.buffer);
+            }
+            Flow::Continue
+        });
+        let left_insert = self.events.subscribe(10, |ctx, mode: &ModeChanged| {
+            if mode.from == EditorState::Insert {
+                let active = ctx.modes.buff.active();
+                autosave(ctx, active);
+            }
+            Flow::Continue
+        });
+
+        self.autosave = vec![changed, left_insert];
+    }
+
+    /// Key typed into the command palette, running the command picked
+    fn palette_key(&mut self, key: KeyEvent) {
+        let palette = match &mut self.palette {
+            Some(palette) => palette,
+            None => return,
+        };
+
+        match palette.handle(key) {
+            Picked::Picking | Picked::Delete(_) => (),
+            Picked::Cancel => self.palette = None,
+            Picked::Select(index) => {
+                self.palette = None;
+                let name = self.commands.iter().nth(index).map(|c| c.name.clone());
+
+                if let Some(name) = name {
+                    self.command(&name, None, "");
                 }
             }
         }
+    }
+
+    fn picker_key(&mut self, key: KeyEvent) {
+        let picker = match &mut self.picker {
+            Some(picker) => picker,
+            None => return,
+        };
+
+        match picker.handle(key) {
+            Picked::Picking => (),
+            Picked::Cancel => self.picker = None,
+            Picked::Select(id) => {
+                self.picker = None;
+                self.show_buffer(id);
+            }
+            Picked::Delete(id) => {
+                let next = self.modes.buff.cycle(id, 1);
+                if let Err(e) = self.delete_buffer(id, false) {
+                    self.message = Some(Message::Error(e));
+                }
+                self.buffer_picker(Some(next));
+            }
+        }
+    }
+
+    /// show the matches of the search pattern in every window
+    fn highlight_search(&mut self) {
+        let pattern = self.modes.editor.highlight();
+        let same =
+            |window: &Window| window.highlight().map(Regex::as_str) == pattern.map(Regex::as_str);
+
+        if self.ui.windows_mut().all(|window| same(window)) {
+            return;
+        }
+
+        for window in self.ui.windows_mut() {
+            window.set_highlight(pattern.cloned());
+        }
+        self.redraw_windows();
+    }
 
-        let ui = self.modes.ui.for_tui();
+    /// Lay the screen out again for a new terminal size and repaint it
+    pub fn resize(&mut self, cols: u16, rows: u16) -> RendResult {
+        let (rows, cols) = (rows.into(), cols.into());
 
-        // self.renderer.draw(ui)?;
-        // self.renderer.cursor(&self.modes.ui.cursor)?;
-        // self.renderer.render()?;
+        self.ui.resize(rows, cols);
+        self.renderer.resize(rows, cols)?;
+        self.events.emit(WindowResized { rows, cols });
+        self.notify();
+        self.redraw_windows();
 
         Ok(())
     }
-}
 
-impl std::fmt::Debug for App {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("App")
-            .field("renderer", &self.renderer)
-            .field("user_machine", &self.user_machine.state())
-            .finish()
+    /// Run a line entered in command mode, see [`ex`]. Errors are shown in
+    /// place of the modeline until the next key
+    pub fn execute(&mut self, command: &str) {
+        info!("running command {:?}", command);
+
+        if let Err(e) = self.run_ex(command) {
+            error!("{}", e);
+            self.message = Some(Message::Error(e));
+        }
     }
-}
 
-pub mod buffer {
-    use log::info;
-    use ropey::{Rope, RopeSlice};
+    fn run_ex(&mut self, line: &str) -> Result<(), String> {
+        let (command, bang, args) = match ex::parse(line)? {
+            Parsed::Empty => return Ok(()),
+            Parsed::Goto(address) => {
+                let window = self.ui.get_active_window();
+                self.modes.buff.move_cursor(window, |b| {
+                    let row = address.row(b.get_cursor().row, b.last_line());
+                    b.set_cursor_char(b.text().line_to_char(row) + b.first_non_blank(row));
+                });
+                return Ok(());
+            }
+            Parsed::Command {
+                range: Some(range),
+                command: Ex::Substitute,
+                args,
+                ..
+            } => return self.substitute(range, &args),
+            Parsed::Command { range: Some(_), .. } => return Err("No range allowed".to_string()),
+            Parsed::Named { name, args } => return self.run_command(&name, None, &args),
+            Parsed::Command {
+                command,
+                bang,
+                args,
+                ..
+            } => (command, bang, args),
+        };
 
-    use crate::modes::Cursor;
+        let file = match args.is_empty() {
+            true => None,
+            false => Some(args.as_str()),
+        };
 
-    pub struct Lines<'a> {
-        pub lines: RopeSlice<'a>,
-        pub empty: usize,
+        match command {
+            Ex::Write => self.write(file)?,
+            Ex::Quit => self.quit(bang)?,
+            Ex::QuitAll => self.quit_all(bang)?,
+            Ex::WriteQuit => {
+                self.write(file)?;
+                self.quit(bang)?;
+            }
+            Ex::WriteAll => self.write_all()?,
+            Ex::WriteQuitAll => {
+                self.write_all()?;
+                self.quit_all(bang)?;
+            }
+            Ex::Edit => match file {
+                Some(path) => self.edit(path)?,
+                None => return Err("No file name".to_string()),
+            },
+            Ex::New => {
+                let id = self.add_buffer(Buffer::default());
+                self.show_buffer(id);
+            }
+            Ex::Set => self.set_option(&args)?,
+            Ex::Buffer if args.is_empty() => (),
+            Ex::Buffer => {
+                let id = self.find_buffer(&args)?;
+                self.show_buffer(id);
+            }
+            Ex::Buffers => self.buffer_picker(None),
+            Ex::BufferDelete => {
+                let id = match args.is_empty() {
+                    true => self.modes.buff.active(),
+                    false => self.find_buffer(&args)?,
+                };
+                self.delete_buffer(id, bang)?;
+            }
+            Ex::BufferNext | Ex::BufferPrev => {
+                let count = args.parse::<isize>().unwrap_or(1);
+                let count = match command {
+                    Ex::BufferNext => count,
+                    _ => -count,
+                };
+                let buff = &self.modes.buff;
+                self.show_buffer(buff.cycle(buff.active(), count));
+            }
+            Ex::Split | Ex::VSplit => {
+                let direction = match command {
+                    Ex::Split => Direction::Horizontal,
+                    _ => Direction::Vertical,
+                };
+                self.window_command(|ui| ui.split(direction));
+
+                if let Some(path) = file {
+                    self.edit(path)?;
+                }
+            }
+            Ex::Close => self.window_command(|ui| {
+                ui.close();
+            }),
+            Ex::Only => self.window_command(UI::only),
+            Ex::Wincmd => self.wincmd(&args)?,
+            Ex::Earlier | Ex::Later => self.undo_command(command == Ex::Earlier, &args)?,
+            Ex::Substitute => {
+                let range = LineRange {
+                    start: Address::Current,
+                    end: Address::Current,
+                };
+                self.substitute(range, &args)?;
+            }
+            Ex::NoHighlight => self.modes.editor.search().clear_highlight(),
+            Ex::Colorscheme => self.colorscheme(&args)?,
+            Ex::Source => self.source(&args)?,
+            Ex::Map(modes) => self.map(modes, &args)?,
+            Ex::Unmap(modes) => self.unmap(modes, &args)?,
+        }
+
+        Ok(())
     }
 
-    #[derive(Default, Debug)]
-    pub struct Buffer {
-        text: Rope,
+    /// `:s` over `range`, one undo step for all the replacements
+    fn substitute(&mut self, range: LineRange, args: &str) -> Result<(), String> {
+        let buffer = self.modes.buff.get_active_buffer();
+        let (row, last) = (buffer.get_cursor().row, buffer.last_line());
+        let (first, last) = (range.start.row(row, last), range.end.row(row, last));
 
-        /// structure tree-sitter document, will do later
-        doc: Option<i32>,
+        if first > last {
+            return Err("Backwards range given".to_string());
+        }
 
-        /// reference to window ID if buffer is visible
-        window: Option<usize>,
+        let search = self.modes.editor.search();
+        let mut substitute = Substitute::parse(args, search.pattern(), first, last)?;
+        search.set_pattern(substitute.regex().clone());
 
-        cursor: Cursor,
+        self.modes.buff.get_active_buffer().commit();
+
+        match substitute.confirm() {
+            true => {
+                self.substitute = Some(substitute);
+                self.confirm_next();
+                Ok(())
+            }
+            false => {
+                substitute.run(self.modes.buff.get_active_buffer());
+                self.finish_substitute(substitute)
+            }
+        }
     }
 
-    impl Buffer {
-        pub fn new(text: Rope, doc: Option<i32>, window: Option<usize>) -> Self {
-            Self {
-                text,
-                doc,
-                window,
-                cursor: Default::default(),
+    /// Key answering whether to replace the match under the cursor: `y` to
+    /// replace it, `n` to skip it, `a` to replace it and the rest, `l` to
+    /// replace it and stop, `q` or escape to stop
+    fn confirm_key(&mut self, KeyEvent { code, .. }: KeyEvent) {
+        let mut substitute = match self.substitute.take() {
+            Some(substitute) => substitute,
+            None => return,
+        };
+
+        let buffer = self.modes.buff.get_active_buffer();
+        let found = match substitute.next_match(buffer) {
+            Some(found) => found,
+            None => return self.end_confirm(substitute),
+        };
+
+        match code {
+            KeyCode::Char('y') => substitute.replace(buffer, found),
+            KeyCode::Char('n') => substitute.skip(buffer, found),
+            KeyCode::Char('a') => {
+                substitute.replace(buffer, found);
+                substitute.run(buffer);
+                return self.end_confirm(substitute);
+            }
+            KeyCode::Char('l') => {
+                substitute.replace(buffer, found);
+                return self.end_confirm(substitute);
             }
+            KeyCode::Char('q') | KeyCode::Esc => return self.end_confirm(substitute),
+            _ => (),
         }
 
-        pub fn build() -> BufferBuilder {
-            BufferBuilder::default()
+        self.substitute = Some(substitute);
+        self.confirm_next();
+    }
+
+    /// move the cursor to the next match and ask about it, finishing when
+    /// there are none left
+    fn confirm_next(&mut self) {
+        let mut substitute = match self.substitute.take() {
+            Some(substitute) => substitute,
+            None => return,
+        };
+
+        let window = self.ui.get_active_window();
+        let found = substitute.next_match(self.modes.buff.get_active_buffer());
+
+        match found {
+            Some(found) => {
+                self.modes
+                    .buff
+                    .edit(window, |b| b.set_cursor_char(found.start));
+                self.message = Some(Message::Info("replace with this? (y/n/a/q/l)".to_string()));
+                self.substitute = Some(substitute);
+            }
+            None => self.end_confirm(substitute),
         }
+    }
 
-        pub fn open_in_window(&mut self, win_id: usize) {
-            // TODO: event to tell old window of change?
-            self.window = Some(win_id);
+    fn end_confirm(&mut self, substitute: Substitute) {
+        if let Err(e) = self.finish_substitute(substitute) {
+            self.message = Some(Message::Error(e));
         }
+    }
+
+    /// close the undo step of a substitution and put the cursor on the
+    /// last line changed
+    fn finish_substitute(&mut self, substitute: Substitute) -> Result<(), String> {
+        let window = self.ui.get_active_window();
 
-        pub fn remove_from_window(&mut self) {
-            // TODO: event to tell old window of change?
-            self.window = None;
+        self.modes.buff.move_cursor(window, |b| {
+            if let Some(row) = substitute.last_replaced() {
+                b.set_cursor_char(b.text().line_to_char(row) + b.first_non_blank(row));
+            }
+            b.clamp_cursor();
+        });
+
+        let summary = substitute.summary()?;
+        self.message = Some(Message::Info(summary));
+        Ok(())
+    }
+
+    /// Switch to the theme called `name`, or show the name of the current
+    /// theme without one
+    fn colorscheme(&mut self, name: &str) -> Result<(), String> {
+        if name.is_empty() {
+            self.message = Some(Message::Info(self.ui.theme().name().to_string()));
+            return Ok(());
         }
 
-        pub fn get_cursor(&self) -> &Cursor {
-            &self.cursor
+        let theme = Theme::load(name, self.ui.color_support())?;
+        self.ui.set_theme(theme);
+        self.redraw_windows();
+
+        Ok(())
+    }
+
+    /// Open `path` in the active window, reading it from disk in the
+    /// background unless a buffer already has it open
+    fn edit(&mut self, path: &str) -> Result<(), String> {
+        if let Some(id) = self.open_buffer(path) {
+            self.show_buffer(id);
+            return Ok(());
         }
 
-        pub fn get_lines_range(&self, start: usize, end: usize) -> Lines {
-            info!("getting lines start {} end {}", start, end);
-            let size_request = end - start;
-            let empty = size_request.saturating_sub(self.text.len_lines());
+        let path = path.to_string();
+        self.spawn(
+            move || Buffer::from_file(&path).map_err(|e| format!("Could not open {}: {}", path, e)),
+            |app, buffer| match buffer {
+                Ok(buffer) => {
+                    // opened again while it was being read
+                    let id = match buffer.path().and_then(|path| app.open_buffer(path)) {
+                        Some(id) => id,
+                        None => app.add_buffer(buffer),
+                    };
+                    app.show_buffer(id);
+                }
+                Err(e) => app.message = Some(Message::Error(e)),
+            },
+        );
 
-            let start_post = self.text.line_to_char(start);
+        Ok(())
+    }
+
+    /// id of the buffer with `path` open
+    fn open_buffer(&self, path: impl AsRef<Path>) -> Option<usize> {
+        let path = Some(path.as_ref());
+        self.modes
+            .buff
+            .iter()
+            .find(|(_, b)| b.path() == path)
+            .map(|(id, _)| id)
+    }
+
+    /// add a buffer, giving its id
+    fn add_buffer(&mut self, buffer: Buffer) -> usize {
+        if buffer.mixed_line_endings() {
+            let ending = buffer.line_ending().name();
+            self.message = Some(Message::Info(format!(
+                "\"{}\" has mixed line endings, written as {}",
+                buffer.name(),
+                ending
+            )));
+        }
 
-            info!("empty {}", empty);
-            let end_pos = if empty == 0 {
-                Some(self.text.line_to_char(end + 1))
-            } else {
-                None
+        let id = self.modes.buff.add(buffer);
+        self.events.emit(BufferOpened { buffer: id });
+        id
+    }
+
+    /// Buffer with the id `arg`, or the only one with `arg` in its name
+    fn find_buffer(&self, arg: &str) -> Result<usize, String> {
+        let buff = &self.modes.buff;
+
+        if let Ok(id) = arg.parse() {
+            return match buff.get(id) {
+                Some(_) => Ok(id),
+                None => Err(format!("Buffer {} does not exist", id)),
             };
+        }
+
+        let mut matches = buff.iter().filter(|(_, b)| b.name().contains(arg));
+        match (matches.next(), matches.next()) {
+            (Some((id, _)), None) => Ok(id),
+            (Some(_), Some(_)) => Err(format!("More than one match for {}", arg)),
+            (None, _) => Err(format!("No matching buffer for {}", arg)),
+        }
+    }
+
+    /// Close buffer `id`, refusing while it has unsaved changes unless
+    /// `force`. Windows showing it move on to the next buffer, or to a new
+    /// empty one when it was the last
+    fn delete_buffer(&mut self, id: usize, force: bool) -> Result<(), String> {
+        let buff = &mut self.modes.buff;
+        let buffer = buff
+            .get(id)
+            .ok_or_else(|| format!("Buffer {} does not exist", id))?;
+
+        if buffer.is_modified() && !force {
+            return Err(format!(
+                "No write since last change for buffer {} (add ! to override)",
+                id
+            ));
+        }
+
+        let windows = buffer.windows().to_vec();
+        let mut next = buff.cycle(id, 1);
+        buff.remove(id);
+
+        if next == id {
+            next = self.add_buffer(Buffer::default());
+        }
+
+        if let Some(buffer) = self.modes.buff.get(next) {
+            for (index, window) in self.ui.windows_mut().enumerate() {
+                if windows.contains(&index) {
+                    window.set_buffer(next, buffer);
+                }
+            }
+        }
+
+        self.window_command(|_| ());
+        Ok(())
+    }
+
+    /// List the buffers to pick one to show, starting on `selected` or the
+    /// active buffer
+    fn buffer_picker(&mut self, selected: Option<usize>) {
+        let buff = &self.modes.buff;
+        let active = buff.active();
 
-            let slice = match end_pos {
-                Some(end_pos) => self.text.slice(start_post..end_pos),
-                None => self.text.slice(start_post..),
+        let items = buff
+            .iter()
+            .map(|(id, buffer)| {
+                let label = format!(
+                    "{:>3} {}{} {} {}",
+                    id,
+                    if id == active { '%' } else { ' ' },
+                    if buffer.windows().is_empty() {
+                        'h'
+                    } else {
+                        'a'
+                    },
+                    if buffer.is_modified() { '+' } else { ' ' },
+                    buffer.name()
+                );
+                (id, label)
+            })
+            .collect();
+
+        let selected = selected.unwrap_or(active);
+        self.picker = Some(Picker::new("Buffers".to_string(), items, selected));
+    }
+
+    /// show buffer `id` in the active window and make it the active buffer
+    fn show_buffer(&mut self, id: usize) {
+        self.buffer_options();
+
+        if let Some(buffer) = self.modes.buff.get(id) {
+            self.ui.get_active_window().set_buffer(id, buffer);
+            self.modes.buff.set_active(id);
+            self.redraw_windows();
+        }
+    }
+
+    /// `earlier`/`later` take a number of steps, one by default, or a time
+    /// such as `10s`, `5m` or `1h`
+    fn undo_command(&mut self, earlier: bool, step: &str) -> Result<(), String> {
+        let step = match step.trim() {
+            "" => "1",
+            step => step,
+        };
+        let units = [("s", 1), ("m", 60), ("h", 60 * 60)];
+        let time = units
+            .into_iter()
+            .find_map(|(suffix, unit)| Some((step.strip_suffix(suffix)?, unit)));
+        let invalid = || format!("Invalid undo step: {}", step);
+
+        let step = match time {
+            Some((n, unit)) => {
+                let secs = n.parse::<u64>().ok().and_then(|n| n.checked_mul(unit));
+                UndoStep::Time(Duration::from_secs(secs.ok_or_else(invalid)?))
+            }
+            None => UndoStep::Steps(step.parse().map_err(|_| invalid())?),
+        };
+
+        let window = self.ui.get_active_window();
+        self.modes.buff.edit(window, |b| {
+            match earlier {
+                true => b.earlier(step),
+                false => b.later(step),
+            }
+            b.clamp_cursor();
+        });
+
+        Ok(())
+    }
+
+    /// `set name=value`, `set name` or `set noname`, for the active window
+    /// or, with buffer options, the active buffer
+    fn set_option(&mut self, option: &str) -> Result<(), String> {
+        match Setting::parse(option)? {
+            Setting::TimeoutLen(ms) => self.keymaps.set_timeout(Duration::from_millis(ms as u64)),
+            Setting::AutoSave(on) => self.set_autosave(on),
+            setting if setting.is_buffer_local() => {
+                self.modes.buff.get_active_buffer().set_local(setting);
+                self.buffer_options();
+            }
+            setting => {
+                let window = self.ui.get_active_window();
+                let mut options = window.options();
+                options.set(&setting);
+                window.set_options(&options);
+            }
+        }
+
+        let window = self.ui.get_active_window();
+        self.modes.buff.edit(window, |_| ());
+        Ok(())
+    }
+
+    /// Take on settings read from the config file, in place of those of
+    /// the last one. Where it could not be read the settings in use are
+    /// kept and the error is shown
+    pub fn configure(&mut self, config: Result<Config, String>) {
+        let config = match config {
+            Ok(config) => config,
+            Err(e) => {
+                error!("{}", e);
+                self.message = Some(Message::Error(e));
+                return;
+            }
+        };
+
+        // keys the last config bound go back to their defaults
+        for (mode, lhs, _) in &self.config.keys {
+            self.keymaps.restore(*mode, lhs);
+        }
+        self.config = config;
+
+        log::set_max_level(self.config.log.level);
+
+        match Theme::load(&self.config.theme, self.ui.color_support()) {
+            Ok(theme) => self.ui.set_theme(theme),
+            Err(e) => self.message = Some(Message::Error(e)),
+        }
+
+        for window in self.ui.windows_mut() {
+            window.set_options(&self.config.window);
+        }
+
+        let timeout = Duration::from_millis(self.config.timeoutlen as u64);
+        self.keymaps.set_timeout(timeout);
+        self.set_autosave(self.config.autosave);
+
+        for (mode, lhs, rhs) in &self.config.keys {
+            if let Err(e) = self.keymaps.map(*mode, lhs, rhs) {
+                self.message = Some(Message::Error(e));
+            }
+        }
+
+        self.buffer_options();
+        self.redraw_windows();
+    }
+
+    /// `:map {lhs} {rhs}` binds keys to others in each of `modes`, `:map
+    /// {lhs}` shows what they are bound to
+    fn map(&mut self, modes: &[MapMode], args: &str) -> Result<(), String> {
+        let (lhs, rhs) = match args.split_once(char::is_whitespace) {
+            Some((lhs, rhs)) => (lhs, rhs.trim_start()),
+            None => (args, ""),
+        };
+
+        if lhs.is_empty() {
+            return Err("Argument required".to_string());
+        }
+
+        if rhs.is_empty() {
+            let action = modes
+                .iter()
+                .find_map(|mode| self.keymaps.binding(*mode, lhs))
+                .ok_or_else(|| "No mapping found".to_string())?;
+            let rhs = match action {
+                Action::Command { name, arg } if arg.is_empty() => format!("<Cmd>{}<CR>", name),
+                Action::Command { name, arg } => format!("<Cmd>{} {}<CR>", name, arg),
+                Action::Keys(keys) => keymap::keys_name(keys),
             };
+            let lhs = keymap::keys_name(&keymap::parse_keys(lhs));
+
+            self.message = Some(Message::Info(format!("{} {}", lhs, rhs)));
+            return Ok(());
+        }
+
+        for mode in modes {
+            self.keymaps.map(*mode, lhs, rhs)?;
+        }
+
+        Ok(())
+    }
+
+    /// `:unmap {lhs}` removes the binding of keys from each of `modes`
+    fn unmap(&mut self, modes: &[MapMode], lhs: &str) -> Result<(), String> {
+        if lhs.is_empty() {
+            return Err("Argument required".to_string());
+        }
+
+        let mut removed = false;
+        for mode in modes {
+            removed |= self.keymaps.unmap(*mode, lhs);
+        }
+
+        match removed {
+            true => Ok(()),
+            false => Err("No such mapping".to_string()),
+        }
+    }
 
-            Lines {
-                lines: slice,
-                empty,
+    /// the open picker, or the keys that can follow those typed
+    fn popup(&self) -> Option<Popup> {
+        match self.picker.as_ref().or(self.palette.as_ref()) {
+            Some(picker) => {
+                let title = match picker.query() {
+                    Some(query) => format!("{}: {}", picker.title(), query),
+                    None => picker.title().to_string(),
+                };
+                let labels = picker.labels().map(String::from).collect();
+                Some(Popup::new(title, labels, Some(picker.selected())))
             }
+            None => self.which_key(),
         }
     }
 
-    #[derive(Default, Debug)]
-    pub struct BufferBuilder {
-        text: Option<Rope>,
-        doc: Option<i32>,
-        window: Option<usize>,
+    /// Keys that can follow those typed so far, while they are the start
+    /// of a longer binding. Not shown when typing text, where a binding
+    /// such as `jk` would have the list flash up at every `j`
+    fn which_key(&self) -> Option<Popup> {
+        let pending = self.keymaps.pending();
+        let mode = MapMode::from_state(self.modes.editor.state());
+
+        if pending.is_empty() || matches!(mode, MapMode::Insert | MapMode::Command) {
+            return None;
+        }
+
+        let next = self.keymaps.continuations(mode);
+        let widest = next.iter().map(|(key, _)| key.len()).max()?;
+        let lines = next
+            .into_iter()
+            .map(|(key, desc)| format!("{:widest$} {}", key, desc, widest = widest))
+            .collect();
+
+        Some(Popup::new(keymap::keys_name(pending), lines, None))
+    }
+
+    /// bring the options of every buffer in line with the config, its
+    /// filetype and what was set for it alone
+    fn buffer_options(&mut self) {
+        let ids: Vec<usize> = self.modes.buff.iter().map(|(id, _)| id).collect();
+
+        for id in ids {
+            if let Some(buffer) = self.modes.buff.get_mut(id) {
+                let options = self
+                    .config
+                    .buffer_options(buffer.filetype(), buffer.local_settings());
+                buffer.set_options(options);
+            }
+        }
     }
 
-    impl BufferBuilder {
-        /// panics if values not met
-        pub fn create(self) -> Buffer {
-            match self {
-                BufferBuilder {
-                    text: Some(text),
-                    doc,
-                    window,
-                } => Buffer::new(text, doc, window),
-                _ => panic!("Buffer constraints not met"),
+    /// `:source` reads the config file again, or the file given
+    fn source(&mut self, path: &str) -> Result<(), String> {
+        let config = match path {
+            "" => Config::load(),
+            path => Config::read(Path::new(path))
+                .map_err(|e| format!("Can't open file {}: {}", path, e))?,
+        };
+
+        self.configure(config);
+        Ok(())
+    }
+
+    /// close the active window, quitting once it is the last
+    fn quit(&mut self, force: bool) -> Result<(), String> {
+        let mut closed = false;
+        self.window_command(|ui| closed = ui.close());
+
+        match closed {
+            true => Ok(()),
+            false => self.quit_all(force),
+        }
+    }
+
+    /// Quit the editor, refusing while a buffer has unsaved changes unless
+    /// `force`
+    pub fn quit_all(&mut self, force: bool) -> Result<(), String> {
+        let modified = self.modes.buff.iter().find(|(_, b)| b.is_modified());
+
+        match (modified, force) {
+            (Some((id, buffer)), false) => Err(format!(
+                "No write since last change for buffer {} ({}) (add ! to override)",
+                id,
+                buffer.name()
+            )),
+            _ => {
+                self.quit = true;
+                Ok(())
             }
         }
+    }
+
+    /// `ctrl-w` commands, a key and an optional count, see [`WINCMD`]
+    fn wincmd(&mut self, arg: &str) -> Result<(), String> {
+        let mut args = arg.split_whitespace();
+        let key = args.next().and_then(|key| key.chars().next());
+        let count = args.next().and_then(|n| n.parse().ok());
 
-        pub fn with_text(mut self, text: String) -> Self {
-            self.text = Some(Rope::from(text));
-            self
+        match WINCMD.iter().find(|(k, _)| Some(*k) == key) {
+            Some((_, name)) => self.run_command(name, count, ""),
+            None => Err(format!("Unknown window command: {}", arg.trim())),
         }
+    }
 
-        pub fn with_window(mut self, win: usize) -> Self {
-            self.window = Some(win);
-            self
+    /// Run the command called `name`, see [`commands`]
+    pub fn run_command(
+        &mut self,
+        name: &str,
+        count: Option<usize>,
+        arg: &str,
+    ) -> Result<(), String> {
+        info!("running {} {:?} {:?}", name, count, arg);
+
+        let run = self
+            .commands
+            .get(name)
+            .ok_or_else(|| format!("Not an editor command: {}", name))?;
+
+        run(self, Args { count, arg })
+    }
+
+    /// run a command, showing its error in place of the modeline
+    fn command(&mut self, name: &str, count: Option<usize>, arg: &str) {
+        if let Err(e) = self.run_command(name, count, arg) {
+            error!("{}", e);
+            self.message = Some(Message::Error(e));
         }
     }
+
+    /// Change the windows, then redraw them and make the buffer of the
+    /// active one the active buffer
+    fn window_command(&mut self, cb: impl FnOnce(&mut UI)) {
+        cb(&mut self.ui);
+
+        let active = self.ui.get_active_window().buffer();
+        self.modes.buff.set_active(active);
+        self.redraw_windows();
+    }
+
+    /// Redraw every window, as other windows may show the buffer just
+    /// edited, and link the buffers to the windows showing them
+    fn redraw_windows(&mut self) {
+        self.redraw = true;
+
+        let buff = &mut self.modes.buff;
+        let mut shown = Vec::new();
+
+        for window in self.ui.windows_mut() {
+            let id = window.buffer();
+            shown.push(id);
+
+            if let Some(buffer) = buff.get(id) {
+                window.set_buffer(id, buffer);
+            }
+        }
+
+        buff.link_windows(&shown);
+    }
+
+    pub fn should_quit(&self) -> bool {
+        self.quit
+    }
+
+    /// draw the ui and place the cursor in the active window
+    pub fn render(&mut self) -> RendResult {
+        let popup = self.popup();
+
+        let Modes {
+            editor,
+            modeline,
+            ui,
+            ..
+        } = &mut self.modes;
+
+        let cursor = match (editor.prompt(), &self.message) {
+            (Some((prompt, line)), _) => Cursor {
+                row: self.ui.modeline_row(),
+                col: self
+                    .ui
+                    .set_command_line(prompt, line.text(), line.cursor_width()),
+            },
+            (_, Some(message)) => {
+                match message {
+                    Message::Info(text) => self.ui.set_message(text, false),
+                    Message::Error(text) => self.ui.set_message(text, true),
+                }
+                self.ui.cursor()
+            }
+            _ => {
+                self.ui
+                    .set_modeline(modeline.status(), &self.config.modeline);
+                self.ui.cursor()
+            }
+        };
+        self.ui.set_popup(popup);
+
+        let cursor = self.ui.popup_cursor().unwrap_or(cursor);
+        ui.update_cursor(|_| cursor);
+
+        self.renderer.render(&mut self.ui)?;
+        self.renderer.cursor(&self.modes.ui.cursor)?;
+
+        Ok(())
+    }
+
+    /// write every modified buffer to its own path
+    fn write_all(&mut self) -> Result<(), String> {
+        let modified: Vec<usize> = self
+            .modes
+            .buff
+            .iter()
+            .filter(|(_, b)| b.is_modified())
+            .map(|(id, _)| id)
+            .collect();
+
+        for id in modified {
+            if let Some(buffer) = self.modes.buff.get_mut(id) {
+                buffer
+                    .write()
+                    .map_err(|e| format!("Could not write buffer {}: {}", id, e))?;
+
+                if let Some(path) = buffer.path() {
+                    let path = path.to_path_buf();
+                    self.events.emit(BufferSaved { buffer: id, path });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// write the active buffer to `path`, or to its own path
+    fn write(&mut self, path: Option<&str>) -> Result<(), String> {
+        let buffer = self.modes.buff.get_active_buffer();
+
+        let written = match path {
+            Some(path) => buffer.write_to(path),
+            None => buffer.write(),
+        };
+        let written = written.map_err(|e| format!("Could not write buffer: {}", e))?;
+
+        let path = buffer.path().map(Path::to_path_buf).unwrap_or_default();
+        let name = path.display().to_string();
+        self.message = Some(Message::Info(format!("\"{}\" {}B written", name, written)));
+
+        let id = self.modes.buff.active();
+        self.events.emit(BufferSaved { buffer: id, path });
+
+        Ok(())
+    }
+}
+
+/// write buffer `id` when it has unsaved changes and a file to go to
+fn autosave(ctx: &mut events::Ctx, id: usize) {
+    let buffer = match ctx.modes.buff.get_mut(id) {
+        Some(buffer) if buffer.is_modified() => buffer,
+        _ => return,
+    };
+    let path = match buffer.path() {
+        Some(path) => path.to_path_buf(),
+        None => return,
+    };
+
+    match buffer.write() {
+        Ok(_) => ctx.emit(BufferSaved { buffer: id, path }),
+        Err(e) => error!("could not autosave buffer {}: {}", id, e),
+    }
+}
+
+impl<B: Backend> std::fmt::Debug for App<B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("App")
+            .field("renderer", &self.renderer)
+            .field("mode", &self.modes.editor.state())
+            .finish()
+    }
 }
+
+pub mod buffer;
+pub mod commands;
+pub mod events;
+pub mod ex;
+mod substitute;
+pub mod tasks;
+
+#[cfg(test)]
+mod tests;
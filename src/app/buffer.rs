@@ -0,0 +1,917 @@
+use log::info;
+use ropey::{Rope, RopeSlice};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::config::{BufferOptions, Setting};
+use crate::modes::Cursor;
+use crate::text::{self, Wrap};
+
+mod history;
+mod syntax;
+
+use history::History;
+pub use history::{Change, UndoStep};
+pub use syntax::Syntax;
+
+pub struct Lines<'a> {
+    pub lines: RopeSlice<'a>,
+    /// number of buffer lines in `lines`
+    pub len: usize,
+}
+
+/// Byte order mark written at the start of some utf-8 files
+const BOM: &str = "\u{feff}";
+
+/// Text encoding a buffer was read with, and will be written back as
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    Utf8Bom,
+}
+
+impl Encoding {
+    /// name shown in the modeline
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf8Bom => "utf-8-bom",
+        }
+    }
+}
+
+/// Line ending style of a file on disk. The rope always holds `\n`, the
+/// original style is restored when the buffer is written
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    /// name shown in the modeline
+    pub fn name(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "unix",
+            LineEnding::CrLf => "dos",
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+
+    /// use the first line ending found in the text, defaulting to `\n`
+    fn detect(text: &str) -> Self {
+        match text.find('\n') {
+            Some(i) if text[..i].ends_with('\r') => LineEnding::CrLf,
+            _ => LineEnding::Lf,
+        }
+    }
+
+    /// the text has lines ending in both `\n` and `\r\n`
+    fn mixed(text: &str) -> bool {
+        let crlf = text.matches("\r\n").count();
+        crlf > 0 && crlf < text.matches('\n').count()
+    }
+}
+
+/// Shape of a visual selection
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SelectionKind {
+    Char,
+    Line,
+    Block,
+}
+
+/// Visual selection running from `anchor` to the cursor
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Selection {
+    /// char index the selection started at
+    pub anchor: usize,
+    pub kind: SelectionKind,
+}
+
+#[derive(Default, Debug)]
+pub struct Buffer {
+    text: Rope,
+
+    /// syntax tree of the text, for languages with a bundled grammar
+    syntax: Option<Syntax>,
+
+    /// windows showing this buffer, by their index in the UI
+    windows: Vec<usize>,
+
+    /// revision of the history last read from or written to disk
+    saved: usize,
+
+    cursor: Cursor,
+
+    /// display column vertical moves aim for, kept while passing over lines
+    /// too short to reach it
+    desired_col: Option<usize>,
+
+    selection: Option<Selection>,
+
+    history: History,
+
+    /// changes made since they were last taken, to be sent out as events
+    changes: Vec<Change>,
+
+    /// file on disk backing this buffer, scratch buffers have none
+    path: Option<PathBuf>,
+
+    /// options set with `:set` for this buffer alone
+    local: Vec<Setting>,
+
+    /// options from the config and `local` together
+    options: BufferOptions,
+
+    encoding: Encoding,
+
+    line_ending: LineEnding,
+
+    /// the file had both line endings, all written as `line_ending`
+    mixed_line_endings: bool,
+
+    /// the last line of the file ended in a line break, which is not shown as
+    /// an empty line after it but is written back
+    final_newline: bool,
+}
+
+impl Buffer {
+    pub fn new(text: Rope, mut syntax: Option<Syntax>, window: Option<usize>) -> Self {
+        if let Some(syntax) = &mut syntax {
+            syntax.parse(&text);
+        }
+
+        Self {
+            text,
+            syntax,
+            windows: window.into_iter().collect(),
+            saved: 0,
+            cursor: Default::default(),
+            desired_col: None,
+            selection: None,
+            history: Default::default(),
+            changes: Default::default(),
+            path: None,
+            local: Default::default(),
+            options: Default::default(),
+            encoding: Default::default(),
+            line_ending: Default::default(),
+            mixed_line_endings: false,
+            final_newline: false,
+        }
+    }
+
+    pub fn build() -> BufferBuilder {
+        BufferBuilder::default()
+    }
+
+    /// Read a file from disk into a new buffer. A missing file gives an empty
+    /// buffer which will create the file when first written
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+
+        let raw = match fs::read_to_string(path) {
+            Ok(raw) => raw,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                info!("new file {}", path.display());
+                String::new()
+            }
+            Err(e) => return Err(e),
+        };
+
+        let (encoding, raw) = match raw.strip_prefix(BOM) {
+            Some(rest) => (Encoding::Utf8Bom, rest),
+            None => (Encoding::Utf8, raw.as_str()),
+        };
+
+        let line_ending = LineEnding::detect(raw);
+        let mixed_line_endings = LineEnding::mixed(raw);
+
+        let raw = match raw.contains("\r\n") {
+            true => raw.replace("\r\n", "\n"),
+            false => raw.to_string(),
+        };
+        let final_newline = raw.ends_with('\n');
+        let text = Rope::from_str(raw.strip_suffix('\n').unwrap_or(&raw));
+
+        info!(
+            "opened {} ({:?}, {:?}, {} lines)",
+            path.display(),
+            encoding,
+            line_ending,
+            text.len_lines()
+        );
+
+        Ok(Self {
+            path: Some(path.to_path_buf()),
+            encoding,
+            line_ending,
+            mixed_line_endings,
+            final_newline,
+            ..Self::new(text, Syntax::for_path(path), None)
+        })
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    pub fn line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+
+    /// the file was read with lines ending both ways, see [`LineEnding`]
+    pub fn mixed_line_endings(&self) -> bool {
+        self.mixed_line_endings
+    }
+
+    /// Save the buffer to its own path
+    pub fn write(&mut self) -> io::Result<usize> {
+        let path = self
+            .path
+            .clone()
+            .ok_or_else(|| io::Error::other("no file name"))?;
+
+        self.write_to(path)
+    }
+
+    /// Save the buffer to `path`, which becomes the buffer's path.
+    ///
+    /// Text is written to a temporary file next to the target which is then
+    /// renamed over it, so a failed write never leaves a half written file.
+    /// Permissions of an existing file are carried over, and a symlink is
+    /// followed to write the file it points at
+    pub fn write_to(&mut self, path: impl AsRef<Path>) -> io::Result<usize> {
+        let path = path.as_ref();
+        let target = link_target(path);
+        let tmp = tmp_path(&target);
+
+        let written = self.write_tmp(&tmp).and_then(|written| {
+            if let Ok(meta) = fs::metadata(&target) {
+                fs::set_permissions(&tmp, meta.permissions())?;
+            }
+            fs::rename(&tmp, &target)?;
+            Ok(written)
+        });
+
+        if written.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+
+        let written = written?;
+        info!("wrote {} bytes to {}", written, path.display());
+
+        self.path = Some(path.to_path_buf());
+        if self.syntax.is_none() {
+            self.syntax = Syntax::for_path(path);
+            self.parse_syntax();
+        }
+        self.commit();
+        self.saved = self.history.current();
+
+        Ok(written)
+    }
+
+    fn write_tmp(&self, tmp: &Path) -> io::Result<usize> {
+        let file = File::create(tmp)?;
+        let mut out = BufWriter::new(file);
+        let mut written = 0;
+
+        if self.encoding == Encoding::Utf8Bom {
+            out.write_all(BOM.as_bytes())?;
+            written += BOM.len();
+        }
+
+        let eol = self.line_ending.as_str();
+
+        for chunk in self.text.chunks() {
+            let mut lines = chunk.split('\n');
+
+            if let Some(first) = lines.next() {
+                out.write_all(first.as_bytes())?;
+                written += first.len();
+            }
+
+            for line in lines {
+                out.write_all(eol.as_bytes())?;
+                out.write_all(line.as_bytes())?;
+                written += eol.len() + line.len();
+            }
+        }
+
+        if self.final_newline {
+            out.write_all(eol.as_bytes())?;
+            written += eol.len();
+        }
+
+        out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+
+        Ok(written)
+    }
+
+    pub fn open_in_window(&mut self, win_id: usize) {
+        if !self.windows.contains(&win_id) {
+            self.windows.push(win_id);
+        }
+    }
+
+    pub fn remove_from_window(&mut self, win_id: usize) {
+        self.windows.retain(|id| *id != win_id);
+    }
+
+    pub fn windows(&self) -> &[usize] {
+        &self.windows
+    }
+
+    /// The text differs from the last save. Undoing back to the saved
+    /// revision makes the buffer unmodified again
+    pub fn is_modified(&self) -> bool {
+        self.history.has_pending() || self.saved != self.history.current()
+    }
+
+    /// path shown for the buffer, `[No Name]` without one
+    pub fn name(&self) -> String {
+        match &self.path {
+            Some(path) => path.display().to_string(),
+            None => "[No Name]".to_string(),
+        }
+    }
+
+    /// language of the text, known from the file extension
+    pub fn filetype(&self) -> Option<&'static str> {
+        self.syntax.as_ref().map(Syntax::name)
+    }
+
+    pub fn options(&self) -> &BufferOptions {
+        &self.options
+    }
+
+    pub fn set_options(&mut self, options: BufferOptions) {
+        self.options = options;
+    }
+
+    /// options set for this buffer alone
+    pub fn local_settings(&self) -> &[Setting] {
+        &self.local
+    }
+
+    /// Set an option for this buffer alone, over an earlier value for it.
+    /// It takes effect once the options are set again
+    pub fn set_local(&mut self, setting: Setting) {
+        let kind = std::mem::discriminant(&setting);
+
+        self.local.retain(|s| std::mem::discriminant(s) != kind);
+        self.local.push(setting);
+    }
+
+    /// insert a tab, or with `expandtab` spaces up to the next multiple of
+    /// `shiftwidth` columns
+    pub fn insert_tab(&mut self) {
+        let BufferOptions {
+            expandtab,
+            shiftwidth,
+        } = self.options;
+
+        match expandtab {
+            true => self.insert(&" ".repeat(shiftwidth - self.cursor_width() % shiftwidth)),
+            false => self.insert("\t"),
+        }
+    }
+
+    pub fn get_cursor(&self) -> &Cursor {
+        &self.cursor
+    }
+
+    /// number of chars in `row`, not counting the line break
+    pub fn line_len(&self, row: usize) -> usize {
+        if row >= self.text.len_lines() {
+            return 0;
+        }
+
+        let line = self.text.line(row);
+        let len = line.len_chars();
+
+        match line.chars().last() {
+            Some('\n') => len - 1,
+            _ => len,
+        }
+    }
+
+    pub fn text(&self) -> &Rope {
+        &self.text
+    }
+
+    /// contents of `row` without the line break
+    pub fn line_text(&self, row: usize) -> String {
+        self.line_slice(row).to_string()
+    }
+
+    /// text of `row` without its line break
+    pub fn line_slice(&self, row: usize) -> RopeSlice<'_> {
+        self.text.line(row).slice(..self.line_len(row))
+    }
+
+    /// char ranges of `row` shown on each screen row when soft wrapped
+    pub fn wrapped_rows(&self, row: usize, wrap: Wrap) -> Vec<Range<usize>> {
+        text::wrap(self.line_slice(row), wrap)
+    }
+
+    /// column of the first non whitespace char in `row`
+    pub fn first_non_blank(&self, row: usize) -> usize {
+        self.text
+            .line(row)
+            .chars()
+            .take(self.line_len(row))
+            .take_while(|c| c.is_whitespace())
+            .count()
+    }
+
+    /// char index into the rope of the cursor
+    pub fn cursor_char(&self) -> usize {
+        self.text.line_to_char(self.cursor.row) + self.cursor.col
+    }
+
+    pub fn set_cursor_char(&mut self, idx: usize) {
+        let idx = idx.min(self.text.len_chars());
+        let row = self.text.char_to_line(idx);
+
+        self.cursor = Cursor {
+            row,
+            col: idx - self.text.line_to_char(row),
+        };
+        self.desired_col = None;
+    }
+
+    /// replace a char range with `content`, leaving the cursor where it is
+    /// in the rope
+    pub fn replace(&mut self, range: Range<usize>, content: &str) {
+        self.change(range, content);
+    }
+
+    /// insert text at a char index, leaving the cursor where it is in the
+    /// rope (callers reposition it as needed)
+    pub fn insert_at(&mut self, idx: usize, content: &str) {
+        self.change(idx..idx, content);
+    }
+
+    /// remove a char range from the rope, returning what was removed
+    pub fn remove(&mut self, range: Range<usize>) -> String {
+        self.change(range, "")
+    }
+
+    /// Replace a char range of the rope. Every edit to the text goes through
+    /// here so it is recorded in the undo history
+    fn change(&mut self, range: Range<usize>, content: &str) -> String {
+        let removed = self.text.slice(range.clone()).to_string();
+
+        let change = Change {
+            at: range.start,
+            removed: removed.clone(),
+            inserted: content.to_string(),
+        };
+
+        if let Some(syntax) = &mut self.syntax {
+            syntax.edit(&self.text, &change);
+        }
+
+        change.apply(&mut self.text);
+        self.history.record(change.clone(), self.cursor);
+        self.changes.push(change);
+
+        removed
+    }
+
+    /// Close the current group of changes as one undo step. Until this is
+    /// called edits keep being added to the same step
+    pub fn commit(&mut self) {
+        self.history.commit(self.cursor);
+    }
+
+    /// step back a revision, false when there was none to undo
+    pub fn undo(&mut self) -> bool {
+        self.commit();
+        let cursor = self.history.undo(&mut self.text);
+        let undone = cursor.is_some();
+        self.restore_cursor(cursor);
+        undone
+    }
+
+    /// step forward a revision, false when there was none to redo
+    pub fn redo(&mut self) -> bool {
+        self.commit();
+        let cursor = self.history.redo(&mut self.text);
+        let redone = cursor.is_some();
+        self.restore_cursor(cursor);
+        redone
+    }
+
+    /// move back through revisions in the order they were made, reaching
+    /// branches undo and redo can not
+    pub fn earlier(&mut self, step: UndoStep) {
+        self.commit();
+        let cursor = self.history.earlier(&mut self.text, step);
+        self.restore_cursor(cursor);
+    }
+
+    /// move forward through revisions in the order they were made
+    pub fn later(&mut self, step: UndoStep) {
+        self.commit();
+        let cursor = self.history.later(&mut self.text, step);
+        self.restore_cursor(cursor);
+    }
+
+    /// Put the cursor back after moving through history, `None` when there
+    /// was nothing to undo or redo. The syntax tree is parsed from scratch
+    /// as history changes the rope without going through [`Buffer::change`]
+    fn restore_cursor(&mut self, cursor: Option<Cursor>) {
+        self.changes.extend(self.history.take_applied());
+
+        if let Some(Cursor { row, col }) = cursor {
+            if let Some(syntax) = &mut self.syntax {
+                syntax.reset();
+            }
+
+            let row = row.min(self.last_line());
+            self.cursor = Cursor {
+                row,
+                col: col.min(self.line_len(row)),
+            };
+            self.desired_col = None;
+        }
+    }
+
+    /// parse the text again if it changed since the syntax tree was built
+    pub fn parse_syntax(&mut self) {
+        if let Some(syntax) = &mut self.syntax {
+            syntax.parse(&self.text);
+        }
+    }
+
+    /// syntax errors found parsing the text, see [`Syntax::errors`]
+    pub fn syntax_errors(&self) -> usize {
+        self.syntax.as_ref().map_or(0, Syntax::errors)
+    }
+
+    /// highlighted char columns of `row` with the name of each, see
+    /// [`Syntax::highlights`]
+    pub fn syntax_highlights(&self, row: usize) -> Vec<(Range<usize>, &str)> {
+        match &self.syntax {
+            Some(syntax) => syntax.highlights(&self.text, row),
+            None => Vec::new(),
+        }
+    }
+
+    /// changes to the text since this was last called
+    pub fn take_changes(&mut self) -> Vec<Change> {
+        std::mem::take(&mut self.changes)
+    }
+
+    /// keep the cursor on a grapheme rather than past the end of the line, as
+    /// it is outside of insert mode
+    pub fn clamp_cursor(&mut self) {
+        let Cursor { row, col } = self.cursor;
+        let len = self.line_len(row);
+
+        if len > 0 && col >= len {
+            let line = self.text.line(row);
+            self.cursor.col = text::prev_grapheme_boundary(line, len);
+        }
+    }
+
+    pub fn selection(&self) -> Option<&Selection> {
+        self.selection.as_ref()
+    }
+
+    pub fn set_selection(&mut self, selection: Option<Selection>) {
+        self.selection = selection;
+    }
+
+    /// Columns of `row` covered by the selection. The range ends one past
+    /// the line length when the line break itself is selected
+    pub fn selection_on_line(&self, row: usize) -> Option<Range<usize>> {
+        let Selection { anchor, kind } = self.selection?;
+        let cursor = self.cursor_char();
+
+        let anchor_row = self.text.char_to_line(anchor);
+        let (top, bottom) = min_max(anchor_row, self.cursor.row);
+
+        if row < top || row > bottom {
+            return None;
+        }
+
+        let len = self.line_len(row);
+
+        match kind {
+            SelectionKind::Line => Some(0..len + 1),
+            SelectionKind::Char => {
+                let (start, end) = min_max(anchor, cursor);
+                let end = text::next_grapheme_boundary(self.text.slice(..), end);
+
+                let line_start = self.text.line_to_char(row);
+                let start = start.saturating_sub(line_start);
+                let end = (end - line_start).min(len + 1);
+
+                Some(start..end)
+            }
+            SelectionKind::Block => {
+                let anchor_col = anchor - self.text.line_to_char(anchor_row);
+                let (left, right) =
+                    min_max(self.width_at(anchor_row, anchor_col), self.cursor_width());
+
+                Some(self.block_cols(row, left, right))
+            }
+        }
+    }
+
+    /// char columns of `row` inside the display columns `left..=right`
+    pub fn block_cols(&self, row: usize, left: usize, right: usize) -> Range<usize> {
+        let len = self.line_len(row);
+        let start = self.col_at_width(row, left);
+
+        if start >= len {
+            return len..len;
+        }
+
+        let end = self.col_at_width(row, right);
+        let end = if end < len {
+            text::next_grapheme_boundary(self.text.line(row), end)
+        } else {
+            len
+        };
+
+        start..end
+    }
+
+    /// insert text at the cursor, leaving the cursor after it
+    pub fn insert(&mut self, content: &str) {
+        let pos = self.cursor_char();
+        self.change(pos..pos, content);
+        self.desired_col = None;
+
+        match content.rfind('\n') {
+            Some(i) => {
+                self.cursor.row += content.matches('\n').count();
+                self.cursor.col = content[i + 1..].chars().count();
+            }
+            None => self.cursor.col += content.chars().count(),
+        }
+    }
+
+    /// split the line at the cursor
+    pub fn newline(&mut self) {
+        self.insert("\n");
+    }
+
+    /// remove the grapheme before the cursor, joining with the previous line
+    /// when at the start of a line
+    pub fn backspace(&mut self) {
+        let pos = self.cursor_char();
+
+        if pos == 0 {
+            return;
+        }
+
+        let prev = text::prev_grapheme_boundary(self.text.slice(..), pos);
+
+        self.change(prev..pos, "");
+        self.set_cursor_char(prev);
+    }
+
+    /// remove the grapheme under the cursor, joining the next line when at
+    /// the end of a line
+    pub fn delete(&mut self) {
+        let pos = self.cursor_char();
+
+        if pos == self.text.len_chars() {
+            return;
+        }
+
+        let next = text::next_grapheme_boundary(self.text.slice(..), pos);
+        self.change(pos..next, "");
+        self.desired_col = None;
+    }
+
+    pub fn move_left(&mut self) {
+        let Cursor { row, col } = self.cursor;
+
+        if col > 0 {
+            let line = self.text.line(row);
+            self.cursor.col = text::prev_grapheme_boundary(line, col);
+        } else if row > 0 {
+            self.cursor.row -= 1;
+            self.cursor.col = self.line_len(row - 1);
+        }
+
+        self.desired_col = None;
+    }
+
+    pub fn move_right(&mut self) {
+        let Cursor { row, col } = self.cursor;
+
+        if col < self.line_len(row) {
+            let line = self.text.line(row);
+            self.cursor.col = text::next_grapheme_boundary(line, col);
+        } else if row < self.last_line() {
+            self.cursor.row += 1;
+            self.cursor.col = 0;
+        }
+
+        self.desired_col = None;
+    }
+
+    pub fn move_up(&mut self, count: usize) {
+        self.move_to_line(self.cursor.row.saturating_sub(count));
+    }
+
+    pub fn move_down(&mut self, count: usize) {
+        self.move_to_line(self.cursor.row.saturating_add(count));
+    }
+
+    /// Move `delta` screen rows up or down through soft wrapped lines,
+    /// keeping the screen column
+    pub fn move_screen_rows(&mut self, delta: isize, wrap: Wrap) {
+        let Cursor { mut row, col } = self.cursor;
+        let mut rows = self.wrapped_rows(row, wrap);
+        let (sub, screen_col) = text::wrap_pos(self.line_slice(row), wrap, &rows, col);
+        let mut sub = sub.min(rows.len() - 1);
+
+        for _ in 0..delta.unsigned_abs() {
+            if delta < 0 && sub > 0 {
+                sub -= 1;
+            } else if delta < 0 && row > 0 {
+                row -= 1;
+                rows = self.wrapped_rows(row, wrap);
+                sub = rows.len() - 1;
+            } else if delta > 0 && sub + 1 < rows.len() {
+                sub += 1;
+            } else if delta > 0 && row < self.last_line() {
+                row += 1;
+                rows = self.wrapped_rows(row, wrap);
+                sub = 0;
+            } else {
+                break;
+            }
+        }
+
+        self.cursor = Cursor {
+            row,
+            col: text::wrap_col(self.line_slice(row), wrap, &rows, sub, screen_col),
+        };
+        self.desired_col = None;
+    }
+
+    pub fn line_start(&mut self) {
+        self.cursor.col = 0;
+        self.desired_col = None;
+    }
+
+    /// move to the end of the line, staying at line ends on vertical moves
+    pub fn line_end(&mut self) {
+        self.cursor.col = self.line_len(self.cursor.row);
+        self.desired_col = Some(usize::MAX);
+    }
+
+    pub fn buffer_start(&mut self) {
+        self.cursor = Cursor::default();
+        self.desired_col = None;
+    }
+
+    pub fn buffer_end(&mut self) {
+        self.cursor.row = self.last_line();
+        self.line_end();
+    }
+
+    /// move to `row`, staying as close to the desired column as the line allows
+    fn move_to_line(&mut self, row: usize) {
+        let desired = match self.desired_col {
+            Some(desired) => desired,
+            None => self.cursor_width(),
+        };
+
+        self.cursor.row = row.min(self.last_line());
+        self.cursor.col = self.col_at_width(self.cursor.row, desired);
+        self.desired_col = Some(desired);
+    }
+
+    /// char column of the grapheme covering display column `width` of `row`
+    pub fn col_at_width(&self, row: usize, width: usize) -> usize {
+        let len = self.line_len(row);
+        let line = self.text.line(row).slice(..len);
+
+        let mut col = 0;
+        let mut seen = 0;
+
+        for g in text::RopeGraphemes::new(line) {
+            seen += text::grapheme_width(&g);
+
+            if seen > width {
+                break;
+            }
+
+            col += g.chars().count();
+        }
+
+        col
+    }
+
+    pub fn last_line(&self) -> usize {
+        self.text.len_lines() - 1
+    }
+
+    /// terminal columns between the start of the line and the cursor
+    pub fn cursor_width(&self) -> usize {
+        let Cursor { row, col } = self.cursor;
+        self.width_at(row, col)
+    }
+
+    /// terminal columns between the start of `row` and `col`
+    pub fn width_at(&self, row: usize, col: usize) -> usize {
+        text::width(self.text.line(row).slice(..col))
+    }
+
+    pub fn get_lines_range(&self, start: usize, end: usize) -> Lines<'_> {
+        info!("getting lines start {} end {}", start, end);
+
+        let end = end.min(self.text.len_lines());
+        let start = start.min(end);
+
+        let slice = self
+            .text
+            .slice(self.text.line_to_char(start)..self.text.line_to_char(end));
+
+        Lines {
+            lines: slice,
+            len: end - start,
+        }
+    }
+}
+
+fn min_max(a: usize, b: usize) -> (usize, usize) {
+    if a <= b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+/// The file `path` leads to through any symlinks, or `path` itself. A
+/// link to a file yet to be written leads to where the link points
+fn link_target(path: &Path) -> PathBuf {
+    if let Ok(target) = fs::canonicalize(path) {
+        return target;
+    }
+
+    match fs::read_link(path) {
+        Ok(target) => path.parent().unwrap_or(Path::new("")).join(target),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+/// hidden sibling of `path` used while writing
+fn tmp_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy())
+        .unwrap_or_default();
+
+    path.with_file_name(format!(".{}.pigeon-tmp", name))
+}
+
+#[derive(Default, Debug)]
+pub struct BufferBuilder {
+    text: Option<Rope>,
+    syntax: Option<Syntax>,
+}
+
+impl BufferBuilder {
+    /// panics if values not met
+    pub fn create(self) -> Buffer {
+        match self {
+            BufferBuilder {
+                text: Some(text),
+                syntax,
+            } => Buffer::new(text, syntax, None),
+            _ => panic!("Buffer constraints not met"),
+        }
+    }
+
+    pub fn with_text(mut self, text: String) -> Self {
+        self.text = Some(Rope::from(text));
+        self
+    }
+}
@@ -0,0 +1,256 @@
+//! Undo tree for a buffer. Every edit is recorded as an invertible change to
+//! the rope, changes are grouped into transactions and each committed
+//! transaction becomes a revision in the tree. Undoing after a new edit keeps
+//! the old branch around, so it can still be reached by moving through the
+//! revisions in the order they were made
+
+use ropey::Rope;
+use std::time::{Duration, Instant};
+
+use crate::modes::Cursor;
+
+/// Replacement of `removed` with `inserted`, starting at char index `at`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Change {
+    pub at: usize,
+    pub removed: String,
+    pub inserted: String,
+}
+
+impl Change {
+    pub fn apply(&self, text: &mut Rope) {
+        let end = self.at + self.removed.chars().count();
+
+        text.remove(self.at..end);
+        text.insert(self.at, &self.inserted);
+    }
+
+    pub fn invert(&self) -> Change {
+        Change {
+            at: self.at,
+            removed: self.inserted.clone(),
+            inserted: self.removed.clone(),
+        }
+    }
+}
+
+/// changes made together, undone and redone as one step
+#[derive(Clone, Debug, Default)]
+struct Transaction {
+    changes: Vec<Change>,
+    cursor_before: Cursor,
+    cursor_after: Cursor,
+}
+
+impl Transaction {
+    /// make the changes, adding them to `applied`
+    fn apply(&self, text: &mut Rope, applied: &mut Vec<Change>) {
+        for change in &self.changes {
+            change.apply(text);
+            applied.push(change.clone());
+        }
+    }
+
+    /// undo the changes, adding their inverse to `applied`
+    fn revert(&self, text: &mut Rope, applied: &mut Vec<Change>) {
+        for change in self.changes.iter().rev() {
+            let change = change.invert();
+            change.apply(text);
+            applied.push(change);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Revision {
+    parent: usize,
+    /// most recently made or visited child, followed by redo
+    last_child: Option<usize>,
+    transaction: Transaction,
+    timestamp: Instant,
+}
+
+/// How far to move through the history with [`History::earlier`] and
+/// [`History::later`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UndoStep {
+    Steps(usize),
+    Time(Duration),
+}
+
+#[derive(Debug)]
+pub struct History {
+    /// revisions in the order they were made, the first is the empty root
+    revisions: Vec<Revision>,
+    current: usize,
+    /// changes not yet committed as a revision
+    pending: Option<Transaction>,
+    /// changes made to the text moving through the history
+    applied: Vec<Change>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            revisions: vec![Revision {
+                parent: 0,
+                last_child: None,
+                transaction: Default::default(),
+                timestamp: Instant::now(),
+            }],
+            current: 0,
+            pending: None,
+            applied: Vec::new(),
+        }
+    }
+}
+
+impl History {
+    /// add a change already made to the text to the open transaction
+    pub fn record(&mut self, change: Change, cursor_before: Cursor) {
+        self.pending
+            .get_or_insert_with(|| Transaction {
+                cursor_before,
+                ..Default::default()
+            })
+            .changes
+            .push(change);
+    }
+
+    /// changes undo and redo made to the text since this was last called
+    pub fn take_applied(&mut self) -> Vec<Change> {
+        std::mem::take(&mut self.applied)
+    }
+
+    /// id of the revision the text is at, ids are never reused
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    /// whether changes have been recorded since the last commit
+    pub fn has_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// close the open transaction, making it a new revision
+    pub fn commit(&mut self, cursor_after: Cursor) {
+        let mut transaction = match self.pending.take() {
+            Some(transaction) => transaction,
+            None => return,
+        };
+
+        transaction.cursor_after = cursor_after;
+
+        let id = self.revisions.len();
+        self.revisions.push(Revision {
+            parent: self.current,
+            last_child: None,
+            transaction,
+            timestamp: Instant::now(),
+        });
+        self.revisions[self.current].last_child = Some(id);
+        self.current = id;
+    }
+
+    /// Step back to the parent revision, returning where the cursor was
+    /// before the undone change
+    pub fn undo(&mut self, text: &mut Rope) -> Option<Cursor> {
+        if self.current == 0 {
+            return None;
+        }
+
+        let revision = &self.revisions[self.current];
+        revision.transaction.revert(text, &mut self.applied);
+
+        let cursor = revision.transaction.cursor_before;
+        self.current = revision.parent;
+
+        Some(cursor)
+    }
+
+    /// Step forward along the most recent branch, returning where the cursor
+    /// was after the redone change
+    pub fn redo(&mut self, text: &mut Rope) -> Option<Cursor> {
+        let child = self.revisions[self.current].last_child?;
+
+        let revision = &self.revisions[child];
+        revision.transaction.apply(text, &mut self.applied);
+        self.current = child;
+
+        Some(revision.transaction.cursor_after)
+    }
+
+    /// move to an older revision by creation order, across branches
+    pub fn earlier(&mut self, text: &mut Rope, step: UndoStep) -> Option<Cursor> {
+        let target = match step {
+            UndoStep::Steps(n) => self.current.saturating_sub(n),
+            UndoStep::Time(d) => {
+                let now = self.revisions[self.current].timestamp;
+                let before = now.checked_sub(d)?;
+                self.revisions
+                    .iter()
+                    .rposition(|r| r.timestamp <= before)
+                    .unwrap_or(0)
+            }
+        };
+
+        self.jump_to(text, target)
+    }
+
+    /// move to a newer revision by creation order, across branches
+    pub fn later(&mut self, text: &mut Rope, step: UndoStep) -> Option<Cursor> {
+        let last = self.revisions.len() - 1;
+
+        let target = match step {
+            UndoStep::Steps(n) => self.current.saturating_add(n).min(last),
+            UndoStep::Time(d) => {
+                let now = self.revisions[self.current].timestamp;
+                match now.checked_add(d) {
+                    Some(after) => self
+                        .revisions
+                        .iter()
+                        .position(|r| r.timestamp >= after)
+                        .unwrap_or(last),
+                    None => last,
+                }
+            }
+        };
+
+        self.jump_to(text, target)
+    }
+
+    /// undo up to the common ancestor of the current and target revisions,
+    /// then redo down to the target
+    fn jump_to(&mut self, text: &mut Rope, target: usize) -> Option<Cursor> {
+        if target == self.current {
+            return None;
+        }
+
+        let path = self.path_to_root(target);
+        let mut cursor = None;
+
+        while !path.contains(&self.current) {
+            cursor = self.undo(text);
+        }
+
+        // path runs from the target up, so walk it backwards to go down
+        let ancestor = path.iter().position(|id| *id == self.current)?;
+        for id in path[..ancestor].iter().rev() {
+            self.revisions[self.current].last_child = Some(*id);
+            cursor = self.redo(text);
+        }
+
+        cursor
+    }
+
+    fn path_to_root(&self, mut id: usize) -> Vec<usize> {
+        let mut path = vec![id];
+
+        while id != 0 {
+            id = self.revisions[id].parent;
+            path.push(id);
+        }
+
+        path
+    }
+}
@@ -0,0 +1,241 @@
+//! Syntax trees for buffers in a language with a bundled grammar. The tree
+//! is edited along with the rope and parsed again from the old tree before
+//! it is next drawn, so only the parts around the changes are parsed. The
+//! highlight query of the grammar names nodes, the renderer picks a style
+//! for each name
+
+use log::error;
+use ropey::Rope;
+use std::cmp::Reverse;
+use std::fmt;
+use std::ops::Range;
+use std::path::Path;
+use tree_sitter::{InputEdit, Language, Node, Parser, Point, Query, QueryCursor, Tree};
+
+use super::history::Change;
+
+/// a grammar built into the editor
+struct Grammar {
+    name: &'static str,
+    /// file extensions the language is picked for
+    extensions: &'static [&'static str],
+    language: fn() -> Language,
+    highlights: &'static str,
+}
+
+const GRAMMARS: &[Grammar] = &[
+    Grammar {
+        name: "rust",
+        extensions: &["rs"],
+        language: tree_sitter_rust::language,
+        highlights: tree_sitter_rust::HIGHLIGHT_QUERY,
+    },
+    Grammar {
+        name: "toml",
+        extensions: &["toml"],
+        language: tree_sitter_toml::language,
+        highlights: tree_sitter_toml::HIGHLIGHT_QUERY,
+    },
+];
+
+pub struct Syntax {
+    name: &'static str,
+    parser: Parser,
+    query: Query,
+    tree: Option<Tree>,
+    /// the text changed since the tree was last parsed
+    stale: bool,
+    /// nodes of the tree the parser could not make sense of or made up
+    errors: usize,
+}
+
+impl fmt::Debug for Syntax {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Syntax")
+            .field("name", &self.name)
+            .field("stale", &self.stale)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Syntax {
+    /// syntax for the bundled language called `name`
+    pub fn new(name: &str) -> Option<Self> {
+        let grammar = GRAMMARS.iter().find(|grammar| grammar.name == name)?;
+        let language = (grammar.language)();
+
+        let mut parser = Parser::new();
+        if let Err(e) = parser.set_language(language) {
+            error!("could not load the {} grammar: {}", name, e);
+            return None;
+        }
+
+        let query = match Query::new(language, grammar.highlights) {
+            Ok(query) => query,
+            Err(e) => {
+                error!("bad highlight query for {}: {:?}", name, e);
+                return None;
+            }
+        };
+
+        Some(Self {
+            name: grammar.name,
+            parser,
+            query,
+            tree: None,
+            stale: true,
+            errors: 0,
+        })
+    }
+
+    /// syntax for a file, picked by its extension
+    pub fn for_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?;
+        let grammar = GRAMMARS
+            .iter()
+            .find(|grammar| grammar.extensions.contains(&extension))?;
+
+        Self::new(grammar.name)
+    }
+
+    /// name of the language, which is the filetype of the buffer
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Tell the tree about `change`, before it is applied to `text`
+    pub fn edit(&mut self, text: &Rope, change: &Change) {
+        self.stale = true;
+
+        let tree = match &mut self.tree {
+            Some(tree) => tree,
+            None => return,
+        };
+
+        let start_byte = text.char_to_byte(change.at);
+        let row = text.byte_to_line(start_byte);
+        let start_position = Point::new(row, start_byte - text.line_to_byte(row));
+
+        tree.edit(&InputEdit {
+            start_byte,
+            old_end_byte: start_byte + change.removed.len(),
+            new_end_byte: start_byte + change.inserted.len(),
+            start_position,
+            old_end_position: end_point(start_position, &change.removed),
+            new_end_position: end_point(start_position, &change.inserted),
+        });
+    }
+
+    /// drop the tree after changes it was not told about, so the next parse
+    /// starts over
+    pub fn reset(&mut self) {
+        self.tree = None;
+        self.stale = true;
+    }
+
+    /// bring the tree up to date with `text`
+    pub fn parse(&mut self, text: &Rope) {
+        if !self.stale {
+            return;
+        }
+
+        self.tree = self
+            .parser
+            .parse_with(&mut |byte, _| chunk_at(text, byte), self.tree.as_ref());
+        self.stale = false;
+        self.errors = self.tree.as_ref().map_or(0, count_errors);
+    }
+
+    /// syntax errors in the text as of the last parse
+    pub fn errors(&self) -> usize {
+        self.errors
+    }
+
+    /// Char columns of `row` inside highlighted nodes and the name the
+    /// query gave each node. Nodes come before the nodes inside them, and
+    /// where patterns name the same node the first one wins
+    pub fn highlights<'a>(&'a self, text: &Rope, row: usize) -> Vec<(Range<usize>, &'a str)> {
+        let tree = match &self.tree {
+            Some(tree) if row < text.len_lines() => tree,
+            _ => return Vec::new(),
+        };
+
+        let line_start = text.line_to_byte(row);
+        let line_end = line_start + text.line(row).len_bytes();
+
+        let mut cursor = QueryCursor::new();
+        cursor.set_byte_range(line_start..line_end);
+
+        let source = |node: Node| {
+            text.byte_slice(node.byte_range())
+                .chunks()
+                .map(str::as_bytes)
+        };
+
+        let mut found: Vec<(Range<usize>, u32)> = Vec::new();
+        for (m, i) in cursor.captures(&self.query, tree.root_node(), source) {
+            let capture = m.captures[i];
+            let range = capture.node.byte_range();
+
+            if !found.iter().any(|(seen, _)| *seen == range) {
+                found.push((range, capture.index));
+            }
+        }
+        found.sort_by_key(|(range, _)| (range.start, Reverse(range.end)));
+
+        let line_char = text.byte_to_char(line_start);
+        let names = self.query.capture_names();
+
+        found
+            .into_iter()
+            .map(|(range, index)| {
+                let start = text.byte_to_char(range.start.max(line_start)) - line_char;
+                let end = text.byte_to_char(range.end.min(line_end)) - line_char;
+                (start..end, names[index as usize].as_str())
+            })
+            .filter(|(cols, _)| !cols.is_empty())
+            .collect()
+    }
+}
+
+/// Error and missing nodes of `tree`, going only into nodes with errors
+/// inside them
+fn count_errors(tree: &Tree) -> usize {
+    let mut cursor = tree.walk();
+    let mut errors = 0;
+
+    loop {
+        let node = cursor.node();
+        if node.is_error() || node.is_missing() {
+            errors += 1;
+        }
+
+        if node.has_error() && !node.is_error() && cursor.goto_first_child() {
+            continue;
+        }
+
+        while !cursor.goto_next_sibling() {
+            if !cursor.goto_parent() {
+                return errors;
+            }
+        }
+    }
+}
+
+/// the bytes of `text` from `byte` to the end of its chunk
+fn chunk_at(text: &Rope, byte: usize) -> &[u8] {
+    if byte >= text.len_bytes() {
+        return &[];
+    }
+
+    let (chunk, start, _, _) = text.chunk_at_byte(byte);
+    &chunk.as_bytes()[byte - start..]
+}
+
+/// where `text` ends when put at `start`
+fn end_point(start: Point, text: &str) -> Point {
+    match text.rfind('\n') {
+        Some(i) => Point::new(start.row + text.matches('\n').count(), text.len() - i - 1),
+        None => Point::new(start.row, start.column + text.len()),
+    }
+}
@@ -0,0 +1,434 @@
+//! Named commands, the entry points to what the editor can do. Key bindings,
+//! the command line and the command palette run them by name, such as
+//! `buffer.save` or `window.split`, with an optional count and argument.
+//! Commands are kept in a [`Registry`] so more can be added while running
+
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use crate::modes::Picker;
+use crate::renderer::{Backend, Direction, UI};
+
+use super::buffer::Buffer;
+use super::App;
+
+/// what a command is run with
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Args<'a> {
+    /// count typed before the keys bound to the command
+    pub count: Option<usize>,
+    /// text after the name on the command line
+    pub arg: &'a str,
+}
+
+impl Args<'_> {
+    /// times to repeat the command, once without a count
+    pub fn times(&self) -> usize {
+        self.count.unwrap_or(1)
+    }
+
+    /// the count as a signed step, as large as fits when it does not
+    pub fn steps(&self) -> isize {
+        isize::try_from(self.times()).unwrap_or(isize::MAX)
+    }
+
+    fn file(&self) -> Option<&str> {
+        match self.arg.is_empty() {
+            true => None,
+            false => Some(self.arg),
+        }
+    }
+}
+
+/// most bytes a count can repeat the text given to `edit.insert` to
+const MAX_INSERT: usize = 64 << 20;
+
+/// most times a count repeats a line break or indent
+const MAX_REPEAT: usize = 1 << 12;
+
+pub type Run<B> = Rc<dyn Fn(&mut App<B>, Args) -> Result<(), String>>;
+
+pub struct Command<B: Backend> {
+    pub name: String,
+    /// one line on what the command does, shown in the palette
+    pub doc: String,
+    run: Run<B>,
+}
+
+/// The commands known by name, in order of their names
+pub struct Registry<B: Backend> {
+    commands: BTreeMap<String, Command<B>>,
+}
+
+impl<B: Backend> Default for Registry<B> {
+    fn default() -> Self {
+        let mut registry = Self {
+            commands: BTreeMap::new(),
+        };
+
+        for (name, doc, run) in builtins::<B>() {
+            registry.register(name, doc, run);
+        }
+
+        registry
+    }
+}
+
+impl<B: Backend> Registry<B> {
+    /// Add a command, replacing any of the same name
+    pub fn register(
+        &mut self,
+        name: &str,
+        doc: &str,
+        run: impl Fn(&mut App<B>, Args) -> Result<(), String> + 'static,
+    ) {
+        let command = Command {
+            name: name.to_string(),
+            doc: doc.to_string(),
+            run: Rc::new(run),
+        };
+        self.commands.insert(name.to_string(), command);
+    }
+
+    /// what runs the command called `name`
+    pub fn get(&self, name: &str) -> Option<Run<B>> {
+        self.commands.get(name).map(|command| command.run.clone())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Command<B>> {
+        self.commands.values()
+    }
+}
+
+/// `:wincmd` keys and the commands they run
+pub const WINCMD: &[(char, &str)] = &[
+    ('s', "window.split"),
+    ('S', "window.split"),
+    ('v', "window.vsplit"),
+    ('c', "window.close"),
+    ('q', "window.close"),
+    ('o', "window.only"),
+    ('w', "window.next"),
+    ('W', "window.prev"),
+    ('h', "window.left"),
+    ('j', "window.down"),
+    ('k', "window.up"),
+    ('l', "window.right"),
+    ('+', "window.taller"),
+    ('-', "window.shorter"),
+    ('>', "window.wider"),
+    ('<', "window.narrower"),
+    ('=', "window.equalize"),
+];
+
+type Builtin<B> = (
+    &'static str,
+    &'static str,
+    fn(&mut App<B>, Args) -> Result<(), String>,
+);
+
+fn builtins<B: Backend>() -> Vec<Builtin<B>> {
+    vec![
+        (
+            "buffer.save",
+            "Write the buffer, to the file given or its own",
+            |app, args| app.write(args.file()),
+        ),
+        (
+            "buffer.save_all",
+            "Write every modified buffer",
+            |app, _| app.write_all(),
+        ),
+        ("buffer.new", "Open a new empty buffer", |app, _| {
+            let id = app.add_buffer(Buffer::default());
+            app.show_buffer(id);
+            Ok(())
+        }),
+        ("buffer.next", "Show the next buffer", |app, args| {
+            let buff = &app.modes.buff;
+            app.show_buffer(buff.cycle(buff.active(), args.steps()));
+            Ok(())
+        }),
+        ("buffer.prev", "Show the previous buffer", |app, args| {
+            let buff = &app.modes.buff;
+            app.show_buffer(buff.cycle(buff.active(), -args.steps()));
+            Ok(())
+        }),
+        ("buffer.delete", "Close the buffer", |app, _| {
+            app.delete_buffer(app.modes.buff.active(), false)
+        }),
+        ("buffer.pick", "List the buffers to pick one", |app, _| {
+            app.buffer_picker(None);
+            Ok(())
+        }),
+        ("cursor.left", "Move the cursor left", |app, args| {
+            cursor(app, |b| {
+                let times = args.times().min(b.text().len_chars());
+                (0..times).for_each(|_| b.move_left())
+            })
+        }),
+        ("cursor.right", "Move the cursor right", |app, args| {
+            cursor(app, |b| {
+                let times = args.times().min(b.text().len_chars());
+                (0..times).for_each(|_| b.move_right())
+            })
+        }),
+        ("cursor.up", "Move the cursor up", |app, args| {
+            cursor(app, |b| b.move_up(args.times()))
+        }),
+        ("cursor.down", "Move the cursor down", |app, args| {
+            cursor(app, |b| b.move_down(args.times()))
+        }),
+        (
+            "cursor.line_start",
+            "Move the cursor to the start of the line",
+            |app, _| cursor(app, Buffer::line_start),
+        ),
+        (
+            "cursor.line_end",
+            "Move the cursor to the end of the line",
+            |app, _| cursor(app, Buffer::line_end),
+        ),
+        (
+            "cursor.buffer_start",
+            "Move the cursor to the first line",
+            |app, _| cursor(app, Buffer::buffer_start),
+        ),
+        (
+            "cursor.buffer_end",
+            "Move the cursor to the last line",
+            |app, _| cursor(app, Buffer::buffer_end),
+        ),
+        ("cursor.page_up", "Scroll a page up", |app, args| {
+            page(app, true, args.times())
+        }),
+        ("cursor.page_down", "Scroll a page down", |app, args| {
+            page(app, false, args.times())
+        }),
+        ("edit.insert", "Insert the text given", |app, args| {
+            if args.arg.is_empty() {
+                return Err("Argument required".to_string());
+            }
+            let times = args.times();
+            let text = match args.arg.len().checked_mul(times) {
+                Some(size) if size <= MAX_INSERT => args.arg.repeat(times),
+                _ => return Err(format!("Count too large to insert: {}", times)),
+            };
+            let window = app.ui.get_active_window();
+            app.modes.buff.edit(window, |b| b.insert(&text));
+            Ok(())
+        }),
+        (
+            "edit.newline",
+            "Break the line at the cursor",
+            |app, args| edit(app, args, |_| MAX_REPEAT, Buffer::newline),
+        ),
+        (
+            "edit.backspace",
+            "Delete the char before the cursor",
+            |app, args| edit(app, args, Buffer::cursor_char, Buffer::backspace),
+        ),
+        (
+            "edit.delete",
+            "Delete the char under the cursor",
+            |app, args| {
+                let after = |b: &Buffer| b.text().len_chars() - b.cursor_char();
+                edit(app, args, after, Buffer::delete)
+            },
+        ),
+        ("edit.tab", "Indent to the next tab stop", |app, args| {
+            edit(app, args, |_| MAX_REPEAT, Buffer::insert_tab)
+        }),
+        ("edit.undo", "Undo the last change", |app, args| {
+            history(app, args, Buffer::undo)
+        }),
+        ("edit.redo", "Redo the last change undone", |app, args| {
+            history(app, args, Buffer::redo)
+        }),
+        (
+            "edit.earlier",
+            "Go back in time through the changes",
+            |app, args| app.undo_command(true, &history_step(args)),
+        ),
+        (
+            "edit.later",
+            "Go forward in time through the changes",
+            |app, args| app.undo_command(false, &history_step(args)),
+        ),
+        (
+            "window.split",
+            "Split the window in two, one above the other",
+            |app, _| window(app, |ui| ui.split(Direction::Horizontal)),
+        ),
+        (
+            "window.vsplit",
+            "Split the window in two side by side",
+            |app, _| window(app, |ui| ui.split(Direction::Vertical)),
+        ),
+        ("window.close", "Close the window", |app, _| {
+            window(app, |ui| {
+                ui.close();
+            })
+        }),
+        ("window.only", "Close every other window", |app, _| {
+            window(app, UI::only)
+        }),
+        ("window.next", "Move to the next window", |app, args| {
+            window(app, |ui| {
+                let times = args.times() % ui.window_count();
+                (0..times).for_each(|_| ui.cycle(true))
+            })
+        }),
+        ("window.prev", "Move to the previous window", |app, args| {
+            window(app, |ui| {
+                let times = args.times() % ui.window_count();
+                (0..times).for_each(|_| ui.cycle(false))
+            })
+        }),
+        ("window.left", "Move to the window on the left", |app, _| {
+            window(app, |ui| ui.focus(0, -1))
+        }),
+        ("window.down", "Move to the window below", |app, _| {
+            window(app, |ui| ui.focus(1, 0))
+        }),
+        ("window.up", "Move to the window above", |app, _| {
+            window(app, |ui| ui.focus(-1, 0))
+        }),
+        (
+            "window.right",
+            "Move to the window on the right",
+            |app, _| window(app, |ui| ui.focus(0, 1)),
+        ),
+        ("window.taller", "Make the window taller", |app, args| {
+            resize(app, Direction::Horizontal, args.steps())
+        }),
+        ("window.shorter", "Make the window shorter", |app, args| {
+            resize(app, Direction::Horizontal, -args.steps())
+        }),
+        ("window.wider", "Make the window wider", |app, args| {
+            resize(app, Direction::Vertical, args.steps())
+        }),
+        (
+            "window.narrower",
+            "Make the window narrower",
+            |app, args| resize(app, Direction::Vertical, -args.steps()),
+        ),
+        (
+            "window.equalize",
+            "Make the windows the same size",
+            |app, _| window(app, UI::equalize),
+        ),
+        (
+            "search.clear",
+            "Stop showing the matches of the last search",
+            |app, _| {
+                app.modes.editor.search().clear_highlight();
+                Ok(())
+            },
+        ),
+        (
+            "editor.quit",
+            "Close the window, quitting after the last",
+            |app, _| app.quit(false),
+        ),
+        (
+            "editor.quit_all",
+            "Quit unless a buffer has unsaved changes",
+            |app, _| app.quit_all(false),
+        ),
+        (
+            "editor.palette",
+            "Search the commands to run one",
+            |app, _| {
+                palette(app);
+                Ok(())
+            },
+        ),
+    ]
+}
+
+/// move the cursor of the active buffer
+fn cursor<B: Backend>(app: &mut App<B>, cb: impl FnOnce(&mut Buffer)) -> Result<(), String> {
+    let window = app.ui.get_active_window();
+    app.modes.buff.move_cursor(window, cb);
+    Ok(())
+}
+
+fn page<B: Backend>(app: &mut App<B>, up: bool, times: usize) -> Result<(), String> {
+    let window = app.ui.get_active_window();
+    // past this many pages every line has been scrolled through
+    let pages = app.modes.buff.get_active_buffer().last_line() / window.rows().max(1) + 1;
+    (0..times.min(pages)).for_each(|_| app.modes.buff.page(window, up));
+    Ok(())
+}
+
+/// change the active buffer, `args.times()` over but no more than `limit`
+/// of the buffer allows
+fn edit<B: Backend>(
+    app: &mut App<B>,
+    args: Args,
+    limit: impl Fn(&Buffer) -> usize,
+    cb: impl Fn(&mut Buffer),
+) -> Result<(), String> {
+    let window = app.ui.get_active_window();
+    app.modes.buff.edit(window, |b| {
+        let times = args.times().min(limit(b));
+        (0..times).for_each(|_| cb(b))
+    });
+    Ok(())
+}
+
+/// undo or redo `args.times()` over, stopping once there is nothing left
+fn history<B: Backend>(
+    app: &mut App<B>,
+    args: Args,
+    cb: impl Fn(&mut Buffer) -> bool,
+) -> Result<(), String> {
+    let window = app.ui.get_active_window();
+    app.modes.buff.edit(window, |b| {
+        for _ in 0..args.times() {
+            if !cb(b) {
+                break;
+            }
+        }
+    });
+    Ok(())
+}
+
+fn window<B: Backend>(app: &mut App<B>, cb: impl FnOnce(&mut UI)) -> Result<(), String> {
+    app.window_command(cb);
+    Ok(())
+}
+
+fn resize<B: Backend>(app: &mut App<B>, direction: Direction, delta: isize) -> Result<(), String> {
+    window(app, |ui| ui.resize_window(direction, delta))
+}
+
+/// the time given, or the count as a number of steps
+fn history_step(args: Args) -> String {
+    match args.file() {
+        Some(time) => time.to_string(),
+        None => args.times().to_string(),
+    }
+}
+
+/// Pick a command from all of them, searched by name and what they do
+fn palette<B: Backend>(app: &mut App<B>) {
+    let widest = app.commands.iter().map(|c| c.name.len()).max();
+    let items = app
+        .commands
+        .iter()
+        .enumerate()
+        .map(|(i, command)| {
+            let label = format!(
+                "{:widest$}  {}",
+                command.name,
+                command.doc,
+                widest = widest.unwrap_or_default()
+            );
+            (i, label)
+        })
+        .collect();
+
+    app.palette = Some(Picker::searching("Commands".to_string(), items));
+}
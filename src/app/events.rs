@@ -0,0 +1,160 @@
+//! Events telling what happened in the editor, such as a buffer changing or
+//! the mode switching, and the hooks run for them. Hooks subscribe to one
+//! type of event on the [`EventBus`], run in order of their priority and can
+//! stop those after them from seeing the event
+
+use std::any::{Any, TypeId};
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+
+use log::debug;
+
+use crate::modes::{Cursor, EditorState, Modes};
+
+use super::buffer::Change;
+
+/// Text of a buffer changed, by the changes in the order they were made,
+/// each replacing the text removed at its char offset
+#[derive(Clone, Debug)]
+pub struct BufferChanged {
+    pub buffer: usize,
+    pub changes: Vec<Change>,
+}
+
+/// The cursor moved, or another buffer became the active one
+#[derive(Clone, Copy, Debug)]
+pub struct CursorMoved {
+    pub buffer: usize,
+    pub cursor: Cursor,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ModeChanged {
+    pub from: EditorState,
+    pub to: EditorState,
+}
+
+/// A buffer was added, from a file or empty
+#[derive(Clone, Copy, Debug)]
+pub struct BufferOpened {
+    pub buffer: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct BufferSaved {
+    pub buffer: usize,
+    pub path: PathBuf,
+}
+
+/// The terminal changed size
+#[derive(Clone, Copy, Debug)]
+pub struct WindowResized {
+    pub rows: usize,
+    pub cols: usize,
+}
+
+/// Whether hooks after this one see the event
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Flow {
+    Continue,
+    Stop,
+}
+
+/// What hooks are given along with the event
+pub struct Ctx<'a> {
+    pub modes: &'a mut Modes,
+    emitted: Vec<(TypeId, Box<dyn Any>)>,
+}
+
+impl Ctx<'_> {
+    /// Emit an event, seen once the hooks of this one are done
+    pub fn emit<E: Any>(&mut self, event: E) {
+        self.emitted.push((TypeId::of::<E>(), Box::new(event)));
+    }
+}
+
+/// Hook subscribed to events of one type, to unsubscribe it with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Subscription {
+    event: TypeId,
+    id: usize,
+}
+
+type Callback = Box<dyn FnMut(&mut Ctx, &dyn Any) -> Flow>;
+
+struct Hook {
+    id: usize,
+    priority: i32,
+    cb: Callback,
+}
+
+/// Hooks by the type of event they are run for, and the events emitted
+/// but not yet seen by them
+#[derive(Default)]
+pub struct EventBus {
+    hooks: HashMap<TypeId, Vec<Hook>>,
+    queue: VecDeque<(TypeId, Box<dyn Any>)>,
+    next_id: usize,
+}
+
+impl EventBus {
+    /// Run `cb` for each event of type `E`. Hooks with a lower `priority`
+    /// run first, those of the same priority in the order they subscribed
+    pub fn subscribe<E, F>(&mut self, priority: i32, mut cb: F) -> Subscription
+    where
+        E: Any,
+        F: FnMut(&mut Ctx, &E) -> Flow + 'static,
+    {
+        let event = TypeId::of::<E>();
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let cb: Callback = Box::new(move |ctx, event| match event.downcast_ref::<E>() {
+            Some(event) => cb(ctx, event),
+            None => Flow::Continue,
+        });
+
+        let hooks = self.hooks.entry(event).or_default();
+        let at = hooks.partition_point(|hook| hook.priority <= priority);
+        hooks.insert(at, Hook { id, priority, cb });
+
+        Subscription { event, id }
+    }
+
+    /// Stop running a hook, false if it was already unsubscribed
+    pub fn unsubscribe(&mut self, subscription: Subscription) -> bool {
+        let hooks = match self.hooks.get_mut(&subscription.event) {
+            Some(hooks) => hooks,
+            None => return false,
+        };
+
+        let len = hooks.len();
+        hooks.retain(|hook| hook.id != subscription.id);
+        hooks.len() != len
+    }
+
+    /// queue an event for the next [`EventBus::dispatch`]
+    pub fn emit<E: Any>(&mut self, event: E) {
+        self.queue.push_back((TypeId::of::<E>(), Box::new(event)));
+    }
+
+    /// Run the hooks of each event queued, in the order they were emitted,
+    /// along with those the hooks emit
+    pub fn dispatch(&mut self, modes: &mut Modes) {
+        while let Some((event, payload)) = self.queue.pop_front() {
+            let mut ctx = Ctx {
+                modes,
+                emitted: Vec::new(),
+            };
+
+            for hook in self.hooks.get_mut(&event).into_iter().flatten() {
+                if (hook.cb)(&mut ctx, payload.as_ref()) == Flow::Stop {
+                    debug!("hook {} stopped the event", hook.id);
+                    break;
+                }
+            }
+
+            self.queue.extend(ctx.emitted);
+        }
+    }
+}
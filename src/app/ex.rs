@@ -0,0 +1,313 @@
+//! Ex commands, the lines entered after `:`. Each command has a full name and
+//! can be shortened down to a minimum length, as in vi, so `:sp` and
+//! `:split` are the same command
+
+use std::fs;
+use std::path::Path;
+
+use crate::keymap::MapMode;
+use crate::renderer::available_themes;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ex {
+    Write,
+    WriteAll,
+    Quit,
+    QuitAll,
+    WriteQuit,
+    WriteQuitAll,
+    Edit,
+    New,
+    Set,
+    Buffer,
+    Buffers,
+    BufferNext,
+    BufferPrev,
+    BufferDelete,
+    Split,
+    VSplit,
+    Close,
+    Only,
+    Wincmd,
+    Earlier,
+    Later,
+    Substitute,
+    NoHighlight,
+    Colorscheme,
+    Source,
+    /// bind keys in these modes
+    Map(&'static [MapMode]),
+    Unmap(&'static [MapMode]),
+}
+
+/// full name, shortest abbreviation and command
+const COMMANDS: &[(&str, usize, Ex)] = &[
+    ("write", 1, Ex::Write),
+    ("quit", 1, Ex::Quit),
+    ("qall", 2, Ex::QuitAll),
+    ("wq", 2, Ex::WriteQuit),
+    ("wqall", 3, Ex::WriteQuitAll),
+    ("wall", 2, Ex::WriteAll),
+    ("edit", 1, Ex::Edit),
+    ("enew", 3, Ex::New),
+    ("set", 2, Ex::Set),
+    ("buffer", 1, Ex::Buffer),
+    ("buffers", 7, Ex::Buffers),
+    ("ls", 2, Ex::Buffers),
+    ("bnext", 2, Ex::BufferNext),
+    ("bprevious", 2, Ex::BufferPrev),
+    ("bdelete", 2, Ex::BufferDelete),
+    ("split", 2, Ex::Split),
+    ("vsplit", 2, Ex::VSplit),
+    ("close", 3, Ex::Close),
+    ("only", 2, Ex::Only),
+    ("wincmd", 4, Ex::Wincmd),
+    ("earlier", 2, Ex::Earlier),
+    ("later", 3, Ex::Later),
+    ("substitute", 1, Ex::Substitute),
+    ("nohlsearch", 3, Ex::NoHighlight),
+    ("colorscheme", 4, Ex::Colorscheme),
+    ("source", 2, Ex::Source),
+    ("map", 3, Ex::Map(&[MapMode::Normal, MapMode::Visual])),
+    ("nmap", 2, Ex::Map(&[MapMode::Normal])),
+    ("vmap", 2, Ex::Map(&[MapMode::Visual])),
+    ("imap", 2, Ex::Map(&[MapMode::Insert])),
+    ("cmap", 2, Ex::Map(&[MapMode::Command])),
+    ("unmap", 3, Ex::Unmap(&[MapMode::Normal, MapMode::Visual])),
+    ("nunmap", 3, Ex::Unmap(&[MapMode::Normal])),
+    ("vunmap", 2, Ex::Unmap(&[MapMode::Visual])),
+    ("iunmap", 2, Ex::Unmap(&[MapMode::Insert])),
+    ("cunmap", 2, Ex::Unmap(&[MapMode::Command])),
+];
+
+/// options known to `:set`, for completion
+pub const OPTIONS: &[&str] = &[
+    "autosave",
+    "expandtab",
+    "noautosave",
+    "noexpandtab",
+    "nowrap",
+    "scrolloff=",
+    "shiftwidth=",
+    "showbreak=",
+    "sidescrolloff=",
+    "timeoutlen=",
+    "wrap",
+];
+
+/// a line named in a range
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Address {
+    /// line number, counting from 1
+    Line(usize),
+    /// `.`, the cursor line
+    Current,
+    /// `$`, the last line
+    Last,
+}
+
+impl Address {
+    /// buffer row of the address, given the cursor row and the last row
+    pub fn row(self, current: usize, last: usize) -> usize {
+        match self {
+            Address::Line(n) => n.saturating_sub(1).min(last),
+            Address::Current => current,
+            Address::Last => last,
+        }
+    }
+}
+
+/// lines a command acts on, written `N,M`, `N` or `%` for every line
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LineRange {
+    pub start: Address,
+    pub end: Address,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Parsed {
+    Command {
+        range: Option<LineRange>,
+        command: Ex,
+        /// written with a trailing `!`, forcing the command
+        bang: bool,
+        args: String,
+    },
+    /// a named command, see [`super::commands`], and the text after it
+    Named {
+        name: String,
+        args: String,
+    },
+    /// a range on its own, jumping to its last line
+    Goto(Address),
+    Empty,
+}
+
+fn lookup(name: &str) -> Option<Ex> {
+    COMMANDS
+        .iter()
+        .find(|(full, min, _)| name.len() >= *min && full.starts_with(name))
+        .map(|(_, _, command)| *command)
+}
+
+/// address at the start of `line` and the text after it
+fn address(line: &str) -> Option<(Address, &str)> {
+    let digits = line
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(line.len());
+
+    match line.chars().next()? {
+        '.' => Some((Address::Current, &line[1..])),
+        '$' => Some((Address::Last, &line[1..])),
+        _ if digits > 0 => {
+            let n = line[..digits].parse().ok()?;
+            Some((Address::Line(n), &line[digits..]))
+        }
+        _ => None,
+    }
+}
+
+/// range at the start of `line` and the text after it
+fn range(line: &str) -> (Option<LineRange>, &str) {
+    if let Some(rest) = line.strip_prefix('%') {
+        let range = LineRange {
+            start: Address::Line(1),
+            end: Address::Last,
+        };
+        return (Some(range), rest);
+    }
+
+    let (start, rest) = match address(line) {
+        Some(found) => found,
+        None => return (None, line),
+    };
+
+    let (end, rest) = match rest.strip_prefix(',').and_then(address) {
+        Some(found) => found,
+        None => (start, rest),
+    };
+
+    (Some(LineRange { start, end }), rest)
+}
+
+/// Parse a command line, without the leading `:`
+pub fn parse(line: &str) -> Result<Parsed, String> {
+    let (range, line) = range(line.trim());
+    let line = line.trim_start();
+
+    if line.is_empty() {
+        return Ok(match range {
+            Some(range) => Parsed::Goto(range.end),
+            None => Parsed::Empty,
+        });
+    }
+
+    let end = line
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(line.len());
+    let (name, rest) = line.split_at(end);
+
+    // named commands are written with a `.`, such as `window.split`
+    if rest.starts_with('.') {
+        let end = line.find(char::is_whitespace).unwrap_or(line.len());
+        let (name, args) = line.split_at(end);
+
+        return match range {
+            Some(_) => Err("No range allowed".to_string()),
+            None => Ok(Parsed::Named {
+                name: name.to_string(),
+                args: args.trim().to_string(),
+            }),
+        };
+    }
+
+    let (bang, args) = match rest.strip_prefix('!') {
+        Some(args) => (true, args),
+        None => (false, rest),
+    };
+
+    match lookup(name) {
+        Some(command) => Ok(Parsed::Command {
+            range,
+            command,
+            bang,
+            args: args.trim().to_string(),
+        }),
+        None => Err(format!("Not an editor command: {}", line)),
+    }
+}
+
+/// Lines the text before the cursor could be completed to: command names,
+/// `:set` options or paths for commands taking a file
+pub fn complete(line: &str) -> Vec<String> {
+    let (name, arg) = match line.split_once(' ') {
+        Some((name, arg)) => (name, arg.trim_start()),
+        None => {
+            return COMMANDS
+                .iter()
+                .filter(|(full, _, _)| full.starts_with(line))
+                .map(|(full, _, _)| full.to_string())
+                .collect();
+        }
+    };
+
+    let candidates = match lookup(name.trim_end_matches('!')) {
+        Some(Ex::Set) => OPTIONS
+            .iter()
+            .filter(|option| option.starts_with(arg))
+            .map(|option| option.to_string())
+            .collect(),
+        Some(Ex::Edit | Ex::Write | Ex::WriteQuit | Ex::Split | Ex::VSplit | Ex::Source) => {
+            complete_path(arg)
+        }
+        Some(Ex::Colorscheme) => available_themes()
+            .into_iter()
+            .filter(|name| name.starts_with(arg))
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    candidates
+        .into_iter()
+        .map(|candidate| format!("{} {}", name, candidate))
+        .collect()
+}
+
+/// entries of the directory of `partial` that start with its file name,
+/// directories ending in `/`
+fn complete_path(partial: &str) -> Vec<String> {
+    let (dir, prefix) = match partial.rfind('/') {
+        Some(i) => partial.split_at(i + 1),
+        None => ("", partial),
+    };
+
+    let entries = match fs::read_dir(if dir.is_empty() {
+        Path::new(".")
+    } else {
+        Path::new(dir)
+    }) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut paths: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let is_dir = entry.file_type().ok()?.is_dir();
+
+            // hidden files only when asked for
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+
+            Some(match is_dir {
+                true => format!("{}{}/", dir, name),
+                false => format!("{}{}", dir, name),
+            })
+        })
+        .collect();
+
+    paths.sort();
+    paths
+}
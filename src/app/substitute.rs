@@ -0,0 +1,247 @@
+//! `:s/pattern/replacement/flags`, replacing matches of a regex over a range
+//! of lines. The pattern uses the regex crate's syntax while the replacement
+//! is written as in vi, `&` for the whole match and `\1` to `\9` for groups.
+//! With the `c` flag each match is confirmed in turn, so the search moves
+//! through the lines one match at a time
+
+use regex::{Regex, RegexBuilder};
+use std::ops::Range;
+
+use super::buffer::Buffer;
+use crate::text::search;
+
+#[derive(Debug)]
+pub struct Substitute {
+    re: Regex,
+    /// replacement in the regex crate's `${1}` syntax
+    template: String,
+    /// replace every match on a line rather than the first
+    global: bool,
+    /// ask before each replacement
+    confirm: bool,
+    /// row and char column to search from
+    row: usize,
+    col: usize,
+    /// last row to search
+    last: usize,
+    replaced: usize,
+    /// rows with a replacement, counted once each
+    lines: usize,
+    last_replaced: Option<usize>,
+}
+
+impl Substitute {
+    /// Parse `/pattern/replacement/flags` to run over rows `first` to
+    /// `last`. Any char other than a letter, digit, space or `\` can stand
+    /// in for `/`. An empty pattern searches for `last_search` again
+    pub fn parse(
+        args: &str,
+        last_search: Option<&Regex>,
+        first: usize,
+        last: usize,
+    ) -> Result<Self, String> {
+        let delimiter = match args.chars().next() {
+            Some(c) if !c.is_alphanumeric() && !c.is_whitespace() && c != '\\' => c,
+            Some(_) => return Err("Regular expression can't be delimited by letters".into()),
+            None => return Err("Argument required".into()),
+        };
+
+        let mut parts = split(&args[delimiter.len_utf8()..], delimiter).into_iter();
+        let pattern = parts.next().unwrap_or_default();
+        let replacement = parts.next().unwrap_or_default();
+        let flags = parts.next().unwrap_or_default();
+
+        let mut builder = match (pattern.is_empty(), last_search) {
+            (true, Some(re)) => RegexBuilder::new(re.as_str()),
+            (true, None) => return Err("No previous regular expression".into()),
+            (false, _) => RegexBuilder::new(&pattern),
+        };
+
+        let (mut global, mut confirm) = (false, false);
+        for flag in flags.trim().chars() {
+            match flag {
+                'g' => global = true,
+                'c' => confirm = true,
+                'i' => {
+                    builder.case_insensitive(true);
+                }
+                'I' => {
+                    builder.case_insensitive(false);
+                }
+                _ => return Err(format!("Trailing characters: {}", flags)),
+            }
+        }
+
+        let re = builder
+            .build()
+            .map_err(|e| format!("Invalid pattern: {}", e))?;
+
+        Ok(Self {
+            re,
+            template: template(&replacement),
+            global,
+            confirm,
+            row: first,
+            col: 0,
+            last,
+            replaced: 0,
+            lines: 0,
+            last_replaced: None,
+        })
+    }
+
+    pub fn regex(&self) -> &Regex {
+        &self.re
+    }
+
+    pub fn confirm(&self) -> bool {
+        self.confirm
+    }
+
+    /// row of the last replacement made
+    pub fn last_replaced(&self) -> Option<usize> {
+        self.last_replaced
+    }
+
+    /// the next match to replace as a char range of the rope, moving the
+    /// search on to it
+    pub fn next_match(&mut self, buffer: &Buffer) -> Option<Range<usize>> {
+        while self.row <= self.last.min(buffer.last_line()) {
+            let found = search::line_matches(buffer.line_slice(self.row), &self.re)
+                .into_iter()
+                .find(|m| m.start >= self.col);
+
+            match found {
+                Some(m) => {
+                    let start = buffer.text().line_to_char(self.row);
+                    self.col = m.start;
+                    return Some(start + m.start..start + m.end);
+                }
+                None => self.next_row(),
+            }
+        }
+
+        None
+    }
+
+    /// Replace the match `found`, given by [`Substitute::next_match`], and
+    /// move the search past the replacement
+    pub fn replace(&mut self, buffer: &mut Buffer, found: Range<usize>) {
+        let line = search::line_str(buffer.line_slice(self.row)).into_owned();
+        let line_start = buffer.text().line_to_char(self.row);
+        let byte = char_to_byte(&line, found.start - line_start);
+
+        let mut replacement = String::new();
+        if let Some(captures) = self.re.captures_at(&line, byte) {
+            captures.expand(&self.template, &mut replacement);
+        }
+
+        buffer.replace(found.clone(), &replacement);
+
+        self.replaced += 1;
+        if self.last_replaced != Some(self.row) {
+            self.lines += 1;
+            self.last_replaced = Some(self.row);
+        }
+
+        let len = replacement.chars().count();
+        self.advance(found.start - line_start + len, found.is_empty());
+    }
+
+    /// move the search past the match `found` without replacing it
+    pub fn skip(&mut self, buffer: &Buffer, found: Range<usize>) {
+        let line_start = buffer.text().line_to_char(self.row);
+        self.advance(found.end - line_start, found.is_empty());
+    }
+
+    /// replace every match left
+    pub fn run(&mut self, buffer: &mut Buffer) {
+        while let Some(found) = self.next_match(buffer) {
+            self.replace(buffer, found);
+        }
+    }
+
+    /// Message once done, an error when nothing matched
+    pub fn summary(&self) -> Result<String, String> {
+        match self.replaced {
+            0 if self.confirm => Ok("0 substitutions".to_string()),
+            0 => Err(format!("Pattern not found: {}", self.re)),
+            1 => Ok("1 substitution on 1 line".to_string()),
+            n => Ok(format!(
+                "{} substitutions on {} line{}",
+                n,
+                self.lines,
+                if self.lines == 1 { "" } else { "s" }
+            )),
+        }
+    }
+
+    /// carry on from column `col`, stepping over an empty match so it is
+    /// not found again
+    fn advance(&mut self, col: usize, empty: bool) {
+        match self.global {
+            true => self.col = col + usize::from(empty),
+            false => self.next_row(),
+        }
+    }
+
+    fn next_row(&mut self) {
+        self.row += 1;
+        self.col = 0;
+    }
+}
+
+/// Split on `delimiter` where it is not escaped with `\`, dropping the
+/// escape. Other escapes are kept for the regex
+fn split(text: &str, delimiter: char) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        let part = parts.last_mut().expect("parts is never empty");
+
+        match c {
+            '\\' => match chars.next() {
+                Some(next) if next == delimiter => part.push(next),
+                Some(next) => {
+                    part.push('\\');
+                    part.push(next);
+                }
+                None => part.push('\\'),
+            },
+            c if c == delimiter => parts.push(String::new()),
+            c => part.push(c),
+        }
+    }
+
+    parts
+}
+
+/// vi replacement syntax to the regex crate's, `&` and `\0` for the match,
+/// `\1` to `\9` for groups and `\&`, `\\` for the chars themselves
+fn template(replacement: &str) -> String {
+    let mut template = String::new();
+    let mut chars = replacement.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '&' => template.push_str("${0}"),
+            '$' => template.push_str("$$"),
+            '\\' => match chars.next() {
+                Some(n @ '0'..='9') => template.push_str(&format!("${{{}}}", n)),
+                Some('$') => template.push_str("$$"),
+                Some(other) => template.push(other),
+                None => template.push('\\'),
+            },
+            c => template.push(c),
+        }
+    }
+
+    template
+}
+
+fn char_to_byte(text: &str, col: usize) -> usize {
+    text.char_indices()
+        .nth(col)
+        .map_or(text.len(), |(byte, _)| byte)
+}
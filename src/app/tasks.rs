@@ -0,0 +1,86 @@
+//! Work done away from handling keys: jobs run in the background, such as
+//! reading a file, hand their result back over a channel, and debounced
+//! jobs wait for a quiet moment, each new request pushing them back
+
+use std::time::{Duration, Instant};
+
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use crate::renderer::Backend;
+
+use super::App;
+
+/// What to do with the result of background work, run on the main loop
+pub type Done<B> = Box<dyn FnOnce(&mut App<B>) + Send>;
+
+/// Work run once its timer fires
+pub type Job<B> = Box<dyn FnOnce(&mut App<B>)>;
+
+struct Timer<B: Backend> {
+    name: &'static str,
+    at: Instant,
+    job: Job<B>,
+}
+
+pub struct Tasks<B: Backend> {
+    sender: UnboundedSender<Done<B>>,
+    /// taken by the main loop, to wait on results with other events
+    receiver: Option<UnboundedReceiver<Done<B>>>,
+    timers: Vec<Timer<B>>,
+}
+
+impl<B: Backend> Default for Tasks<B> {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        Self {
+            sender,
+            receiver: Some(receiver),
+            timers: Vec::new(),
+        }
+    }
+}
+
+impl<B: Backend> Tasks<B> {
+    /// where background work sends what to do with its result
+    pub fn sender(&self) -> UnboundedSender<Done<B>> {
+        self.sender.clone()
+    }
+
+    /// The results of background work, once
+    pub fn take_receiver(&mut self) -> Option<UnboundedReceiver<Done<B>>> {
+        self.receiver.take()
+    }
+
+    /// Run `job` after `delay`, in place of the job of the same `name` if
+    /// its time has not yet come
+    pub fn debounce(&mut self, name: &'static str, delay: Duration, job: Job<B>) {
+        self.cancel(name);
+        self.timers.push(Timer {
+            name,
+            at: Instant::now() + delay,
+            job,
+        });
+    }
+
+    pub fn cancel(&mut self, name: &'static str) {
+        self.timers.retain(|timer| timer.name != name);
+    }
+
+    /// when the next timer fires
+    pub fn deadline(&self) -> Option<Instant> {
+        self.timers.iter().map(|timer| timer.at).min()
+    }
+
+    /// Take the jobs whose time has come, in the order they fire
+    pub fn due(&mut self) -> Vec<Job<B>> {
+        let now = Instant::now();
+        let (mut due, waiting) = std::mem::take(&mut self.timers)
+            .into_iter()
+            .partition::<Vec<_>, _>(|timer| timer.at <= now);
+
+        self.timers = waiting;
+        due.sort_by_key(|timer| timer.at);
+        due.into_iter().map(|timer| timer.job).collect()
+    }
+}
@@ -0,0 +1,1095 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use crossterm::style::{Attribute, Color};
+use std::path::PathBuf;
+
+use super::buffer::{Buffer, Syntax};
+use super::events::{BufferChanged, Flow, ModeChanged};
+use super::ex::{self, Address, Ex, LineRange, Parsed};
+use super::{App, Message};
+use crate::config::{Config, Setting};
+use crate::keymap::{self, MapMode};
+use crate::modes::EditorState;
+use crate::renderer::{ColorSupport, TestBackend};
+
+mod golden;
+
+const ROWS: usize = 6;
+const COLS: usize = 20;
+
+fn app(text: &str) -> App<TestBackend> {
+    let buff = Buffer::build().with_text(text.to_string()).create();
+    let mut app = App::new(TestBackend::new(ROWS, COLS), ROWS, COLS, buff);
+
+    app.render().unwrap();
+    app
+}
+
+fn press(app: &mut App<TestBackend>, code: KeyCode, modifiers: KeyModifiers) {
+    app.handle(KeyEvent { code, modifiers });
+    app.draw().unwrap();
+}
+
+/// press the key for each char of `keys`
+fn type_keys(app: &mut App<TestBackend>, keys: &str) {
+    for c in keys.chars() {
+        press(app, KeyCode::Char(c), KeyModifiers::NONE);
+    }
+}
+
+/// type `line` on the command line and run it
+fn ex(app: &mut App<TestBackend>, line: &str) {
+    type_keys(app, line);
+    press(app, KeyCode::Enter, KeyModifiers::NONE);
+}
+
+/// a file or directory in the temp dir for the test `name`
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("pigeon-{}-{}", name, std::process::id()))
+}
+
+fn screen(app: &App<TestBackend>) -> String {
+    app.renderer.backend().screen()
+}
+
+fn cursor(app: &App<TestBackend>) -> Option<(usize, usize)> {
+    app.renderer.backend().cursor()
+}
+
+#[test]
+fn draws_buffer_and_modeline() {
+    let app = app("hello\nworld");
+
+    assert_eq!(
+        screen(&app),
+        "hello\n\
+         world\n\
+         ~\n\
+         ~\n\
+         ~\n\
+         |> Normal     1:1 <|\n"
+    );
+    assert_eq!(cursor(&app), Some((0, 0)));
+}
+
+#[test]
+fn insert_text() {
+    let mut app = app("hello\nworld");
+
+    type_keys(&mut app, "jAly");
+    assert_eq!(screen(&app).lines().nth(1), Some("worldly"));
+    assert_eq!(screen(&app).lines().last(), Some("|> Insert [+] 2:8 <|"));
+    assert_eq!(cursor(&app), Some((1, 7)));
+
+    press(&mut app, KeyCode::Esc, KeyModifiers::NONE);
+    assert_eq!(cursor(&app), Some((1, 6)));
+}
+
+#[test]
+fn delete_and_undo_line() {
+    let mut app = app("one\ntwo\nthree");
+
+    type_keys(&mut app, "jdd");
+    assert_eq!(screen(&app), "one\nthree\n~\n~\n~\n|> Normal [+] 2:1 <|\n");
+
+    type_keys(&mut app, "u");
+    assert_eq!(
+        screen(&app),
+        "one\ntwo\nthree\n~\n~\n|> Normal     2:1 <|\n"
+    );
+}
+
+#[test]
+fn huge_counts() {
+    let mut app = app("one\ntwo\nthree");
+    let max = usize::MAX.to_string();
+
+    type_keys(&mut app, &format!("j{}j", max));
+    assert_eq!(cursor(&app), Some((2, 0)));
+
+    type_keys(&mut app, &format!("k{}dd", max));
+    assert_eq!(screen(&app), "one\n~\n~\n~\n~\n|> Normal [+] 1:1 <|\n");
+
+    type_keys(&mut app, &format!("uj{}d{}d", max, max));
+    assert_eq!(screen(&app), "one\ntwo\n~\n~\n~\n|> Normal [+] 2:1 <|\n");
+
+    type_keys(&mut app, &format!("yy{}p", max));
+    let error = format!("Count too large to paste: {}", max);
+    assert_eq!(app.message, Some(Message::Error(error)));
+    type_keys(&mut app, "99999999p");
+    assert_eq!(screen(&app).lines().nth(2), Some("~"));
+
+    // word motions stop at either end of the buffer
+    let mut app = self::app("one two\nthree four");
+    type_keys(&mut app, &format!("{}w", max));
+    assert_eq!(cursor(&app), Some((1, 9)));
+    type_keys(&mut app, &format!("{}b", max));
+    assert_eq!(cursor(&app), Some((0, 0)));
+    type_keys(&mut app, &format!("{}e", max));
+    assert_eq!(cursor(&app), Some((1, 9)));
+    ex(&mut app, ":set wrap");
+    type_keys(&mut app, &format!("{}gk", max));
+    assert_eq!(app.modes.buff.get_active_buffer().get_cursor().row, 0);
+
+    // as do undo and redo once there is nothing more to undo or redo
+    type_keys(&mut app, "xx");
+    type_keys(&mut app, &format!("{}u", max));
+    assert_eq!(screen(&app).lines().next(), Some("one two"));
+    press(&mut app, KeyCode::Char('1'), KeyModifiers::NONE);
+    for _ in 0..19 {
+        press(&mut app, KeyCode::Char('9'), KeyModifiers::NONE);
+    }
+    press(&mut app, KeyCode::Char('r'), KeyModifiers::CONTROL);
+    assert_eq!(screen(&app).lines().next(), Some("one t"));
+}
+
+#[test]
+fn huge_command_counts() {
+    let mut app = app("one\ntwo\nthree");
+    let max = usize::MAX.to_string();
+    for (key, command) in [
+        ("H", "cursor.left"),
+        ("L", "cursor.right"),
+        ("J", "cursor.page_down"),
+        ("K", "cursor.page_up"),
+        ("X", "edit.delete"),
+        ("B", "edit.backspace"),
+        ("I", "edit.insert -"),
+        ("N", "window.next"),
+        ("T", "window.taller"),
+        ("W", "window.wider"),
+        ("O", "edit.newline"),
+        ("M", "buffer.next"),
+    ] {
+        ex(&mut app, &format!(":nmap {} <Cmd>{}<CR>", key, command));
+    }
+
+    type_keys(&mut app, &format!("{}L", max));
+    assert_eq!(cursor(&app), Some((2, 5)));
+    type_keys(&mut app, &format!("{}H", max));
+    assert_eq!(cursor(&app), Some((0, 0)));
+    type_keys(&mut app, &format!("{}J", max));
+    assert_eq!(cursor(&app), Some((2, 0)));
+    type_keys(&mut app, &format!("{}K", max));
+    assert_eq!(cursor(&app), Some((0, 0)));
+
+    // deleting stops at the ends of the buffer, inserting is refused or capped
+    type_keys(&mut app, &format!("l{}X", max));
+    assert_eq!(screen(&app).lines().next(), Some("o"));
+    type_keys(&mut app, &format!("u{}B", max));
+    assert_eq!(screen(&app).lines().next(), Some("ne"));
+    type_keys(&mut app, &format!("u{}I", max));
+    let error = format!("Count too large to insert: {}", max);
+    assert_eq!(app.message, Some(Message::Error(error)));
+    type_keys(&mut app, &format!("3I{}O", max));
+    let buffer = app.modes.buff.get_active_buffer();
+    assert_eq!(buffer.text().line(0), "o---\n");
+    assert_eq!(buffer.last_line(), 4098);
+    type_keys(&mut app, "uu");
+
+    // windows and buffers cycle round, and resizing takes what room there is
+    ex(&mut app, ":window.split");
+    type_keys(&mut app, &format!("{}N{}T", max, max));
+    assert_eq!(
+        screen(&app),
+        "one\n\
+         ────────────────────\n\
+         one\n\
+         two\n\
+         three\n\
+         |> Normal     1:2 <|\n"
+    );
+    ex(&mut app, ":window.vsplit");
+    type_keys(&mut app, &format!("{}W", max));
+    assert_eq!(screen(&app).lines().nth(2), Some("one               │n"));
+    ex(&mut app, ":buffer.new");
+    type_keys(&mut app, &format!("{}M", max));
+    assert_eq!(screen(&app).lines().nth(2), Some("one               │n"));
+}
+
+#[test]
+fn invalid_undo_steps() {
+    let mut app = app("text");
+
+    for step in ["é", "99999999999999999h", "1x"] {
+        ex(&mut app, &format!(":earlier {}", step));
+        let error = format!("Invalid undo step: {}", step);
+        assert_eq!(app.message, Some(Message::Error(error)));
+    }
+
+    type_keys(&mut app, "x");
+    ex(&mut app, ":later 18446744073709551615");
+    ex(&mut app, ":later 5124095576030431h");
+    assert_eq!(app.message, None);
+    assert_eq!(screen(&app).lines().next(), Some("ext"));
+}
+
+#[test]
+fn visual_selection_is_reversed() {
+    let mut app = app("select me");
+
+    type_keys(&mut app, "ve");
+    let reversed: Vec<bool> = (0..8)
+        .map(|col| {
+            let cell = app.renderer.backend().cell(0, col).unwrap();
+            cell.style.attr == Some(Attribute::Reverse)
+        })
+        .collect();
+
+    assert_eq!(reversed, [true, true, true, true, true, true, false, false]);
+    assert_eq!(screen(&app).lines().last(), Some("|> Visual     1:6 <|"));
+}
+
+#[test]
+fn view_follows_cursor() {
+    let text: Vec<String> = (1..=20).map(|n| format!("line {}", n)).collect();
+    let mut app = app(&text.join("\n"));
+
+    type_keys(&mut app, "G");
+    assert_eq!(
+        screen(&app),
+        "line 16\nline 17\nline 18\nline 19\nline 20\n|> Normal    20:1 <|\n"
+    );
+    assert_eq!(cursor(&app), Some((4, 0)));
+
+    type_keys(&mut app, "gg");
+    assert_eq!(screen(&app).lines().next(), Some("line 1"));
+    assert_eq!(cursor(&app), Some((0, 0)));
+}
+
+#[test]
+fn clears_stale_text() {
+    let mut app = app("a long line of text");
+
+    type_keys(&mut app, "d$");
+    assert_eq!(screen(&app).lines().next(), Some(""));
+}
+
+#[test]
+fn vertical_split() {
+    let mut app = app("left\nright");
+
+    press(&mut app, KeyCode::Char('w'), KeyModifiers::CONTROL);
+    type_keys(&mut app, "v");
+
+    assert_eq!(
+        screen(&app),
+        "left      │left\n\
+         right     │right\n\
+         ~         │~\n\
+         ~         │~\n\
+         ~         │~\n\
+         |> Normal     1:1 <|\n"
+    );
+    assert_eq!(cursor(&app), Some((0, 0)));
+
+    press(&mut app, KeyCode::Char('w'), KeyModifiers::CONTROL);
+    type_keys(&mut app, "l");
+    assert_eq!(cursor(&app), Some((0, 11)));
+}
+
+#[test]
+fn resize_redraws() {
+    let mut app = app("hello world");
+
+    app.resize(8, 3).unwrap();
+    app.draw().unwrap();
+    assert_eq!(screen(&app), "hello wo\n~\n|> Norma\n");
+
+    // nothing fits, but the command line still opens
+    app.resize(0, 0).unwrap();
+    type_keys(&mut app, ":set");
+    app.draw().unwrap();
+    assert_eq!(screen(&app), "");
+}
+
+#[test]
+fn soft_wrap() {
+    let mut app = app("the quick brown fox jumps\nover");
+
+    ex(&mut app, ":set wrap");
+    assert_eq!(
+        screen(&app),
+        "the quick brown fox\n↪ jumps\nover\n~\n~\n|> Normal     1:1 <|\n"
+    );
+
+    type_keys(&mut app, "gj");
+    assert_eq!(cursor(&app), Some((1, 2)));
+
+    type_keys(&mut app, "j");
+    assert_eq!(cursor(&app), Some((2, 3)));
+}
+
+#[test]
+fn command_errors_are_shown_inline() {
+    let mut app = app("text");
+
+    ex(&mut app, ":frob");
+    assert_eq!(screen(&app).lines().last(), Some("Not an editor comman"));
+    let cell = app.renderer.backend().cell(ROWS - 1, 0).unwrap();
+    assert_eq!(cell.style.fg, Some(Color::Red));
+    assert_eq!(cursor(&app), Some((0, 0)));
+
+    type_keys(&mut app, "l");
+    assert_eq!(screen(&app).lines().last(), Some("|> Normal     1:2 <|"));
+}
+
+#[test]
+fn ex_abbreviations() {
+    let command = |line| match ex::parse(line) {
+        Ok(Parsed::Command {
+            command,
+            bang,
+            args,
+            ..
+        }) => Some((command, bang, args)),
+        _ => None,
+    };
+
+    assert_eq!(command("w"), Some((Ex::Write, false, String::new())));
+    assert_eq!(command("sp"), Some((Ex::Split, false, String::new())));
+    assert_eq!(command("q!"), Some((Ex::Quit, true, String::new())));
+    assert_eq!(command("e a.txt"), Some((Ex::Edit, false, "a.txt".into())));
+    assert_eq!(command("wincmd l"), Some((Ex::Wincmd, false, "l".into())));
+    assert_eq!(
+        command("s/a/b/"),
+        Some((Ex::Substitute, false, "/a/b/".into()))
+    );
+    assert_eq!(command("x"), None);
+    assert_eq!(ex::parse(" 12 "), Ok(Parsed::Goto(Address::Line(12))));
+    assert_eq!(ex::parse("$"), Ok(Parsed::Goto(Address::Last)));
+    assert_eq!(
+        ex::parse("%s/a/b/g"),
+        Ok(Parsed::Command {
+            range: Some(LineRange {
+                start: Address::Line(1),
+                end: Address::Last,
+            }),
+            command: Ex::Substitute,
+            bang: false,
+            args: "/a/b/g".into(),
+        })
+    );
+    assert_eq!(ex::complete("se now"), ["se nowrap"]);
+}
+
+#[test]
+fn edit_and_cycle_buffers() {
+    let path = temp_path("edit");
+    std::fs::write(&path, "from disk\n").unwrap();
+
+    let mut app = app("scratch");
+    ex(&mut app, &format!(":e {}", path.display()));
+    assert_eq!(screen(&app).lines().next(), Some("from disk"));
+
+    ex(&mut app, ":bn");
+    assert_eq!(screen(&app).lines().next(), Some("scratch"));
+
+    // an open file is not read again
+    ex(&mut app, &format!(":e {}", path.display()));
+    assert_eq!(app.modes.buff.iter().count(), 2);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn final_newline_round_trip() {
+    let path = temp_path("newline");
+    std::fs::write(&path, "one\ntwo\n").unwrap();
+
+    let mut app = app("scratch");
+    ex(&mut app, &format!(":e {}", path.display()));
+    type_keys(&mut app, "Gj");
+    assert_eq!(cursor(&app), Some((1, 0)));
+    assert_eq!(app.modes.buff.get_active_buffer().last_line(), 1);
+
+    // the line break ending the last line is kept, and not added where
+    // there was none
+    ex(&mut app, ":w");
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "one\ntwo\n");
+    type_keys(&mut app, "Gdd");
+    ex(&mut app, ":w");
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "one\n");
+
+    std::fs::write(&path, "one").unwrap();
+    ex(&mut app, ":bd");
+    ex(&mut app, &format!(":e {}", path.display()));
+    ex(&mut app, ":w");
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "one");
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn save_through_symlinks_and_mixed_endings() {
+    let dir = temp_path("save");
+    let (file, link) = (dir.join("file.txt"), dir.join("link.txt"));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(&file, "one\r\ntwo\nthree\r\n").unwrap();
+    std::os::unix::fs::symlink("file.txt", &link).unwrap();
+
+    let mut app = app("scratch");
+    ex(&mut app, &format!(":e {}", link.display()));
+    assert_eq!(
+        app.message,
+        Some(Message::Info(format!(
+            "\"{}\" has mixed line endings, written as dos",
+            link.display()
+        )))
+    );
+
+    ex(&mut app, ":w");
+    assert!(std::fs::symlink_metadata(&link).unwrap().is_symlink());
+    assert_eq!(
+        std::fs::read_to_string(&file).unwrap(),
+        "one\r\ntwo\r\nthree\r\n"
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn buffer_ids_survive_deletes() {
+    let mut app = app("zero");
+
+    ex(&mut app, ":enew");
+    ex(&mut app, ":enew");
+    type_keys(&mut app, "itwo");
+    press(&mut app, KeyCode::Esc, KeyModifiers::NONE);
+
+    ex(&mut app, ":bd 1");
+    let ids: Vec<usize> = app.modes.buff.iter().map(|(id, _)| id).collect();
+    assert_eq!(ids, [0, 2]);
+
+    // the modified buffer is kept until forced
+    ex(&mut app, ":bd");
+    assert_eq!(app.modes.buff.active(), 2);
+    ex(&mut app, ":bd!");
+    assert_eq!(app.modes.buff.active(), 0);
+    assert_eq!(screen(&app).lines().next(), Some("zero"));
+    assert_eq!(app.modes.buff.get(0).unwrap().windows(), [0]);
+
+    // picking from the list
+    ex(&mut app, ":enew");
+    ex(&mut app, ":ls");
+    type_keys(&mut app, "k");
+    press(&mut app, KeyCode::Enter, KeyModifiers::NONE);
+    assert_eq!(app.modes.buff.active(), 0);
+    assert!(app.modes.buff.get(3).unwrap().windows().is_empty());
+}
+
+#[test]
+fn quit_guards_unsaved_changes() {
+    let path = temp_path("quit");
+    let mut app = app("text");
+
+    type_keys(&mut app, "x");
+    ex(&mut app, ":q");
+    assert!(!app.should_quit());
+
+    // undoing back to the saved text is not a change
+    type_keys(&mut app, "u");
+    assert!(!app.modes.buff.get_active_buffer().is_modified());
+
+    type_keys(&mut app, "x");
+    ex(&mut app, &format!(":w {}", path.display()));
+    assert!(!app.modes.buff.get_active_buffer().is_modified());
+    press(&mut app, KeyCode::Esc, KeyModifiers::NONE);
+    assert_eq!(
+        screen(&app).lines().nth(ROWS - 1),
+        Some("|> Normal     1:1 <|")
+    );
+
+    type_keys(&mut app, "x");
+    ex(&mut app, ":qa");
+    assert!(!app.should_quit());
+    ex(&mut app, ":qa!");
+    assert!(app.should_quit());
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn incremental_search() {
+    let mut app = app("one fish\ntwo fish\nred fish");
+
+    // the cursor follows the pattern as it is typed
+    type_keys(&mut app, "/tw");
+    assert_eq!(screen(&app).lines().last(), Some("/tw"));
+    assert_eq!(cursor(&app), Some((ROWS - 1, 3)));
+    assert_eq!(app.modes.buff.get_active_buffer().get_cursor().row, 1);
+
+    press(&mut app, KeyCode::Esc, KeyModifiers::NONE);
+    assert_eq!(cursor(&app), Some((0, 0)));
+
+    type_keys(&mut app, "/fi");
+    press(&mut app, KeyCode::Enter, KeyModifiers::NONE);
+    assert_eq!(cursor(&app), Some((0, 4)));
+    let bg = |app: &App<TestBackend>, col| app.renderer.backend().cell(0, col).unwrap().style.bg;
+    assert_eq!(bg(&app, 4), Some(Color::Yellow));
+    assert_eq!(bg(&app, 3), None);
+
+    type_keys(&mut app, "nn");
+    assert_eq!(cursor(&app), Some((2, 4)));
+    type_keys(&mut app, "N");
+    assert_eq!(cursor(&app), Some((1, 4)));
+    // a huge count goes round the matches without visiting each
+    type_keys(&mut app, &format!("{}n", usize::MAX));
+    assert_eq!(cursor(&app), Some((1, 4)));
+    type_keys(&mut app, "2n");
+    assert_eq!(cursor(&app), Some((0, 4)));
+    type_keys(&mut app, "4n");
+    assert_eq!(cursor(&app), Some((1, 4)));
+
+    type_keys(&mut app, "?");
+    press(&mut app, KeyCode::Enter, KeyModifiers::NONE);
+    assert_eq!(cursor(&app), Some((0, 4)));
+
+    type_keys(&mut app, "/cat");
+    press(&mut app, KeyCode::Enter, KeyModifiers::NONE);
+    assert_eq!(screen(&app).lines().last(), Some("Pattern not found: c"));
+    assert_eq!(cursor(&app), Some((0, 4)));
+}
+
+#[test]
+fn substitute_lines() {
+    let mut app = app("a-b a-b\nc-d\na-b");
+    let text = |app: &mut App<TestBackend>| app.modes.buff.get_active_buffer().text().to_string();
+
+    ex(&mut app, r":s/(\w)-(\w)/\2-\1/");
+    assert_eq!(text(&mut app), "b-a a-b\nc-d\na-b");
+
+    ex(&mut app, ":%s/-/+&+/g");
+    assert_eq!(text(&mut app), "b+-+a a+-+b\nc+-+d\na+-+b");
+    assert_eq!(screen(&app).lines().last(), Some("4 substitutions on 3"));
+    assert_eq!(cursor(&app), Some((2, 0)));
+
+    // one undo step for the whole command
+    type_keys(&mut app, "u");
+    assert_eq!(text(&mut app), "b-a a-b\nc-d\na-b");
+
+    ex(&mut app, ":2,3s/x/y/");
+    assert_eq!(screen(&app).lines().last(), Some("Pattern not found: x"));
+
+    ex(&mut app, ":%s/-/=/gc");
+    type_keys(&mut app, "yn");
+    assert_eq!(screen(&app).lines().last(), Some("replace with this? ("));
+    assert_eq!(cursor(&app), Some((1, 1)));
+    type_keys(&mut app, "a");
+    assert_eq!(text(&mut app), "b=a a-b\nc=d\na=b");
+    assert_eq!(screen(&app).lines().last(), Some("3 substitutions on 3"));
+}
+
+#[test]
+fn syntax_highlighting() {
+    let buff = Buffer::new("fn main() {}".into(), Syntax::new("rust"), None);
+    let mut app = App::new(TestBackend::new(ROWS, COLS), ROWS, COLS, buff);
+    app.render().unwrap();
+
+    let fg = |app: &App<TestBackend>, row, col| {
+        let cell = app.renderer.backend().cell(row, col).unwrap();
+        cell.style.fg
+    };
+    assert_eq!(fg(&app, 0, 0), Some(Color::Red));
+    assert_eq!(fg(&app, 0, 3), Some(Color::Blue));
+    assert_eq!(fg(&app, 0, 7), None);
+
+    // the tree follows edits, the comment is highlighted and the line it
+    // pushed down keeps its colours
+    type_keys(&mut app, "O// x");
+    press(&mut app, KeyCode::Esc, KeyModifiers::NONE);
+    assert_eq!(fg(&app, 0, 0), Some(Color::DarkGrey));
+    assert_eq!(fg(&app, 1, 0), Some(Color::Red));
+
+    type_keys(&mut app, "jwi_x");
+    press(&mut app, KeyCode::Esc, KeyModifiers::NONE);
+    assert_eq!(screen(&app).lines().nth(1), Some("fn main_x() {}"));
+    assert_eq!(fg(&app, 1, 8), Some(Color::Blue));
+    assert_eq!(fg(&app, 1, 9), None);
+
+    type_keys(&mut app, "uu");
+    assert_eq!(screen(&app).lines().next(), Some("fn main() {}"));
+    assert_eq!(fg(&app, 0, 7), None);
+    assert_eq!(fg(&app, 0, 0), Some(Color::Red));
+
+    // selecting keeps the colour of the text
+    type_keys(&mut app, "v");
+    let cell = app.renderer.backend().cell(0, 0).unwrap();
+    assert_eq!(cell.style.attr, Some(Attribute::Reverse));
+    assert_eq!(cell.style.fg, Some(Color::Red));
+}
+
+#[test]
+fn switch_colorscheme() {
+    let mut app = app("text");
+    let modeline = |app: &App<TestBackend>| {
+        let cell = app.renderer.backend().cell(ROWS - 1, 0).unwrap();
+        cell.style
+    };
+
+    assert_eq!(modeline(&app).attr, Some(Attribute::Reverse));
+
+    ex(&mut app, ":colo gruvbox");
+    let colors = app.ui.color_support();
+    let bg2 = colors.fit(Color::Rgb {
+        r: 0x50,
+        g: 0x49,
+        b: 0x45,
+    });
+    assert_eq!(modeline(&app).bg, Some(bg2));
+    // replaces the style the default theme gives, rather than adding to it
+    assert_eq!(app.ui.theme().get("ui.selection").attr, None);
+    assert_eq!(
+        app.ui.theme().get("syntax.function.method").fg,
+        Some(colors.fit(Color::Rgb {
+            r: 0xb8,
+            g: 0xbb,
+            b: 0x26
+        }))
+    );
+
+    ex(&mut app, ":colorscheme");
+    assert_eq!(screen(&app).lines().last(), Some("gruvbox"));
+
+    ex(&mut app, ":colo nope");
+    assert_eq!(screen(&app).lines().last(), Some("Cannot find color sc"));
+    assert_eq!(app.ui.theme().name(), "gruvbox");
+}
+
+#[test]
+fn colour_fallbacks() {
+    let red = Color::Rgb {
+        r: 250,
+        g: 10,
+        b: 5,
+    };
+
+    assert_eq!(ColorSupport::TrueColor.fit(red), red);
+    assert_eq!(ColorSupport::Ansi256.fit(red), Color::AnsiValue(196));
+    assert_eq!(ColorSupport::Ansi16.fit(red), Color::Red);
+    assert_eq!(
+        ColorSupport::Ansi16.fit(Color::AnsiValue(244)),
+        Color::DarkGrey
+    );
+    assert_eq!(ColorSupport::Ansi16.fit(Color::Cyan), Color::Cyan);
+}
+
+#[test]
+fn indent_options() {
+    let mut app = app("one\ntwo");
+
+    type_keys(&mut app, ">>");
+    assert_eq!(screen(&app).lines().next(), Some("    one"));
+
+    ex(&mut app, ":set sw=2");
+    type_keys(&mut app, "j>>");
+    assert_eq!(screen(&app).lines().nth(1), Some("  two"));
+
+    type_keys(&mut app, "A");
+    press(&mut app, KeyCode::Tab, KeyModifiers::NONE);
+    type_keys(&mut app, "x");
+    press(&mut app, KeyCode::Esc, KeyModifiers::NONE);
+    assert_eq!(screen(&app).lines().nth(1), Some("  two x"));
+
+    ex(&mut app, ":set noet");
+    type_keys(&mut app, "0>>");
+    assert_eq!(
+        app.modes.buff.get_active_buffer().text().line(1),
+        "\t  two x"
+    );
+
+    ex(&mut app, ":set sw=0");
+    assert_eq!(screen(&app).lines().last(), Some("Argument must be pos"));
+    ex(&mut app, ":set tabs");
+    assert_eq!(screen(&app).lines().last(), Some("Unknown option: tabs"));
+}
+
+#[test]
+fn config_file() {
+    let config = Config::parse(
+        "theme = \"gruvbox\"\n\
+         [editor]\n\
+         wrap = true\n\
+         shiftwidth = 2\n\
+         [filetype.rust]\n\
+         shiftwidth = 8\n",
+    )
+    .unwrap();
+
+    assert!(config.window.wrap);
+    assert_eq!(config.buffer_options(None, &[]).shiftwidth, 2);
+    assert_eq!(config.buffer_options(Some("rust"), &[]).shiftwidth, 8);
+    assert_eq!(
+        config
+            .buffer_options(Some("rust"), &[Setting::ShiftWidth(3)])
+            .shiftwidth,
+        3
+    );
+
+    assert_eq!(
+        Config::parse("[editor]\nwrap = 1"),
+        Err("wrap must be true or false".to_string())
+    );
+    assert_eq!(
+        Config::parse("[filetype.rust]\nwrap = true"),
+        Err("wrap can not be set for a filetype".to_string())
+    );
+    assert_eq!(
+        Config::parse("colours = 1"),
+        Err("Unknown setting: colours".to_string())
+    );
+    assert!(Config::parse("[editor\n")
+        .unwrap_err()
+        .starts_with("line 1:"));
+
+    let mut app = app("text");
+    app.configure(Ok(config));
+    app.render().unwrap();
+    assert_eq!(app.ui.theme().name(), "gruvbox");
+    assert!(app.ui.get_active_window().options().wrap);
+
+    app.configure(Err("Error in config".to_string()));
+    app.render().unwrap();
+    assert_eq!(screen(&app).lines().last(), Some("Error in config"));
+    assert_eq!(app.ui.theme().name(), "gruvbox");
+}
+
+#[test]
+fn key_notation() {
+    let keys = keymap::parse_keys("<C-w>v<lt><S-Tab><Nop><foo>");
+    assert_eq!(
+        keys[..3],
+        [
+            KeyEvent::new(KeyCode::Char('w'), KeyModifiers::CONTROL),
+            KeyEvent::new(KeyCode::Char('v'), KeyModifiers::NONE),
+            KeyEvent::new(KeyCode::Char('<'), KeyModifiers::NONE),
+        ]
+    );
+    assert_eq!(keymap::keys_name(&keys), "<C-w>v<lt><S-Tab><lt>foo>");
+    assert_eq!(keymap::parse_keys("<C-A>"), keymap::parse_keys("<C-a>"));
+    assert_eq!(keymap::parse_keys("<S-a>"), keymap::parse_keys("A"));
+}
+
+#[test]
+fn key_bindings() {
+    let mut app = app("one\ntwo");
+
+    // a prefix lists what can follow it until the binding is typed out
+    press(&mut app, KeyCode::Char('w'), KeyModifiers::CONTROL);
+    assert!(screen(&app).lines().next().unwrap().contains("<C-w>"));
+    type_keys(&mut app, "v");
+    assert!(!screen(&app).contains("<C-w>"));
+    assert!(screen(&app).lines().next().unwrap().contains('│'));
+    ex(&mut app, ":only");
+
+    ex(&mut app, ":nmap Q dd");
+    type_keys(&mut app, "Q");
+    assert_eq!(screen(&app).lines().next(), Some("two"));
+    ex(&mut app, ":map Q");
+    assert_eq!(screen(&app).lines().last(), Some("Q dd"));
+    ex(&mut app, ":unmap Q");
+    ex(&mut app, ":unmap Q");
+    assert_eq!(screen(&app).lines().last(), Some("No such mapping"));
+
+    // held back keys go on to the mode once the timeout passes
+    ex(&mut app, ":imap jk <Esc>");
+    type_keys(&mut app, "ijkx");
+    assert_eq!(screen(&app).lines().next(), Some("wo"));
+    ex(&mut app, ":set tm=0");
+    type_keys(&mut app, "ij");
+    assert_eq!(screen(&app).lines().next(), Some("wo"));
+    app.idle();
+    app.draw().unwrap();
+    assert_eq!(screen(&app).lines().next(), Some("jwo"));
+}
+
+#[test]
+fn keys_in_config_file() {
+    let config = Config::parse("[keys.normal]\nQ = \"dd\"\n[editor]\ntimeoutlen = 0").unwrap();
+    assert_eq!(
+        config.keys,
+        [(MapMode::Normal, "Q".to_string(), "dd".to_string())]
+    );
+    assert_eq!(config.timeoutlen, 0);
+    assert_eq!(
+        Config::parse("[keys.replace]\nQ = \"dd\""),
+        Err("Unknown mode: keys.replace".to_string())
+    );
+
+    let mut app = app("one\ntwo");
+    app.configure(Ok(config));
+    type_keys(&mut app, "Q");
+    assert_eq!(screen(&app).lines().next(), Some("two"));
+
+    // sourcing again drops what the last config bound and set
+    app.configure(Config::parse("[keys.normal]\n\"<C-w>v\" = \"x\""));
+    assert!(app.keymaps.binding(MapMode::Normal, "Q").is_none());
+    assert_eq!(app.keymaps.timeout(), keymap::Keymaps::default().timeout());
+    app.configure(Ok(Config::default()));
+    assert_eq!(
+        app.keymaps.binding(MapMode::Normal, "<C-w>v"),
+        Some(&keymap::Action::Command {
+            name: "window.vsplit".to_string(),
+            arg: String::new(),
+        })
+    );
+}
+
+#[test]
+fn named_commands() {
+    let mut app = app("one\ntwo\nthree");
+
+    ex(&mut app, ":cursor.down");
+    assert_eq!(cursor(&app), Some((1, 0)));
+    ex(&mut app, ":edit.insert >");
+    assert_eq!(screen(&app).lines().nth(1), Some(">two"));
+    ex(&mut app, ":cursor.sideways");
+    assert_eq!(screen(&app).lines().last(), Some("Not an editor comman"));
+
+    // commands added later are bound and counted like any other
+    app.commands
+        .register("test.count", "Show the count", |app, args| {
+            app.message = Some(Message::Info(format!("count {}", args.times())));
+            Ok(())
+        });
+    ex(&mut app, ":nmap Q <Cmd>test.count<CR>");
+    type_keys(&mut app, "3Q");
+    assert_eq!(screen(&app).lines().last(), Some("count 3"));
+    ex(&mut app, ":map Q");
+    assert_eq!(screen(&app).lines().last(), Some("Q <Cmd>test.count<CR"));
+}
+
+#[test]
+fn command_palette() {
+    let mut app = app("text");
+
+    press(&mut app, KeyCode::Char('p'), KeyModifiers::CONTROL);
+    assert!(screen(&app).contains("Commands:"));
+    type_keys(&mut app, "vspl");
+    assert!(screen(&app).contains("window.vsplit"));
+    press(&mut app, KeyCode::Enter, KeyModifiers::NONE);
+
+    assert!(!screen(&app).contains("Commands:"));
+    assert!(screen(&app).lines().next().unwrap().contains('│'));
+
+    press(&mut app, KeyCode::Char('p'), KeyModifiers::CONTROL);
+    press(&mut app, KeyCode::Esc, KeyModifiers::NONE);
+    assert!(!screen(&app).contains("Commands:"));
+    assert_eq!(app.modes.editor.state(), EditorState::Normal);
+}
+
+#[test]
+fn event_hooks() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let mut app = app("text");
+    let seen = Rc::new(RefCell::new(Vec::new()));
+
+    // hooks keep state and run by priority, then in the order subscribed
+    let log = seen.clone();
+    let mut count = 0;
+    let late = app.events.subscribe(5, move |_, change: &BufferChanged| {
+        count += 1;
+        log.borrow_mut()
+            .push(format!("late {} {}", count, change.buffer));
+        Flow::Continue
+    });
+    let log = seen.clone();
+    app.events.subscribe(-5, move |_, change: &BufferChanged| {
+        let change = &change.changes[0];
+        log.borrow_mut()
+            .push(format!("early -{} +{}", change.removed, change.inserted));
+        Flow::Continue
+    });
+    let log = seen.clone();
+    app.events.subscribe(0, move |_, mode: &ModeChanged| {
+        log.borrow_mut()
+            .push(format!("{} to {}", mode.from.label(), mode.to.label()));
+        Flow::Continue
+    });
+
+    type_keys(&mut app, "ia");
+    press(&mut app, KeyCode::Esc, KeyModifiers::NONE);
+    assert_eq!(
+        *seen.borrow(),
+        [
+            "Normal to Insert",
+            "early - +a",
+            "late 1 0",
+            "Insert to Normal"
+        ]
+    );
+
+    // a hook can stop those after it, and be unsubscribed
+    seen.borrow_mut().clear();
+    let stop = app.events.subscribe(0, |_, _: &BufferChanged| Flow::Stop);
+    type_keys(&mut app, "x");
+    assert_eq!(*seen.borrow(), ["early -a +"]);
+
+    assert!(app.events.unsubscribe(stop));
+    assert!(app.events.unsubscribe(late));
+    assert!(!app.events.unsubscribe(late));
+    seen.borrow_mut().clear();
+    type_keys(&mut app, "u");
+    assert_eq!(*seen.borrow(), ["early - +a"]);
+}
+
+#[test]
+fn autosave() {
+    let path = temp_path("autosave");
+    std::fs::write(&path, "one\n").unwrap();
+    let read = || std::fs::read_to_string(&path).unwrap();
+
+    let mut app = app("scratch");
+
+    ex(&mut app, &format!(":e {}", path.display()));
+    ex(&mut app, ":set autosave");
+
+    // written on leaving insert mode, not while typing
+    type_keys(&mut app, "Atwo");
+    assert_eq!(read(), "one\n");
+    press(&mut app, KeyCode::Esc, KeyModifiers::NONE);
+    assert_eq!(read(), "onetwo\n");
+    assert_eq!(screen(&app).lines().last(), Some("|> Normal     1:6 <|"));
+
+    // and after each change outside it
+    type_keys(&mut app, "x");
+    assert_eq!(read(), "onetw\n");
+
+    // buffers without a file are left alone
+    ex(&mut app, ":bp");
+    type_keys(&mut app, "x");
+    assert!(app.modes.buff.get_active_buffer().is_modified());
+
+    ex(&mut app, ":bn");
+    ex(&mut app, ":set noautosave");
+    type_keys(&mut app, "x");
+    assert_eq!(read(), "onetw\n");
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn background_work_and_timers() {
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    let path = temp_path("tasks");
+    std::fs::write(&path, "from disk\n").unwrap();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let mut app = app("scratch");
+    let mut results = app.results().unwrap();
+    assert!(app.results().is_none());
+
+    // files are read in the background and shown once they are in
+    runtime.block_on(async {
+        ex(&mut app, &format!(":e {}", path.display()));
+        assert_eq!(screen(&app).lines().next(), Some("scratch"));
+
+        let done = results.recv().await.unwrap();
+        app.finish(done);
+    });
+    app.draw().unwrap();
+    assert_eq!(screen(&app).lines().next(), Some("from disk"));
+    assert!(!app.redraw);
+
+    // a debounced job runs once, after the last time it was asked for
+    let runs = Rc::new(Cell::new(0));
+    for delay in [60, 0] {
+        let runs = runs.clone();
+        let job = Box::new(move |_: &mut App<TestBackend>| runs.set(runs.get() + 1));
+        app.tasks.debounce("test", Duration::from_secs(delay), job);
+    }
+    assert!(app.deadline().is_some());
+    app.idle();
+    assert_eq!(runs.get(), 1);
+    assert!(app.deadline().is_none());
+    // it changed nothing on screen
+    assert!(!app.redraw);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn draws_only_changes() {
+    let mut app = app("one");
+    let key = |app: &mut App<TestBackend>, c| {
+        app.handle(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        std::mem::take(&mut app.redraw)
+    };
+    app.draw().unwrap();
+
+    // nowhere to move, and a count waiting for its motion
+    assert!(!key(&mut app, 'h'));
+    assert!(!key(&mut app, '2'));
+    assert!(key(&mut app, 'l'));
+    assert!(key(&mut app, ':'));
+    assert!(key(&mut app, 'w'));
+}
+
+#[test]
+fn modeline_segments() {
+    let buff = Buffer::new("fn main() {\n}\n".into(), Syntax::new("rust"), None);
+    let mut app = App::new(TestBackend::new(ROWS, 50), ROWS, 50, buff);
+    let modeline = |app: &App<TestBackend>| screen(app).lines().last().unwrap().to_string();
+
+    app.configure(Config::parse(
+        r#"
+        [modeline]
+        left = ["mode", "filename", "modified"]
+        centre = ["filetype"]
+        right = ["diagnostics", "encoding", "lineending", "position", "percentage"]
+        "#,
+    ));
+    app.render().unwrap();
+    assert_eq!(
+        modeline(&app),
+        "|> Normal [No Name]    rust  utf-8 unix 1:1 33% <|"
+    );
+
+    // the syntax error shows up, pushing the centre out
+    type_keys(&mut app, "$x");
+    assert_eq!(
+        modeline(&app),
+        "|> Normal [No Name] [+] E:1 utf-8 unix 1:10 33% <|"
+    );
+
+    // narrower, the right side goes from the first segment, then the name
+    let shown = [
+        (30, "|> Normal [No Name] [+] 33% <|"),
+        (26, "|> Normal <ame] [+] 33% <|"),
+        (20, "|> Normal [+] 33% <|"),
+        (12, "|> Normal [+"),
+    ];
+    for (cols, line) in shown {
+        app.resize(cols, ROWS as u16).unwrap();
+        app.draw().unwrap();
+        assert_eq!(modeline(&app), line);
+    }
+
+    let config = Config::parse("[modeline]\nleft = [\"mode\", \"clock\"]");
+    assert_eq!(config, Err("Unknown modeline segment: clock".to_string()));
+}
+
+#[test]
+fn git_branch_in_worktree() {
+    let dir = temp_path("git");
+    let path = dir.join("tree").join("file.txt");
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::create_dir_all(dir.join("repo")).unwrap();
+    std::fs::write(dir.join("tree/.git"), "gitdir: ../repo\n").unwrap();
+    std::fs::write(dir.join("repo/HEAD"), "ref: refs/heads/topic\n").unwrap();
+    std::fs::write(&path, "text\n").unwrap();
+
+    let mut app = app("scratch");
+    app.configure(Config::parse("[modeline]\nleft = [\"branch\"]\nright = []"));
+    ex(&mut app, &format!(":e {}", path.display()));
+    assert_eq!(screen(&app).lines().last(), Some("|> topic          <|"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
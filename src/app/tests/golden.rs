@@ -0,0 +1,111 @@
+//! Replays key scripts against the app and compares the result with golden
+//! files. Each case in `tests/golden` is a `<name>.keys` script, an optional
+//! `<name>.in` with the text to start from and `<name>.out` with the
+//! expected buffer text, cursor and screen. Run with `PIGEON_BLESS=1` to
+//! write the `.out` files from the current behaviour.
+//!
+//! Scripts use the key notation of `:map`: plain chars are typed as they
+//! are and special keys are written as `<Esc>`, `<CR>`, `<BS>`, `<C-w>` and
+//! so on. Line breaks in a script are ignored, so long scripts can be split
+//! up.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::app::buffer::Buffer;
+use crate::app::App;
+use crate::keymap;
+use crate::renderer::TestBackend;
+
+const ROWS: usize = 8;
+const COLS: usize = 30;
+
+/// Replay the keys of `script` against a buffer of `text`, giving the buffer
+/// text, its cursor and the screen
+fn run(text: &str, script: &str) -> Result<String, String> {
+    // the last line break ends the last line, as when opening a file
+    let text = text.strip_suffix('\n').unwrap_or(text);
+    let buff = Buffer::build().with_text(text.to_string()).create();
+    let mut app = App::new(TestBackend::new(ROWS, COLS), ROWS, COLS, buff);
+
+    app.render().map_err(|e| e.to_string())?;
+
+    for key in keymap::parse_keys(&script.replace(['\n', '\r'], "")) {
+        app.handle(key);
+        app.draw().map_err(|e| e.to_string())?;
+    }
+
+    let buffer = app.modes.buff.get_active_buffer();
+    let text = buffer.text().to_string();
+    let cursor = *buffer.get_cursor();
+
+    let backend = app.renderer.backend();
+    let screen_cursor = match backend.cursor() {
+        Some((row, col)) => format!("{}:{}", row, col),
+        None => "hidden".to_string(),
+    };
+
+    Ok(format!(
+        "-- text\n{}\n-- cursor\n{}:{}\n-- screen cursor\n{}\n-- screen\n{}",
+        text,
+        cursor.row + 1,
+        cursor.col + 1,
+        screen_cursor,
+        backend.screen()
+    ))
+}
+
+fn cases() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+
+    let mut cases: Vec<PathBuf> = fs::read_dir(&dir)
+        .expect("could not read golden files")
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "keys"))
+        .collect();
+
+    cases.sort();
+    cases
+}
+
+#[test]
+fn golden_files() {
+    let bless = std::env::var_os("PIGEON_BLESS").is_some();
+    let mut failures = Vec::new();
+
+    for keys in cases() {
+        let name = keys.file_stem().unwrap_or_default().to_string_lossy();
+        let script = fs::read_to_string(&keys).expect("could not read key script");
+        let text = fs::read_to_string(keys.with_extension("in")).unwrap_or_default();
+        let out = keys.with_extension("out");
+
+        let actual = match run(&text, &script) {
+            Ok(actual) => actual,
+            Err(e) => {
+                failures.push(format!("{}: {}", name, e));
+                continue;
+            }
+        };
+
+        if bless {
+            fs::write(&out, &actual).expect("could not write golden file");
+            continue;
+        }
+
+        match fs::read_to_string(&out) {
+            Ok(expected) if expected == actual => (),
+            Ok(expected) => failures.push(format!(
+                "{}: output differs\n--- expected\n{}--- actual\n{}",
+                name, expected, actual
+            )),
+            Err(_) => failures.push(format!("{}: no {}", name, out.display())),
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} golden file(s) failed, run with PIGEON_BLESS=1 to update\n\n{}",
+        failures.len(),
+        failures.join("\n\n")
+    );
+}
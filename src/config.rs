@@ -0,0 +1,449 @@
+//! Settings read at startup from `config.toml` in the config directory,
+//! `$XDG_CONFIG_HOME/pigeon` or `~/.config/pigeon`:
+//!
+//! ```toml
+//! theme = "gruvbox"
+//!
+//! [log]
+//! file = "/tmp/pigeon.log"
+//! level = "info"
+//!
+//! [editor]
+//! wrap = true
+//! scrolloff = 5
+//!
+//! [filetype.rust]
+//! shiftwidth = 4
+//!
+//! [keys.normal]
+//! "<C-b>" = "<Cmd>buffer.pick<CR>"
+//! "<C-w>v" = "<Nop>"
+//!
+//! [modeline]
+//! right = ["filetype", "position", "percentage"]
+//! ```
+//!
+//! `[editor]` takes any option `:set` knows. Options kept for each buffer,
+//! such as `expandtab`, can also be set for a filetype, which wins over the
+//! editor wide value, and with `:set` for one buffer, which wins over both.
+//! `[keys.{mode}]` binds keys as `:map` does, for the `normal`, `visual`,
+//! `insert` or `command` mode. `[modeline]` picks the segments shown on its
+//! `left`, `centre` and `right`, see [`crate::renderer::Segment`]. Without a
+//! file every setting keeps its default
+
+use log::LevelFilter;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::keymap::{self, MapMode, TIMEOUT_LEN};
+use crate::renderer::{Segment, Segments};
+
+/// lines kept visible above and below the cursor
+const SCROLL_OFF: usize = 3;
+
+/// columns kept visible left and right of the cursor
+const SIDE_SCROLL_OFF: usize = 5;
+
+/// drawn at the start of rows continuing a soft wrapped line
+const WRAP_MARKER: &str = "↪ ";
+
+/// One option given a value, by `:set` or the config file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Setting {
+    Wrap(bool),
+    ScrollOff(usize),
+    SideScrollOff(usize),
+    ShowBreak(String),
+    ExpandTab(bool),
+    ShiftWidth(usize),
+    /// milliseconds to wait for the rest of a key binding
+    TimeoutLen(usize),
+    /// write buffers with a file as soon as they change outside insert mode
+    AutoSave(bool),
+}
+
+impl Setting {
+    /// Parse the argument of `:set`, `name`, `noname` or `name=value`
+    pub fn parse(arg: &str) -> Result<Self, String> {
+        let number = |value: &str| {
+            value
+                .trim()
+                .parse::<usize>()
+                .map_err(|_| format!("Number required after =: {}", arg))
+        };
+
+        let setting = match arg.split_once('=') {
+            None => match arg.trim() {
+                "wrap" => Setting::Wrap(true),
+                "nowrap" => Setting::Wrap(false),
+                "expandtab" | "et" => Setting::ExpandTab(true),
+                "noexpandtab" | "noet" => Setting::ExpandTab(false),
+                "autosave" => Setting::AutoSave(true),
+                "noautosave" => Setting::AutoSave(false),
+                "" => return Err("Argument required".to_string()),
+                name => return Err(format!("Unknown option: {}", name)),
+            },
+            Some((name, value)) => match name.trim() {
+                "scrolloff" | "so" => Setting::ScrollOff(number(value)?),
+                "sidescrolloff" | "siso" => Setting::SideScrollOff(number(value)?),
+                // trailing spaces are lost when the line is trimmed, so they
+                // are written escaped, as `showbreak=>\ `
+                "showbreak" | "sbr" => Setting::ShowBreak(value.replace("\\ ", " ")),
+                "shiftwidth" | "sw" => Setting::ShiftWidth(number(value)?),
+                "timeoutlen" | "tm" => Setting::TimeoutLen(number(value)?),
+                name => return Err(format!("Unknown option: {}", name)),
+            },
+        };
+
+        setting.validate()
+    }
+
+    /// an option set in the config file as `name = value`
+    pub fn from_toml(name: &str, value: &toml::Value) -> Result<Self, String> {
+        let boolean = || {
+            value
+                .as_bool()
+                .ok_or_else(|| format!("{} must be true or false", name))
+        };
+        let number = || {
+            value
+                .as_integer()
+                .and_then(|n| usize::try_from(n).ok())
+                .ok_or_else(|| format!("{} must be a number of at least 0", name))
+        };
+
+        let setting = match name {
+            "wrap" => Setting::Wrap(boolean()?),
+            "scrolloff" => Setting::ScrollOff(number()?),
+            "sidescrolloff" => Setting::SideScrollOff(number()?),
+            "showbreak" => match value.as_str() {
+                Some(marker) => Setting::ShowBreak(marker.to_string()),
+                None => return Err(format!("{} must be a string", name)),
+            },
+            "expandtab" => Setting::ExpandTab(boolean()?),
+            "shiftwidth" => Setting::ShiftWidth(number()?),
+            "timeoutlen" => Setting::TimeoutLen(number()?),
+            "autosave" => Setting::AutoSave(boolean()?),
+            _ => return Err(format!("Unknown option: {}", name)),
+        };
+
+        setting.validate()
+    }
+
+    fn validate(self) -> Result<Self, String> {
+        match self {
+            Setting::ShiftWidth(0) => Err("Argument must be positive: shiftwidth".to_string()),
+            setting => Ok(setting),
+        }
+    }
+
+    /// kept for each buffer rather than each window
+    pub fn is_buffer_local(&self) -> bool {
+        matches!(self, Setting::ExpandTab(_) | Setting::ShiftWidth(_))
+    }
+}
+
+/// Options kept for each window
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WindowOptions {
+    pub wrap: bool,
+    pub scrolloff: usize,
+    pub sidescrolloff: usize,
+    pub showbreak: String,
+}
+
+impl Default for WindowOptions {
+    fn default() -> Self {
+        Self {
+            wrap: false,
+            scrolloff: SCROLL_OFF,
+            sidescrolloff: SIDE_SCROLL_OFF,
+            showbreak: WRAP_MARKER.to_string(),
+        }
+    }
+}
+
+impl WindowOptions {
+    /// take the value of `setting`, when it is a window option
+    pub fn set(&mut self, setting: &Setting) {
+        match setting {
+            Setting::Wrap(wrap) => self.wrap = *wrap,
+            Setting::ScrollOff(lines) => self.scrolloff = *lines,
+            Setting::SideScrollOff(cols) => self.sidescrolloff = *cols,
+            Setting::ShowBreak(marker) => self.showbreak = marker.clone(),
+            _ => (),
+        }
+    }
+}
+
+/// Options kept for each buffer
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BufferOptions {
+    /// indent with spaces rather than tabs
+    pub expandtab: bool,
+    /// columns of each level of indent
+    pub shiftwidth: usize,
+}
+
+impl Default for BufferOptions {
+    fn default() -> Self {
+        Self {
+            expandtab: true,
+            shiftwidth: 4,
+        }
+    }
+}
+
+impl BufferOptions {
+    /// take the value of `setting`, when it is a buffer option
+    pub fn set(&mut self, setting: &Setting) {
+        match setting {
+            Setting::ExpandTab(expand) => self.expandtab = *expand,
+            Setting::ShiftWidth(cols) => self.shiftwidth = *cols,
+            _ => (),
+        }
+    }
+
+    /// text making up one level of indent
+    pub fn indent(&self) -> String {
+        match self.expandtab {
+            true => " ".repeat(self.shiftwidth),
+            false => "\t".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogSettings {
+    pub file: PathBuf,
+    pub level: LevelFilter,
+}
+
+impl Default for LogSettings {
+    fn default() -> Self {
+        Self {
+            file: PathBuf::from("log.txt"),
+            level: LevelFilter::Debug,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    pub theme: String,
+    pub log: LogSettings,
+    /// milliseconds to wait for the rest of a key binding
+    pub timeoutlen: usize,
+    pub autosave: bool,
+    /// key bindings, the mode and the keys bound to others
+    pub keys: Vec<(MapMode, String, String)>,
+    pub modeline: Segments,
+    /// options new windows start with
+    pub window: WindowOptions,
+    pub buffer: BufferOptions,
+    /// buffer options by filetype, over those in `buffer`
+    pub filetypes: HashMap<String, Vec<Setting>>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            theme: "default".to_string(),
+            log: Default::default(),
+            timeoutlen: TIMEOUT_LEN,
+            autosave: false,
+            keys: Default::default(),
+            modeline: Default::default(),
+            window: Default::default(),
+            buffer: Default::default(),
+            filetypes: Default::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Read `config.toml` in the config directory, all defaults when there
+    /// is none
+    pub fn load() -> Result<Self, String> {
+        let path = match config_dir() {
+            Some(dir) => dir.join("config.toml"),
+            None => return Ok(Self::default()),
+        };
+
+        match Self::read(&path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            result => result.map_err(|e| format!("Could not read {}: {}", path.display(), e))?,
+        }
+    }
+
+    /// Read the config file at `path`. The outer error is for the file not
+    /// being readable, the inner one for what it says being wrong
+    pub fn read(path: &Path) -> io::Result<Result<Self, String>> {
+        let text = fs::read_to_string(path)?;
+
+        Ok(Self::parse(&text).map_err(|e| format!("Error in {}: {}", path.display(), e)))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let table: toml::Table = text.parse().map_err(|e: toml::de::Error| {
+            let line = e
+                .span()
+                .map_or(0, |span| text[..span.start].matches('\n').count() + 1);
+            format!("line {}: {}", line, e.message())
+        })?;
+
+        let mut config = Self::default();
+
+        for (key, value) in &table {
+            match (key.as_str(), value) {
+                ("theme", toml::Value::String(name)) => config.theme = name.clone(),
+                ("log", toml::Value::Table(log)) => config.log = parse_log(log)?,
+                ("editor", toml::Value::Table(editor)) => {
+                    for (name, value) in editor {
+                        let setting = Setting::from_toml(name, value)?;
+                        config.window.set(&setting);
+                        config.buffer.set(&setting);
+                        match setting {
+                            Setting::TimeoutLen(ms) => config.timeoutlen = ms,
+                            Setting::AutoSave(on) => config.autosave = on,
+                            _ => (),
+                        }
+                    }
+                }
+                ("filetype", toml::Value::Table(filetypes)) => {
+                    for (filetype, value) in filetypes {
+                        let settings = parse_filetype(filetype, value)?;
+                        config.filetypes.insert(filetype.clone(), settings);
+                    }
+                }
+                ("keys", toml::Value::Table(keys)) => config.keys = parse_keys(keys)?,
+                ("modeline", toml::Value::Table(modeline)) => {
+                    config.modeline = parse_modeline(modeline)?
+                }
+                ("theme", _) => return Err("theme must be a theme name".to_string()),
+                ("log" | "editor" | "filetype" | "keys" | "modeline", _) => {
+                    return Err(format!("{} must be a table", key))
+                }
+                _ => return Err(format!("Unknown setting: {}", key)),
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// options of a buffer of `filetype`, with `local` set for it alone
+    pub fn buffer_options(&self, filetype: Option<&str>, local: &[Setting]) -> BufferOptions {
+        let mut options = self.buffer.clone();
+        let for_filetype = filetype.and_then(|filetype| self.filetypes.get(filetype));
+
+        for setting in for_filetype.into_iter().flatten().chain(local) {
+            options.set(setting);
+        }
+
+        options
+    }
+}
+
+fn parse_log(log: &toml::Table) -> Result<LogSettings, String> {
+    let mut settings = LogSettings::default();
+
+    for (key, value) in log {
+        match (key.as_str(), value) {
+            ("file", toml::Value::String(file)) => settings.file = PathBuf::from(file),
+            ("level", toml::Value::String(level)) => {
+                settings.level = level
+                    .parse()
+                    .map_err(|_| format!("Unknown log level: {}", level))?;
+            }
+            ("file" | "level", _) => return Err(format!("log.{} must be a string", key)),
+            _ => return Err(format!("Unknown setting: log.{}", key)),
+        }
+    }
+
+    Ok(settings)
+}
+
+/// `[keys.{mode}]` tables of keys and what they are bound to
+fn parse_keys(keys: &toml::Table) -> Result<Vec<(MapMode, String, String)>, String> {
+    let mut maps = Vec::new();
+
+    for (name, bindings) in keys {
+        let mode =
+            MapMode::from_name(name).ok_or_else(|| format!("Unknown mode: keys.{}", name))?;
+        let bindings = bindings
+            .as_table()
+            .ok_or_else(|| format!("keys.{} must be a table", name))?;
+
+        for (lhs, rhs) in bindings {
+            let rhs = rhs
+                .as_str()
+                .ok_or_else(|| format!("keys.{}.{} must be a string", name, lhs))?;
+
+            if keymap::parse_keys(lhs).is_empty() {
+                return Err(format!("Invalid key sequence: keys.{}.{}", name, lhs));
+            }
+            maps.push((mode, lhs.clone(), rhs.to_string()));
+        }
+    }
+
+    Ok(maps)
+}
+
+/// `[modeline]` lists of segment names for each side, those left out keep
+/// their default
+fn parse_modeline(modeline: &toml::Table) -> Result<Segments, String> {
+    let mut segments = Segments::default();
+
+    for (side, names) in modeline {
+        let list = match side.as_str() {
+            "left" => &mut segments.left,
+            "centre" => &mut segments.centre,
+            "right" => &mut segments.right,
+            _ => return Err(format!("Unknown setting: modeline.{}", side)),
+        };
+        let names = names
+            .as_array()
+            .ok_or_else(|| format!("modeline.{} must be a list", side))?;
+
+        *list = names
+            .iter()
+            .map(|name| match name.as_str() {
+                Some(name) => Segment::from_name(name)
+                    .ok_or_else(|| format!("Unknown modeline segment: {}", name)),
+                None => Err(format!("modeline.{} must be a list of names", side)),
+            })
+            .collect::<Result<_, _>>()?;
+    }
+
+    Ok(segments)
+}
+
+fn parse_filetype(filetype: &str, value: &toml::Value) -> Result<Vec<Setting>, String> {
+    let table = value
+        .as_table()
+        .ok_or_else(|| format!("filetype.{} must be a table", filetype))?;
+
+    table
+        .iter()
+        .map(|(name, value)| {
+            let setting = Setting::from_toml(name, value)?;
+            match setting.is_buffer_local() {
+                true => Ok(setting),
+                false => Err(format!("{} can not be set for a filetype", name)),
+            }
+        })
+        .collect()
+}
+
+/// `$XDG_CONFIG_HOME/pigeon`, or `~/.config/pigeon` when that is not set
+pub fn config_dir() -> Option<PathBuf> {
+    let config = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+
+    Some(config.join("pigeon"))
+}
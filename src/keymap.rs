@@ -0,0 +1,523 @@
+//! Key bindings. Each mode has a trie of key sequences, such as `gg` or
+//! `<C-w>v`, bound to an action: a named command to run or keys to handle
+//! as if typed. Keys are looked up as they are pressed. While they are the
+//! start of a longer binding they are held back until it is typed out or
+//! `timeoutlen` passes, then the longest binding they match is run. Keys
+//! bound to nothing go on to the mode as usual.
+//!
+//! Sequences are written as in vi, a char for itself and `<...>` for other
+//! keys and modifiers: `<C-w>`, `<A-x>`, `<S-Tab>`, `<CR>`, `<Esc>`,
+//! `<Space>`, `<lt>` for `<` and `<Nop>` for no keys at all. Bound to
+//! `<Cmd>name<CR>` they run the named command, see [`crate::app::commands`]
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::modes::EditorState;
+
+/// how long keys starting a longer binding are held back, in milliseconds
+pub const TIMEOUT_LEN: usize = 1000;
+
+/// Modes with their own bindings
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MapMode {
+    Normal,
+    Visual,
+    Insert,
+    /// the command line and searches
+    Command,
+}
+
+impl MapMode {
+    pub const ALL: [MapMode; 4] = [
+        MapMode::Normal,
+        MapMode::Visual,
+        MapMode::Insert,
+        MapMode::Command,
+    ];
+
+    pub fn from_state(state: EditorState) -> Self {
+        match state {
+            EditorState::Normal => MapMode::Normal,
+            EditorState::Visual(_) => MapMode::Visual,
+            EditorState::Insert => MapMode::Insert,
+            EditorState::Command | EditorState::Search(_) => MapMode::Command,
+        }
+    }
+
+    /// name of the mode in the config file
+    pub fn name(&self) -> &'static str {
+        match self {
+            MapMode::Normal => "normal",
+            MapMode::Visual => "visual",
+            MapMode::Insert => "insert",
+            MapMode::Command => "command",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|mode| mode.name() == name)
+    }
+}
+
+/// What a key sequence is bound to
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// Named command run with the argument given and the count typed
+    /// before the keys
+    Command { name: String, arg: String },
+    /// keys handled by the mode as if typed, without looking them up again
+    Keys(Vec<KeyEvent>),
+}
+
+#[derive(Clone, Debug)]
+struct Binding {
+    action: Action,
+    /// shown in the list of keys that can follow a prefix
+    desc: String,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Node {
+    binding: Option<Binding>,
+    next: HashMap<KeyEvent, Node>,
+}
+
+impl Node {
+    fn get(&self, keys: &[KeyEvent]) -> Option<&Node> {
+        keys.iter().try_fold(self, |node, key| node.next.get(key))
+    }
+
+    /// Remove the binding of `keys`, dropping nodes left with nothing below
+    /// them. Gives whether there was one
+    fn remove(&mut self, keys: &[KeyEvent]) -> bool {
+        let (key, rest) = match keys.split_first() {
+            Some(split) => split,
+            None => return self.binding.take().is_some(),
+        };
+
+        let node = match self.next.get_mut(key) {
+            Some(node) => node,
+            None => return false,
+        };
+
+        let removed = node.remove(rest);
+        if node.binding.is_none() && node.next.is_empty() {
+            self.next.remove(key);
+        }
+        removed
+    }
+}
+
+/// what to do with keys that were looked up
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Step {
+    /// the key is bound to nothing, for the mode to handle
+    Key(KeyEvent),
+    Run(Action),
+}
+
+/// The bindings of every mode and the keys typed towards one
+#[derive(Debug)]
+pub struct Keymaps {
+    maps: HashMap<MapMode, Node>,
+    /// keys held back as the start of a longer binding
+    pending: Vec<KeyEvent>,
+    timeout: Duration,
+}
+
+impl Default for Keymaps {
+    fn default() -> Self {
+        let mut keymaps = Self {
+            maps: HashMap::new(),
+            pending: Vec::new(),
+            timeout: Duration::from_millis(TIMEOUT_LEN as u64),
+        };
+
+        for mode in MapMode::ALL {
+            keymaps.command(mode, "<C-q>", "editor.quit_all");
+            keymaps.command(mode, "<C-s>", "buffer.save");
+        }
+
+        for mode in [MapMode::Normal, MapMode::Visual, MapMode::Insert] {
+            keymaps.command(mode, "<PageUp>", "cursor.page_up");
+            keymaps.command(mode, "<PageDown>", "cursor.page_down");
+        }
+
+        for mode in [MapMode::Normal, MapMode::Visual] {
+            keymaps.builtin(mode, "gg", "first line");
+            keymaps.builtin(mode, "gj", "down a screen row");
+            keymaps.builtin(mode, "g<Down>", "down a screen row");
+            keymaps.builtin(mode, "gk", "up a screen row");
+            keymaps.builtin(mode, "g<Up>", "up a screen row");
+        }
+
+        let normal = [
+            ("<C-p>", "editor.palette"),
+            ("g-", "edit.earlier"),
+            ("g+", "edit.later"),
+            ("<C-w>s", "window.split"),
+            ("<C-w>S", "window.split"),
+            ("<C-w>v", "window.vsplit"),
+            ("<C-w>c", "window.close"),
+            ("<C-w>q", "window.close"),
+            ("<C-w>o", "window.only"),
+            ("<C-w>w", "window.next"),
+            ("<C-w>W", "window.prev"),
+            ("<C-w>h", "window.left"),
+            ("<C-w><Left>", "window.left"),
+            ("<C-w>j", "window.down"),
+            ("<C-w><Down>", "window.down"),
+            ("<C-w>k", "window.up"),
+            ("<C-w><Up>", "window.up"),
+            ("<C-w>l", "window.right"),
+            ("<C-w><Right>", "window.right"),
+            ("<C-w>+", "window.taller"),
+            ("<C-w>-", "window.shorter"),
+            ("<C-w>>", "window.wider"),
+            ("<C-w><lt>", "window.narrower"),
+            ("<C-w>=", "window.equalize"),
+        ];
+
+        let insert = [
+            ("<CR>", "edit.newline"),
+            ("<BS>", "edit.backspace"),
+            ("<Del>", "edit.delete"),
+            ("<Tab>", "edit.tab"),
+            ("<Left>", "cursor.left"),
+            ("<Right>", "cursor.right"),
+            ("<Up>", "cursor.up"),
+            ("<Down>", "cursor.down"),
+            ("<Home>", "cursor.line_start"),
+            ("<End>", "cursor.line_end"),
+            ("<C-Home>", "cursor.buffer_start"),
+            ("<C-End>", "cursor.buffer_end"),
+        ];
+
+        for (keys, command) in normal {
+            keymaps.command(MapMode::Normal, keys, command);
+        }
+        for (keys, command) in insert {
+            keymaps.command(MapMode::Insert, keys, command);
+        }
+
+        keymaps
+    }
+}
+
+impl Keymaps {
+    fn command(&mut self, mode: MapMode, keys: &str, name: &str) {
+        let action = Action::Command {
+            name: name.to_string(),
+            arg: String::new(),
+        };
+        self.bind(mode, &parse_keys(keys), action, name.to_string());
+    }
+
+    /// bind keys the mode already handles to themselves, so they are known
+    /// to follow their prefix
+    fn builtin(&mut self, mode: MapMode, keys: &str, desc: &str) {
+        let keys = parse_keys(keys);
+        self.bind(mode, &keys, Action::Keys(keys.clone()), desc.to_string());
+    }
+
+    fn bind(&mut self, mode: MapMode, keys: &[KeyEvent], action: Action, desc: String) {
+        let node = keys
+            .iter()
+            .fold(self.maps.entry(mode).or_default(), |node, key| {
+                node.next.entry(*key).or_default()
+            });
+        node.binding = Some(Binding { action, desc });
+    }
+
+    /// Bind `lhs` in `mode` to `rhs`, as `:map` does. `rhs` is keys, or
+    /// `<Cmd>name arg<CR>` to run a named command
+    pub fn map(&mut self, mode: MapMode, lhs: &str, rhs: &str) -> Result<(), String> {
+        let keys = parse_keys(lhs);
+        if keys.is_empty() {
+            return Err(format!("Invalid key sequence: {}", lhs));
+        }
+
+        let command = rhs
+            .strip_prefix("<Cmd>")
+            .and_then(|command| command.strip_suffix("<CR>"));
+
+        let action = match command {
+            Some(command) => {
+                let (name, arg) = command.trim().split_once(' ').unwrap_or((command, ""));
+                Action::Command {
+                    name: name.trim().to_string(),
+                    arg: arg.trim().to_string(),
+                }
+            }
+            None => Action::Keys(parse_keys(rhs)),
+        };
+
+        self.bind(mode, &keys, action, rhs.to_string());
+        Ok(())
+    }
+
+    /// Remove the binding of `lhs` in `mode`, giving whether there was one
+    pub fn unmap(&mut self, mode: MapMode, lhs: &str) -> bool {
+        let keys = parse_keys(lhs);
+
+        match self.maps.get_mut(&mode) {
+            Some(root) if !keys.is_empty() => root.remove(&keys),
+            _ => false,
+        }
+    }
+
+    /// Put back what `lhs` is bound to in `mode` by default, removing the
+    /// binding when it has none
+    pub fn restore(&mut self, mode: MapMode, lhs: &str) {
+        let keys = parse_keys(lhs);
+        let default = Self::default()
+            .maps
+            .get(&mode)
+            .and_then(|root| root.get(&keys))
+            .and_then(|node| node.binding.clone());
+
+        match default {
+            Some(Binding { action, desc }) => self.bind(mode, &keys, action, desc),
+            None => {
+                self.unmap(mode, lhs);
+            }
+        }
+    }
+
+    /// what `lhs` is bound to in `mode`
+    pub fn binding(&self, mode: MapMode, lhs: &str) -> Option<&Action> {
+        let node = self.maps.get(&mode)?.get(&parse_keys(lhs))?;
+        node.binding.as_ref().map(|binding| &binding.action)
+    }
+
+    /// how long to wait for the rest of a binding before the keys held
+    /// back go on as they are
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    pub fn push(&mut self, key: KeyEvent) {
+        self.pending.push(normalise(key));
+    }
+
+    /// keys held back as the start of a longer binding
+    pub fn pending(&self) -> &[KeyEvent] {
+        &self.pending
+    }
+
+    /// Take what to do with the next of the pending keys in `mode`, none
+    /// while they could still be the start of a longer binding. Once
+    /// `timed_out` keys are not held back for longer bindings
+    pub fn next(&mut self, mode: MapMode, timed_out: bool) -> Option<Step> {
+        if self.pending.is_empty() {
+            return None;
+        }
+
+        let mut node = self.maps.get(&mode);
+        let mut found = None;
+
+        for (i, key) in self.pending.iter().enumerate() {
+            node = node.and_then(|node| node.next.get(key));
+
+            match node {
+                Some(Node {
+                    binding: Some(binding),
+                    ..
+                }) => found = Some((i + 1, binding.action.clone())),
+                Some(_) => (),
+                None => break,
+            }
+        }
+
+        if matches!(node, Some(node) if !node.next.is_empty()) && !timed_out {
+            return None;
+        }
+
+        match found {
+            Some((len, action)) => {
+                self.pending.drain(..len);
+                Some(Step::Run(action))
+            }
+            None => Some(Step::Key(self.pending.remove(0))),
+        }
+    }
+
+    /// Keys that can follow the pending ones in `mode` and what they do,
+    /// in order of their names
+    pub fn continuations(&self, mode: MapMode) -> Vec<(String, String)> {
+        let node = match self
+            .maps
+            .get(&mode)
+            .and_then(|root| root.get(&self.pending))
+        {
+            Some(node) => node,
+            None => return Vec::new(),
+        };
+
+        let mut next: Vec<(String, String)> = node
+            .next
+            .iter()
+            .map(|(key, node)| {
+                let desc = match &node.binding {
+                    Some(binding) if node.next.is_empty() => binding.desc.clone(),
+                    Some(binding) => format!("{} +", binding.desc),
+                    None => "+".to_string(),
+                };
+                (key_name(key), desc)
+            })
+            .collect();
+
+        next.sort();
+        next
+    }
+}
+
+/// A key as bindings are looked up by. Chars already tell whether shift
+/// was held, so it is dropped from them, and from `<S-Tab>`
+pub fn normalise(KeyEvent { code, modifiers }: KeyEvent) -> KeyEvent {
+    let modifiers = match code {
+        KeyCode::Char(_) | KeyCode::BackTab => modifiers - KeyModifiers::SHIFT,
+        _ => modifiers,
+    };
+
+    KeyEvent { code, modifiers }
+}
+
+/// Keys written in vi notation. A `<` not starting a known key name stands
+/// for itself
+pub fn parse_keys(text: &str) -> Vec<KeyEvent> {
+    let mut keys = Vec::new();
+    let mut rest = text;
+
+    while let Some(c) = rest.chars().next() {
+        let named = match c {
+            '<' => rest[1..]
+                .find('>')
+                .and_then(|end| Some((parse_name(&rest[1..end + 1])?, end + 2))),
+            _ => None,
+        };
+
+        match named {
+            Some((key, len)) => {
+                keys.extend(key);
+                rest = &rest[len..];
+            }
+            None => {
+                keys.push(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+
+    keys
+}
+
+/// The key named between `<` and `>`, such as `C-w` or `CR`. `Nop` is a
+/// name for no key
+fn parse_name(name: &str) -> Option<Option<KeyEvent>> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut name = name;
+
+    while let Some((modifier, rest)) = name.split_once('-').filter(|(_, rest)| !rest.is_empty()) {
+        modifiers |= match modifier.to_ascii_lowercase().as_str() {
+            "c" => KeyModifiers::CONTROL,
+            "a" | "m" => KeyModifiers::ALT,
+            "s" => KeyModifiers::SHIFT,
+            _ => return None,
+        };
+        name = rest;
+    }
+
+    let mut chars = name.chars();
+    let code = match (chars.next(), chars.next()) {
+        (Some(c), None) => KeyCode::Char(c),
+        _ => match name.to_ascii_lowercase().as_str() {
+            "nop" if modifiers.is_empty() => return Some(None),
+            "cr" | "enter" | "return" => KeyCode::Enter,
+            "esc" => KeyCode::Esc,
+            "tab" if modifiers.contains(KeyModifiers::SHIFT) => KeyCode::BackTab,
+            "tab" => KeyCode::Tab,
+            "bs" | "backspace" => KeyCode::Backspace,
+            "del" | "delete" => KeyCode::Delete,
+            "insert" => KeyCode::Insert,
+            "space" => KeyCode::Char(' '),
+            "lt" => KeyCode::Char('<'),
+            "bar" => KeyCode::Char('|'),
+            "bslash" => KeyCode::Char('\\'),
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            f => KeyCode::F(f.strip_prefix('f')?.parse().ok()?),
+        },
+    };
+
+    // `<S-a>` is `A`, `<C-A>` is `<C-a>` as a terminal can't tell them apart
+    let code = match code {
+        KeyCode::Char(c) if modifiers.contains(KeyModifiers::SHIFT) => {
+            KeyCode::Char(c.to_ascii_uppercase())
+        }
+        KeyCode::Char(c) if modifiers.contains(KeyModifiers::CONTROL) => {
+            KeyCode::Char(c.to_ascii_lowercase())
+        }
+        code => code,
+    };
+
+    Some(Some(normalise(KeyEvent { code, modifiers })))
+}
+
+/// `key` written in vi notation
+pub fn key_name(key: &KeyEvent) -> String {
+    let name = match key.code {
+        KeyCode::Char(' ') => "Space".to_string(),
+        KeyCode::Char('<') => "lt".to_string(),
+        KeyCode::Char(c) if key.modifiers.is_empty() => return c.to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::Enter => "CR".to_string(),
+        KeyCode::Esc => "Esc".to_string(),
+        KeyCode::Tab => "Tab".to_string(),
+        KeyCode::BackTab => "S-Tab".to_string(),
+        KeyCode::Backspace => "BS".to_string(),
+        KeyCode::Delete => "Del".to_string(),
+        KeyCode::Insert => "Insert".to_string(),
+        KeyCode::Up => "Up".to_string(),
+        KeyCode::Down => "Down".to_string(),
+        KeyCode::Left => "Left".to_string(),
+        KeyCode::Right => "Right".to_string(),
+        KeyCode::Home => "Home".to_string(),
+        KeyCode::End => "End".to_string(),
+        KeyCode::PageUp => "PageUp".to_string(),
+        KeyCode::PageDown => "PageDown".to_string(),
+        KeyCode::F(n) => format!("F{}", n),
+        KeyCode::Null => "Nul".to_string(),
+    };
+
+    let mut prefix = String::new();
+    for (modifier, letter) in [
+        (KeyModifiers::CONTROL, "C-"),
+        (KeyModifiers::ALT, "A-"),
+        (KeyModifiers::SHIFT, "S-"),
+    ] {
+        if key.modifiers.contains(modifier) {
+            prefix.push_str(letter);
+        }
+    }
+
+    format!("<{}{}>", prefix, name)
+}
+
+/// `keys` written in vi notation
+pub fn keys_name(keys: &[KeyEvent]) -> String {
+    keys.iter().map(key_name).collect()
+}
@@ -1,14 +1,30 @@
 pub use log::{debug, error, info, warn};
 use simplelog::*;
 use std::fs::File;
+use std::io;
+
+use crate::config::LogSettings;
+
+/// Setup logger writing to the file in `settings`, must be called early
+pub fn init_logger(settings: &LogSettings) -> io::Result<()> {
+    let file = File::create(&settings.file).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!(
+                "could not create log file {}: {}",
+                settings.file.display(),
+                e
+            ),
+        )
+    })?;
 
-/// Setup logger, must be called early
-pub fn init_logger() {
     CombinedLogger::init(vec![WriteLogger::new(
-        LevelFilter::Debug,
+        settings.level,
         Config::default(),
-        File::create("log.txt").unwrap(),
+        file,
     )])
     .unwrap();
-    info!("logger created and writing to: log.txt")
+    info!("logger created and writing to: {}", settings.file.display());
+
+    Ok(())
 }
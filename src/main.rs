@@ -1,52 +1,66 @@
-use std::time::Duration;
+use crossterm::event::{Event, EventStream};
+use futures::{future, StreamExt};
+use tokio::time;
 
-use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
+use crate::{app::App, config::Config, logger::*};
 
-use crate::{app::App, logger::*};
-
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    println!("Hello, world!");
-    init_logger();
+/// Wait on keys, the results of background work and timers, drawing the
+/// screen once they have been handled if anything changed
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let config = Config::load();
+    init_logger(&config.as_ref().map(|c| c.log.clone()).unwrap_or_default())?;
 
     info!("app starting");
 
-    let mut app = App::default();
-
-    loop {
-        if event::poll(Duration::from_millis(16))? {
-            match event::read()? {
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char('q'),
-                    modifiers: KeyModifiers::CONTROL,
-                }) => break,
-                Event::Key(KeyEvent { code, .. }) => app.handle(code)?,
-                _ => (),
+    let mut app = match std::env::args().nth(1) {
+        Some(file) => App::with_file(file)?,
+        None => App::default(),
+    };
+    app.configure(config);
+
+    app.draw()?;
+
+    let mut terminal = EventStream::new();
+    let mut results = app.results().ok_or("results already taken")?;
+
+    while !app.should_quit() {
+        let deadline = app.deadline();
+        let timer = async {
+            match deadline {
+                Some(at) => time::sleep_until(at.into()).await,
+                None => future::pending().await,
             }
+        };
+
+        tokio::select! {
+            event = terminal.next() => match event {
+                Some(Ok(Event::Key(key))) => app.handle(key),
+                Some(Ok(Event::Resize(cols, rows))) => app.resize(cols, rows)?,
+                Some(Ok(_)) => (),
+                Some(Err(e)) => return Err(e.into()),
+                None => break,
+            },
+            Some(done) = results.recv() => app.finish(done),
+            () = timer => app.idle(),
         }
 
-        app.idle()?;
+        app.draw()?;
     }
 
     Ok(())
 }
 
-mod text {
-    use ropey::Rope;
-    use ropey::{iter::Chunks, RopeSlice};
-    use std::fs::File;
-    use std::io::{BufReader, BufWriter};
-
-    pub fn get_file(file: &str) -> Result<Rope, std::io::Error> {
-        Rope::from_reader(BufReader::new(File::open(file)?))
-    }
-}
-
 mod app;
 
 mod renderer;
 
-mod experiments;
-
 mod modes;
 
 mod logger;
+
+mod text;
+
+mod config;
+
+mod keymap;
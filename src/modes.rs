@@ -1,10 +1,18 @@
-use crossterm::terminal;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
 
-use crate::app::AppEvent;
+use crate::app::{buffer::Buffer, events::BufferChanged};
+use crate::renderer::{Status, Window};
 
-pub struct Ctx<'a> {
-    pub modes: &'a mut Modes,
-}
+mod command_line;
+mod editing;
+mod picker;
+mod search;
+
+pub use editing::{EditorMode, EditorState};
+pub use picker::{Picked, Picker};
 
 /// All modes known to our app
 #[derive(Default, Debug)]
@@ -12,136 +20,147 @@ pub struct Modes {
     pub ui: UiMode,
     pub buff: BufferMode,
     pub modeline: ModelineMode,
+    pub editor: EditorMode,
 }
 
-#[derive(Debug)]
+/// The open buffers, by id. Ids are handed out in order and never reused,
+/// so an id stays valid while other buffers are closed
+#[derive(Debug, Default)]
 pub struct BufferMode {
-    pub buffers: Vec<Buffer>,
+    buffers: BTreeMap<usize, Buffer>,
+    next_id: usize,
     active: usize,
 }
 
 impl BufferMode {
-    // how do I emit events?
-    pub fn insert_self(&mut self, ui_mode: &mut UiMode, msg: &str, cbs: &mut Vec<AppEvent>) {
-        let active_buffer = self.get_active_buffer();
-
-        active_buffer.insert(msg);
-
-        ui_mode.update_body(active_buffer.content.clone());
+    pub fn insert_self(&mut self, window: &mut Window, msg: &str) {
+        self.edit(window, |buffer| buffer.insert(msg));
+    }
 
-        ui_mode.update_cursor(|_| {
-            Cursor::from_cursor_and_scroll_offset(&active_buffer.cursor, &active_buffer.scroll)
+    /// move the cursor of the active buffer, keeping it in view. Moving
+    /// ends the current undo step, so typing either side of the move is
+    /// undone separately
+    pub fn move_cursor<F>(&mut self, window: &mut Window, cb: F)
+    where
+        F: FnOnce(&mut Buffer),
+    {
+        self.edit(window, |buffer| {
+            buffer.commit();
+            cb(buffer);
         });
-
-        cbs.push(AppEvent::Move(4, 1));
     }
 
-    pub fn insert_newline(&mut self, ui_mode: &mut UiMode) {
-        let active_buffer = self.get_active_buffer();
-
-        active_buffer.newline();
+    /// scroll the window a page up or down, taking the cursor with it
+    pub fn page(&mut self, window: &mut Window, up: bool) {
+        let rows = window.rows().max(1);
+        let last_line = self.get_active_buffer().last_line();
 
-        ui_mode.update_body(active_buffer.content.clone());
+        window.scroll_by(if up { -(rows as isize) } else { rows as isize }, last_line);
 
-        ui_mode.update_cursor(|_| {
-            Cursor::from_cursor_and_scroll_offset(&active_buffer.cursor, &active_buffer.scroll)
+        self.move_cursor(window, |buffer| match up {
+            true => buffer.move_up(rows),
+            false => buffer.move_down(rows),
         });
     }
 
-    fn get_active_buffer(&mut self) -> &mut Buffer {
+    /// changes made to each buffer since the last call
+    pub fn text_changes(&mut self) -> Vec<BufferChanged> {
         self.buffers
-            .get_mut(self.active)
-            .expect("active buffer does not exist")
+            .iter_mut()
+            .map(|(id, buffer)| BufferChanged {
+                buffer: *id,
+                changes: buffer.take_changes(),
+            })
+            .filter(|changed| !changed.changes.is_empty())
+            .collect()
     }
 
-    pub fn backspace(&mut self, ui_mode: &mut UiMode) {
+    /// apply an edit to the active buffer and redraw the window showing it
+    pub fn edit<F>(&mut self, window: &mut Window, cb: F)
+    where
+        F: FnOnce(&mut Buffer),
+    {
+        let active = self.active;
         let active_buffer = self.get_active_buffer();
 
-        active_buffer.backspace();
+        cb(active_buffer);
 
-        ui_mode.update_body(active_buffer.content.clone());
-        // ui_mode.update_cursor(|curs| Cursor {
-        //     col: if curs.col > 1 { curs.col - 1 } else { curs.col },
-        //     ..*curs
-        // });
-        ui_mode.update_cursor(|_| {
-            Cursor::from_cursor_and_scroll_offset(&active_buffer.cursor, &active_buffer.scroll)
-        });
+        window.set_buffer(active, active_buffer);
     }
-}
 
-impl Default for BufferMode {
-    fn default() -> Self {
-        let mut first_buff = Buffer::default();
-        first_buff.insert("type away > ");
+    /// add a buffer, giving its id
+    pub fn add(&mut self, buffer: Buffer) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.buffers.insert(id, buffer);
+        id
+    }
 
-        Self {
-            buffers: vec![first_buff],
-            active: 0,
-        }
+    pub fn get(&self, id: usize) -> Option<&Buffer> {
+        self.buffers.get(&id)
     }
-}
 
-#[derive(Debug)]
-pub struct Buffer {
-    pub content: Vec<String>,
-    pub cursor: Cursor, // start simple, handle array later
-    pub scroll: Pos,
-}
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Buffer> {
+        self.buffers.get_mut(&id)
+    }
 
-impl Buffer {
-    fn insert(&mut self, content: &str) {
-        if let Some(line) = self.content.get_mut(self.cursor.row) {
-            self.cursor.col += content.len();
-            *line += content;
-        }
+    /// buffers in the order they were opened
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Buffer)> {
+        self.buffers.iter().map(|(id, buffer)| (*id, buffer))
     }
 
-    fn newline(&mut self) {
-        self.content.push("".into());
-        self.cursor.row += 1;
-        self.cursor.col = 0;
+    pub fn remove(&mut self, id: usize) -> Option<Buffer> {
+        self.buffers.remove(&id)
+    }
+
+    /// buffer `count` places after `id` in the order they were opened,
+    /// wrapping around
+    pub fn cycle(&self, id: usize, count: isize) -> usize {
+        let ids: Vec<usize> = self.buffers.keys().copied().collect();
+        let pos = ids.iter().position(|i| *i == id).unwrap_or_default();
+        let len = ids.len().max(1) as isize;
+
+        ids.get((pos as isize + count.rem_euclid(len)).rem_euclid(len) as usize)
+            .copied()
+            .unwrap_or(id)
     }
 
-    fn backspace(&mut self) {
-        // maybe need to get this to report back if it went up a line
-        if let Some(line) = self.content.get_mut(self.cursor.row) {
-            match line.pop() {
-                Some(_) => self.cursor.col -= 1,
-                None => {
-                    // remove last line and move cursor back up
-                    if self.content.len() > 1 {
-                        self.content.pop();
-                        self.cursor.row -= 1;
-
-                        if let Some(prev_line) = self.content.get(self.cursor.row) {
-                            if prev_line.len() > 1 {
-                                self.cursor.col = prev_line.len() - 1;
-                            } else {
-                                self.cursor.col = 0
-                            }
-                        }
-                    }
+    /// Point each buffer at the windows showing it, from the buffer id
+    /// shown in each window
+    pub fn link_windows(&mut self, shown: &[usize]) {
+        for (id, buffer) in self.buffers.iter_mut() {
+            for window in buffer.windows().to_vec() {
+                if shown.get(window) != Some(id) {
+                    buffer.remove_from_window(window);
                 }
             }
+
+            for (window, _) in shown.iter().enumerate().filter(|(_, b)| *b == id) {
+                buffer.open_in_window(window);
+            }
         }
     }
-}
 
-impl Default for Buffer {
-    fn default() -> Self {
-        Self {
-            content: vec!["".to_string()],
-            cursor: Default::default(),
-            scroll: Default::default(),
-        }
+    pub fn active(&self) -> usize {
+        self.active
+    }
+
+    pub fn set_active(&mut self, id: usize) {
+        self.active = id;
+    }
+
+    pub fn get_active_buffer(&mut self) -> &mut Buffer {
+        self.buffers
+            .get_mut(&self.active)
+            .expect("active buffer does not exist")
     }
 }
 
+/// Scroll offset of a view into a buffer, `y` is the first visible line
 #[derive(Copy, Clone, Debug, Default)]
 pub struct Pos {
-    x: usize,
-    y: usize,
+    pub x: usize,
+    pub y: usize,
 }
 
 impl Pos {
@@ -153,7 +172,7 @@ impl Pos {
     }
 }
 
-#[derive(Copy, Clone, Debug, Default)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
 pub struct Cursor {
     pub row: usize,
     pub col: usize,
@@ -167,54 +186,22 @@ impl Cursor {
         row.and_then(|r| col.map(|c| (r, c)))
     }
 
+    /// position of a buffer cursor on screen, given the scroll offset of the view
     pub fn from_cursor_and_scroll_offset(cursor: &Cursor, pos: &Pos) -> Self {
         Cursor {
-            row: cursor.row + pos.x,
-            col: cursor.col + pos.y,
+            row: cursor.row.saturating_sub(pos.y),
+            col: cursor.col.saturating_sub(pos.x),
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct UiMode {
-    pub headline: String,
-    pub body: Vec<String>,
-    pub modeline: String,
+    /// cursor position on screen
     pub cursor: Cursor,
 }
 
-impl Default for UiMode {
-    fn default() -> Self {
-        let (columns, rows) = terminal::size().expect("could not get terminal size");
-
-        Self {
-            headline: "-".repeat(columns.into()),
-            body: (2..rows).map(|_| "~".into()).collect(),
-            modeline: format!("|> Normal {} <|", " ".repeat((columns - 13).into())),
-            cursor: Default::default(),
-        }
-    }
-}
-
 impl UiMode {
-    pub fn for_tui(&self) -> String {
-        String::new() + &self.headline + "\n" + &self.body.join("\n") + "\n" + &self.modeline
-    }
-
-    pub fn update_body(&mut self, mut content: Vec<String>) {
-        let (columns, rows) = terminal::size().expect("could not get terminal size");
-
-        let filled_lines: u16 = content.len() as u16 + 2;
-
-        let mut empty_rows: Vec<_> = (0..(rows - filled_lines))
-            .map(|_| "~".to_string())
-            .collect();
-
-        content.append(&mut empty_rows);
-
-        self.body = content;
-    }
-
     pub fn update_cursor<F>(&mut self, cb: F)
     where
         F: FnOnce(&Cursor) -> Cursor,
@@ -223,44 +210,113 @@ impl UiMode {
     }
 }
 
+/// What the modeline shows of the active buffer, kept up to date by hooks
 #[derive(Debug)]
 pub struct ModelineMode {
-    left: String,
-    row: usize,
-    col: usize,
+    status: Status,
+    /// directory of the file the git branch was looked up for, empty for
+    /// a buffer without one
+    dir: Option<PathBuf>,
+    /// directory whose branch is to be looked up, see [`git_branch`]
+    lookup: Option<PathBuf>,
 }
 
 impl Default for ModelineMode {
     fn default() -> Self {
         Self {
-            left: "|> Normal".into(),
-            col: 0,
-            row: 0,
+            status: Status {
+                mode: EditorState::Normal.label(),
+                ..Default::default()
+            },
+            dir: None,
+            lookup: None,
         }
     }
 }
 
-// impl ModelineMode {
-//     pub fn setup() {
+impl ModelineMode {
+    pub fn status(&self) -> &Status {
+        &self.status
+    }
+
+    pub fn set_mode(&mut self, state: EditorState) {
+        self.status.mode = state.label();
+    }
+
+    pub fn set_cursor(&mut self, cursor: Cursor) {
+        self.status.cursor = cursor;
+    }
 
-//     }
-// }
+    /// take on the name, state and text of `buffer`, asking for the git
+    /// branch to be looked up again when it is in another directory
+    pub fn set_buffer(&mut self, buffer: &Buffer) {
+        let status = &mut self.status;
+
+        status.name = buffer.name();
+        status.modified = buffer.is_modified();
+        status.lines = buffer.last_line() + 1;
+        status.filetype = buffer.filetype();
+        status.encoding = buffer.encoding().name();
+        status.line_ending = buffer.line_ending().name();
+        status.errors = buffer.syntax_errors();
+
+        let dir = buffer
+            .path()
+            .and_then(Path::parent)
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+
+        if self.dir.as_ref() != Some(&dir) {
+            status.branch = None;
+            self.lookup = Some(dir.clone());
+            self.dir = Some(dir);
+        }
+    }
 
-pub fn update_modeline(ctx: &mut Ctx, e: &AppEvent) {
-    if let AppEvent::Move(x, y) = e {
-        ctx.modes.modeline.col += *y as usize;
-        ctx.modes.modeline.row += *x as usize;
+    /// directory whose git branch is wanted, once
+    pub fn take_lookup(&mut self) -> Option<PathBuf> {
+        self.lookup.take()
+    }
+
+    /// show the `branch` looked up for `dir`, unless the active buffer has
+    /// since moved to another directory
+    pub fn set_branch(&mut self, dir: &Path, branch: Option<String>) {
+        if self.dir.as_deref() == Some(dir) {
+            self.status.branch = branch;
+        }
     }
 }
 
-// impl ModelineMode {
-//     pub fn setup_hooks<'a>(&'a mut self, hooks: &'a mut Hooks) {
-//         hooks.on_type.push(&mut |txt: &str| {
-//             self.col += txt.len();
-//         })
-//     }
-// }
-
-// struct Hooks<'a> {
-//     on_type: Vec<&'a mut dyn FnOnce(&str)>,
-// }
+/// Branch checked out in the git repository holding `dir`, the current
+/// directory when empty, or the start of the commit when none is. This
+/// reads from disk so is best done in the background
+pub fn git_branch(dir: &Path) -> Option<String> {
+    let dir = match dir.as_os_str().is_empty() {
+        true => env::current_dir().ok()?,
+        false => dir.canonicalize().ok()?,
+    };
+    let head = dir
+        .ancestors()
+        .find_map(|dir| fs::read_to_string(git_dir(dir)?.join("HEAD")).ok())?;
+    let head = head.trim();
+
+    match head.strip_prefix("ref: refs/heads/") {
+        Some(branch) => Some(branch.to_string()),
+        None => head.get(..7).map(String::from),
+    }
+}
+
+/// The git directory of a repository at `dir`. In worktrees and submodules
+/// `.git` is a file giving its path as `gitdir: <path>`
+fn git_dir(dir: &Path) -> Option<PathBuf> {
+    let git = dir.join(".git");
+
+    if git.is_dir() {
+        return Some(git);
+    }
+
+    let file = fs::read_to_string(&git).ok()?;
+    let path = file.strip_prefix("gitdir:")?.trim();
+
+    Some(dir.join(path))
+}
@@ -0,0 +1,205 @@
+//! Line editing for the command and search lines, with history of entered
+//! lines and completion of the text before the cursor
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::text;
+
+/// what a key did to the command line
+#[derive(Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Editing,
+    Cancel,
+    Enter(String),
+}
+
+/// candidates for the text completion started from
+#[derive(Debug)]
+struct Completion {
+    original: String,
+    candidates: Vec<String>,
+    /// candidate shown, `None` for the original text
+    index: Option<usize>,
+}
+
+#[derive(Debug, Default)]
+pub struct CommandLine {
+    text: String,
+    /// byte index into `text`
+    cursor: usize,
+    /// entered lines, oldest first
+    history: Vec<String>,
+    /// history entry shown and the text typed before moving through history
+    browsing: Option<(usize, String)>,
+    /// gives the lines the text before the cursor could be completed to
+    complete: Option<fn(&str) -> Vec<String>>,
+    completion: Option<Completion>,
+}
+
+impl CommandLine {
+    /// a command line completing with `complete` on tab
+    pub fn with_completion(complete: fn(&str) -> Vec<String>) -> Self {
+        Self {
+            complete: Some(complete),
+            ..Default::default()
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// columns taken by the text before the cursor
+    pub fn cursor_width(&self) -> usize {
+        text::width(self.text[..self.cursor].into())
+    }
+
+    fn set_text(&mut self, text: String) {
+        self.cursor = text.len();
+        self.text = text;
+    }
+
+    fn prev_boundary(&self) -> usize {
+        self.text[..self.cursor]
+            .char_indices()
+            .next_back()
+            .map_or(0, |(i, _)| i)
+    }
+
+    fn next_boundary(&self) -> usize {
+        self.text[self.cursor..]
+            .chars()
+            .next()
+            .map_or(self.cursor, |c| self.cursor + c.len_utf8())
+    }
+
+    pub fn handle(&mut self, KeyEvent { code, modifiers }: KeyEvent) -> Outcome {
+        let ctrl = modifiers.contains(KeyModifiers::CONTROL);
+
+        if !matches!(code, KeyCode::Tab | KeyCode::BackTab) {
+            self.completion = None;
+        }
+        if !matches!(code, KeyCode::Up | KeyCode::Down) {
+            self.browsing = None;
+        }
+
+        match code {
+            KeyCode::Esc => return self.finish(Outcome::Cancel),
+            KeyCode::Enter => {
+                let line = std::mem::take(&mut self.text);
+                if !line.trim().is_empty() && self.history.last() != Some(&line) {
+                    self.history.push(line.clone());
+                }
+                return self.finish(Outcome::Enter(line));
+            }
+            KeyCode::Backspace if self.text.is_empty() => return self.finish(Outcome::Cancel),
+            KeyCode::Backspace => {
+                let start = self.prev_boundary();
+                self.text.replace_range(start..self.cursor, "");
+                self.cursor = start;
+            }
+            KeyCode::Delete => {
+                let end = self.next_boundary();
+                self.text.replace_range(self.cursor..end, "");
+            }
+            KeyCode::Left => self.cursor = self.prev_boundary(),
+            KeyCode::Right => self.cursor = self.next_boundary(),
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::Char('b') if ctrl => self.cursor = 0,
+            KeyCode::End => self.cursor = self.text.len(),
+            KeyCode::Char('e') if ctrl => self.cursor = self.text.len(),
+            KeyCode::Char('u') if ctrl => {
+                self.text.replace_range(..self.cursor, "");
+                self.cursor = 0;
+            }
+            KeyCode::Char('w') if ctrl => {
+                let before = self.text[..self.cursor].trim_end();
+                let start = before
+                    .rfind(|c: char| c.is_whitespace())
+                    .map_or(0, |i| i + 1);
+                self.text.replace_range(start..self.cursor, "");
+                self.cursor = start;
+            }
+            KeyCode::Char(c) if !ctrl => {
+                self.text.insert(self.cursor, c);
+                self.cursor += c.len_utf8();
+            }
+            KeyCode::Up => self.history_step(true),
+            KeyCode::Down => self.history_step(false),
+            KeyCode::Tab => self.complete(true),
+            KeyCode::BackTab => self.complete(false),
+            _ => (),
+        }
+
+        Outcome::Editing
+    }
+
+    fn finish(&mut self, outcome: Outcome) -> Outcome {
+        self.text.clear();
+        self.cursor = 0;
+        self.browsing = None;
+        self.completion = None;
+        outcome
+    }
+
+    /// Move to an older or newer history entry starting with the text typed
+    /// before browsing, back to that text after the newest
+    fn history_step(&mut self, older: bool) {
+        let (current, typed) = match self.browsing.take() {
+            Some(browsing) => browsing,
+            None => (self.history.len(), self.text.clone()),
+        };
+
+        let matches = |i: &usize| self.history[*i].starts_with(&typed);
+        let next = match older {
+            true => (0..current).rev().find(matches),
+            false => (current + 1..self.history.len()).find(matches),
+        };
+
+        match next {
+            Some(i) => {
+                self.set_text(self.history[i].clone());
+                self.browsing = Some((i, typed));
+            }
+            None if older => self.browsing = Some((current, typed)),
+            None => self.set_text(typed),
+        }
+    }
+
+    /// cycle through the completions of the text before the cursor
+    fn complete(&mut self, forward: bool) {
+        let complete = match self.complete {
+            Some(complete) => complete,
+            None => return,
+        };
+
+        let completion = self.completion.get_or_insert_with(|| {
+            let original = self.text[..self.cursor].to_string();
+            Completion {
+                candidates: complete(&original),
+                original,
+                index: None,
+            }
+        });
+
+        let len = completion.candidates.len();
+        if len == 0 {
+            return;
+        }
+
+        // the original text sits between the last and first candidate
+        completion.index = match (completion.index, forward) {
+            (None, true) => Some(0),
+            (None, false) => Some(len - 1),
+            (Some(i), true) if i + 1 < len => Some(i + 1),
+            (Some(i), false) if i > 0 => Some(i - 1),
+            (Some(_), _) => None,
+        };
+
+        let text = match completion.index {
+            Some(i) => completion.candidates[i].clone(),
+            None => completion.original.clone(),
+        };
+        self.set_text(text);
+    }
+}
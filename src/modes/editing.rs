@@ -0,0 +1,1041 @@
+//! Modal, vi style, editing. Which mode the editor is in is kept by a
+//! rust-fsm state machine, key presses within a mode are parsed into counts,
+//! operators and motions and applied to the active buffer
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use log::info;
+use regex::Regex;
+use rust_fsm::{StateMachine, StateMachineImpl};
+
+use crate::app::{
+    buffer::{Buffer, Selection, SelectionKind},
+    ex,
+};
+use crate::renderer::Window;
+use crate::text::{self, Wrap};
+
+use super::command_line::{CommandLine, Outcome};
+use super::search::{Search, SearchDirection};
+use super::BufferMode;
+
+/// most bytes a count can repeat the register to when pasting
+const MAX_PASTE: usize = 64 << 20;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EditorState {
+    Normal,
+    Insert,
+    Visual(SelectionKind),
+    Command,
+    Search(SearchDirection),
+}
+
+impl EditorState {
+    /// name shown in the modeline
+    pub fn label(&self) -> &'static str {
+        match self {
+            EditorState::Normal => "Normal",
+            EditorState::Insert => "Insert",
+            EditorState::Visual(SelectionKind::Char) => "Visual",
+            EditorState::Visual(SelectionKind::Line) => "V-Line",
+            EditorState::Visual(SelectionKind::Block) => "V-Block",
+            EditorState::Command => "Command",
+            EditorState::Search(_) => "Search",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EditorInput {
+    Insert,
+    Visual(SelectionKind),
+    Command,
+    Search(SearchDirection),
+    Escape,
+}
+
+#[derive(Debug)]
+pub struct EditorMachine;
+
+impl StateMachineImpl for EditorMachine {
+    type Input = EditorInput;
+
+    type State = EditorState;
+
+    type Output = ();
+
+    const INITIAL_STATE: Self::State = EditorState::Normal;
+
+    fn transition(state: &Self::State, input: &Self::Input) -> Option<Self::State> {
+        use EditorInput as I;
+        use EditorState as S;
+
+        match (state, input) {
+            (S::Normal, I::Insert) => Some(S::Insert),
+            (S::Normal, I::Visual(kind)) => Some(S::Visual(*kind)),
+            (S::Normal, I::Command) => Some(S::Command),
+            (S::Normal, I::Search(direction)) => Some(S::Search(*direction)),
+            // pressing the key of the current visual mode leaves it
+            (S::Visual(current), I::Visual(kind)) if current == kind => Some(S::Normal),
+            (S::Visual(_), I::Visual(kind)) => Some(S::Visual(*kind)),
+            (S::Visual(_), I::Insert) => Some(S::Insert),
+            (S::Visual(_), I::Command) => Some(S::Command),
+            (_, I::Escape) => Some(S::Normal),
+            _ => None,
+        }
+    }
+
+    fn output(_state: &Self::State, _input: &Self::Input) -> Option<Self::Output> {
+        None
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operator {
+    Delete,
+    Change,
+    Yank,
+    Indent,
+    Dedent,
+}
+
+impl Operator {
+    fn from_key(code: KeyCode) -> Option<Self> {
+        let op = match code {
+            KeyCode::Char('d') => Operator::Delete,
+            KeyCode::Char('c') => Operator::Change,
+            KeyCode::Char('y') => Operator::Yank,
+            KeyCode::Char('>') => Operator::Indent,
+            KeyCode::Char('<') => Operator::Dedent,
+            _ => return None,
+        };
+
+        Some(op)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    /// up and down by screen rows of wrapped lines
+    ScreenUp,
+    ScreenDown,
+    WordStart,
+    WordBack,
+    WordEnd,
+    LineStart,
+    FirstNonBlank,
+    LineEnd,
+    BufferStart,
+    BufferEnd,
+}
+
+/// how much text a motion covers when used with an operator
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MotionKind {
+    /// up to but not including the target
+    Exclusive,
+    /// up to and including the target
+    Inclusive,
+    /// every line between cursor and target
+    Linewise,
+}
+
+impl Motion {
+    fn kind(&self) -> MotionKind {
+        match self {
+            Motion::WordEnd | Motion::LineEnd => MotionKind::Inclusive,
+            Motion::Up | Motion::Down | Motion::BufferStart | Motion::BufferEnd => {
+                MotionKind::Linewise
+            }
+            _ => MotionKind::Exclusive,
+        }
+    }
+}
+
+/// text an operator acts on
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Region {
+    /// char range of the rope
+    Chars(std::ops::Range<usize>),
+    /// first and last row
+    Lines(usize, usize),
+    /// first and last row, first and last display column
+    Block(usize, usize, usize, usize),
+}
+
+/// result of parsing a key as a motion
+enum Parsed {
+    Motion(Motion),
+    /// key started a multi key motion, such as `g`
+    Pending,
+    Other,
+}
+
+/// keys typed so far towards a normal mode command
+#[derive(Default, Debug)]
+struct Pending {
+    count: Option<usize>,
+    operator: Option<(Operator, Option<usize>)>,
+    g: bool,
+}
+
+impl Pending {
+    fn push_digit(&mut self, digit: u32) {
+        let count = self.count.unwrap_or(0);
+        self.count = Some(count.saturating_mul(10).saturating_add(digit as usize));
+    }
+
+    /// counts typed before the operator and the motion multiply together
+    fn total_count(&self) -> Option<usize> {
+        match (self.operator.and_then(|(_, c)| c), self.count) {
+            (None, None) => None,
+            (a, b) => Some(a.unwrap_or(1).saturating_mul(b.unwrap_or(1))),
+        }
+    }
+}
+
+/// text last deleted or yanked
+#[derive(Default, Debug)]
+struct Register {
+    text: String,
+    linewise: bool,
+}
+
+pub struct EditorMode {
+    machine: StateMachine<EditorMachine>,
+    pending: Pending,
+    register: Register,
+    command_line: CommandLine,
+    /// line typed after `/` or `?`
+    search_line: CommandLine,
+    search: Search,
+    /// error from the last key, such as a search finding nothing
+    error: Option<String>,
+    /// wrapping of the window keys go to, for moving by screen rows
+    wrap: Option<Wrap>,
+}
+
+impl Default for EditorMode {
+    fn default() -> Self {
+        Self {
+            machine: StateMachine::new(),
+            pending: Default::default(),
+            register: Default::default(),
+            command_line: CommandLine::with_completion(ex::complete),
+            search_line: Default::default(),
+            search: Default::default(),
+            error: None,
+            wrap: None,
+        }
+    }
+}
+
+impl std::fmt::Debug for EditorMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EditorMode")
+            .field("state", self.machine.state())
+            .field("pending", &self.pending)
+            .field("register", &self.register)
+            .finish()
+    }
+}
+
+impl EditorMode {
+    pub fn state(&self) -> EditorState {
+        *self.machine.state()
+    }
+
+    /// line being typed in command or search mode and the char shown
+    /// before it
+    pub fn prompt(&self) -> Option<(char, &CommandLine)> {
+        match self.state() {
+            EditorState::Command => Some((':', &self.command_line)),
+            EditorState::Search(direction) => Some((direction.prompt(), &self.search_line)),
+            _ => None,
+        }
+    }
+
+    pub fn search(&mut self) -> &mut Search {
+        &mut self.search
+    }
+
+    /// pattern whose matches the windows show
+    pub fn highlight(&self) -> Option<&Regex> {
+        self.search.highlight()
+    }
+
+    /// error left by the last key, taking it
+    pub fn take_error(&mut self) -> Option<String> {
+        self.error.take()
+    }
+
+    /// Handle a key press against the active buffer, returns a command line
+    /// once it has been entered
+    pub fn handle(
+        &mut self,
+        key: KeyEvent,
+        buff: &mut BufferMode,
+        window: &mut Window,
+    ) -> Option<String> {
+        self.wrap = window.wrap();
+
+        match self.state() {
+            EditorState::Insert => self.insert_key(key, buff, window),
+            EditorState::Command => return self.command_key(key, buff, window),
+            EditorState::Search(direction) => self.search_key(key, direction, buff, window),
+            // a command that enters insert mode, such as `cw`, shares its
+            // undo step with the text typed afterwards
+            EditorState::Normal => buff.edit(window, |b| {
+                self.normal_key(key, b);
+                if self.state() != EditorState::Insert {
+                    b.clamp_cursor();
+                    b.commit();
+                }
+            }),
+            EditorState::Visual(kind) => buff.edit(window, |b| {
+                self.visual_key(kind, key, b);
+                if self.state() != EditorState::Insert {
+                    b.clamp_cursor();
+                    b.commit();
+                }
+            }),
+        }
+
+        None
+    }
+
+    /// Count typed before a key binding that runs a command, dropping
+    /// anything else typed towards a command in the editor
+    pub fn take_count(&mut self) -> Option<usize> {
+        std::mem::take(&mut self.pending).count
+    }
+
+    /// move to another mode, keeping the selection of the buffer in step
+    fn switch(&mut self, input: EditorInput, b: &mut Buffer) {
+        let from = self.state();
+
+        if self.machine.consume(&input).is_err() {
+            return;
+        }
+
+        self.pending = Default::default();
+
+        let to = self.state();
+        info!("mode {:?} -> {:?}", from, to);
+
+        match to {
+            EditorState::Visual(kind) => {
+                let anchor = match b.selection() {
+                    Some(selection) => selection.anchor,
+                    None => b.cursor_char(),
+                };
+                b.set_selection(Some(Selection { anchor, kind }));
+            }
+            _ => b.set_selection(None),
+        }
+
+        // like vi, leaving insert mode steps back onto the last typed char
+        if from == EditorState::Insert && to == EditorState::Normal && b.get_cursor().col > 0 {
+            b.move_left();
+        }
+    }
+
+    fn insert_key(
+        &mut self,
+        KeyEvent { code, modifiers }: KeyEvent,
+        buff: &mut BufferMode,
+        window: &mut Window,
+    ) {
+        let ctrl = modifiers.contains(KeyModifiers::CONTROL);
+
+        // other keys, such as enter or backspace, are bound to commands
+        match code {
+            KeyCode::Esc => buff.edit(window, |b| {
+                self.switch(EditorInput::Escape, b);
+                b.clamp_cursor();
+                b.commit();
+            }),
+            KeyCode::Char(k) if !ctrl => buff.insert_self(window, &k.to_string()),
+            _ => (),
+        }
+    }
+
+    fn command_key(
+        &mut self,
+        key: KeyEvent,
+        buff: &mut BufferMode,
+        window: &mut Window,
+    ) -> Option<String> {
+        let line = match self.command_line.handle(key) {
+            Outcome::Editing => return None,
+            Outcome::Cancel => None,
+            Outcome::Enter(line) => Some(line),
+        };
+
+        buff.edit(window, |b| self.switch(EditorInput::Escape, b));
+        line
+    }
+
+    /// Key typed into a `/` or `?` search, moving the cursor to the first
+    /// match as the pattern changes and back when the search is left
+    fn search_key(
+        &mut self,
+        key: KeyEvent,
+        direction: SearchDirection,
+        buff: &mut BufferMode,
+        window: &mut Window,
+    ) {
+        let from = self.search.origin();
+        let text = buff.get_active_buffer().text();
+
+        let to = match self.search_line.handle(key) {
+            Outcome::Editing => {
+                let found = self
+                    .search
+                    .preview(text, self.search_line.text(), direction);
+                let to = found.map_or(from, |found| found.start);
+                return buff.edit(window, |b| b.set_cursor_char(to));
+            }
+            Outcome::Cancel => {
+                self.search.cancel();
+                from
+            }
+            Outcome::Enter(line) => match self.search.confirm(text, &line, direction) {
+                Ok(found) => found.start,
+                Err(e) => {
+                    self.error = Some(e);
+                    from
+                }
+            },
+        };
+
+        buff.edit(window, |b| {
+            b.set_cursor_char(to);
+            self.switch(EditorInput::Escape, b);
+        });
+    }
+
+    /// `n` and `N`, to the `count`th match of the last search
+    fn repeat_search(&mut self, b: &mut Buffer, count: usize, reverse: bool) {
+        match self
+            .search
+            .repeat(b.text(), b.cursor_char(), count, reverse)
+        {
+            Ok(found) => b.set_cursor_char(found.start),
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+    fn normal_key(&mut self, key: KeyEvent, b: &mut Buffer) {
+        let KeyEvent { code, modifiers } = key;
+        let ctrl = modifiers.contains(KeyModifiers::CONTROL);
+
+        if let KeyCode::Char(c @ '0'..='9') = code {
+            if c != '0' || self.pending.count.is_some() {
+                self.pending.push_digit(c.to_digit(10).unwrap_or_default());
+                return;
+            }
+        }
+
+        match self.parse_motion(key) {
+            Parsed::Pending => return,
+            Parsed::Motion(motion) => {
+                match self.pending.operator {
+                    Some((op, _)) => {
+                        let count = self.pending.total_count();
+                        if let Some(input) = self.operate_motion(op, b, motion, count) {
+                            self.switch(input, b);
+                        }
+                    }
+                    None => self.motion(b, motion, self.pending.count, false),
+                }
+                self.pending = Default::default();
+                return;
+            }
+            Parsed::Other => (),
+        }
+
+        if let Some(op) = Operator::from_key(code) {
+            match self.pending.operator {
+                // a doubled operator, such as `dd`, acts on whole lines
+                Some((pending, _)) if pending == op => {
+                    let count = self.pending.total_count().unwrap_or(1);
+                    let row = b.get_cursor().row;
+                    let last = row.saturating_add(count - 1).min(b.last_line());
+
+                    if let Some(input) = self.apply(op, b, Region::Lines(row, last)) {
+                        self.switch(input, b);
+                    }
+                    self.pending = Default::default();
+                }
+                Some(_) => self.pending = Default::default(),
+                None => self.pending.operator = Some((op, self.pending.count.take())),
+            }
+            return;
+        }
+
+        let pending = std::mem::take(&mut self.pending);
+
+        // anything else is only valid without an operator waiting
+        if pending.operator.is_some() {
+            return;
+        }
+
+        let count = pending.count;
+        let times = count.unwrap_or(1);
+
+        match code {
+            KeyCode::Esc => (),
+            KeyCode::Char('v') if ctrl => self.switch(EditorInput::Visual(SelectionKind::Block), b),
+            KeyCode::Char('v') => self.switch(EditorInput::Visual(SelectionKind::Char), b),
+            KeyCode::Char('V') => self.switch(EditorInput::Visual(SelectionKind::Line), b),
+            KeyCode::Char(':') => self.switch(EditorInput::Command, b),
+            KeyCode::Char(c @ ('/' | '?')) => {
+                self.search.begin(b.cursor_char());
+                let direction = match c {
+                    '/' => SearchDirection::Forward,
+                    _ => SearchDirection::Backward,
+                };
+                self.switch(EditorInput::Search(direction), b);
+            }
+            KeyCode::Char('n') => self.repeat_search(b, times, false),
+            KeyCode::Char('N') => self.repeat_search(b, times, true),
+            KeyCode::Char('i') => self.switch(EditorInput::Insert, b),
+            KeyCode::Char('a') => {
+                if b.line_len(b.get_cursor().row) > 0 {
+                    b.move_right();
+                }
+                self.switch(EditorInput::Insert, b);
+            }
+            KeyCode::Char('I') => {
+                let row = b.get_cursor().row;
+                b.set_cursor_char(b.text().line_to_char(row) + b.first_non_blank(row));
+                self.switch(EditorInput::Insert, b);
+            }
+            KeyCode::Char('A') => {
+                b.line_end();
+                self.switch(EditorInput::Insert, b);
+            }
+            KeyCode::Char('o') => {
+                b.line_end();
+                b.newline();
+                self.switch(EditorInput::Insert, b);
+            }
+            KeyCode::Char('O') => {
+                b.line_start();
+                b.insert("\n");
+                b.move_up(1);
+                self.switch(EditorInput::Insert, b);
+            }
+            KeyCode::Char('x') | KeyCode::Delete => {
+                let _ = self.operate_motion(Operator::Delete, b, Motion::Right, Some(times));
+            }
+            KeyCode::Char('X') => {
+                let _ = self.operate_motion(Operator::Delete, b, Motion::Left, Some(times));
+            }
+            KeyCode::Char('D') => {
+                let _ = self.operate_motion(Operator::Delete, b, Motion::LineEnd, count);
+            }
+            KeyCode::Char('C') => {
+                if let Some(input) =
+                    self.operate_motion(Operator::Change, b, Motion::LineEnd, count)
+                {
+                    self.switch(input, b);
+                }
+            }
+            // stopping once there is nothing left, as the count may be huge
+            KeyCode::Char('u') => {
+                for _ in 0..times {
+                    if !b.undo() {
+                        break;
+                    }
+                }
+            }
+            KeyCode::Char('r') if ctrl => {
+                for _ in 0..times {
+                    if !b.redo() {
+                        break;
+                    }
+                }
+            }
+            KeyCode::Char('p') => self.paste(b, true, times),
+            KeyCode::Char('P') => self.paste(b, false, times),
+            _ => (),
+        }
+    }
+
+    fn visual_key(&mut self, kind: SelectionKind, key: KeyEvent, b: &mut Buffer) {
+        let KeyEvent { code, modifiers } = key;
+        let ctrl = modifiers.contains(KeyModifiers::CONTROL);
+
+        if let KeyCode::Char(c @ '0'..='9') = code {
+            if c != '0' || self.pending.count.is_some() {
+                self.pending.push_digit(c.to_digit(10).unwrap_or_default());
+                return;
+            }
+        }
+
+        match self.parse_motion(key) {
+            Parsed::Pending => return,
+            Parsed::Motion(motion) => {
+                let count = self.pending.count.take();
+                self.motion(b, motion, count, false);
+                return;
+            }
+            Parsed::Other => (),
+        }
+
+        self.pending = Default::default();
+
+        let op = match code {
+            KeyCode::Esc => return self.switch(EditorInput::Escape, b),
+            KeyCode::Char('v') if ctrl => {
+                return self.switch(EditorInput::Visual(SelectionKind::Block), b)
+            }
+            KeyCode::Char('v') => return self.switch(EditorInput::Visual(SelectionKind::Char), b),
+            KeyCode::Char('V') => return self.switch(EditorInput::Visual(SelectionKind::Line), b),
+            KeyCode::Char(':') => return self.switch(EditorInput::Command, b),
+            KeyCode::Char('o') => {
+                if let Some(selection) = b.selection().copied() {
+                    let cursor = b.cursor_char();
+                    b.set_cursor_char(selection.anchor);
+                    b.set_selection(Some(Selection {
+                        anchor: cursor,
+                        kind,
+                    }));
+                }
+                return;
+            }
+            KeyCode::Char('x') => Operator::Delete,
+            KeyCode::Char('s') => Operator::Change,
+            code => match Operator::from_key(code) {
+                Some(op) => op,
+                None => return,
+            },
+        };
+
+        let region = match selection_region(b) {
+            Some(region) => region,
+            None => return,
+        };
+
+        let input = self.apply(op, b, region).unwrap_or(EditorInput::Escape);
+        self.switch(input, b);
+    }
+
+    fn parse_motion(&mut self, KeyEvent { code, modifiers }: KeyEvent) -> Parsed {
+        let ctrl = modifiers.contains(KeyModifiers::CONTROL);
+
+        if std::mem::take(&mut self.pending.g) {
+            return match code {
+                KeyCode::Char('g') => Parsed::Motion(Motion::BufferStart),
+                KeyCode::Char('k') | KeyCode::Up => Parsed::Motion(Motion::ScreenUp),
+                KeyCode::Char('j') | KeyCode::Down => Parsed::Motion(Motion::ScreenDown),
+                _ => {
+                    self.pending = Default::default();
+                    Parsed::Pending
+                }
+            };
+        }
+
+        let motion = match code {
+            KeyCode::Home if ctrl => Motion::BufferStart,
+            KeyCode::End if ctrl => Motion::BufferEnd,
+            KeyCode::Char('h') | KeyCode::Left | KeyCode::Backspace => Motion::Left,
+            KeyCode::Char('l') | KeyCode::Right | KeyCode::Char(' ') => Motion::Right,
+            KeyCode::Char('k') | KeyCode::Up => Motion::Up,
+            KeyCode::Char('j') | KeyCode::Down | KeyCode::Enter => Motion::Down,
+            KeyCode::Char('w') => Motion::WordStart,
+            KeyCode::Char('b') => Motion::WordBack,
+            KeyCode::Char('e') => Motion::WordEnd,
+            KeyCode::Char('0') | KeyCode::Home => Motion::LineStart,
+            KeyCode::Char('^') => Motion::FirstNonBlank,
+            KeyCode::Char('$') | KeyCode::End => Motion::LineEnd,
+            KeyCode::Char('G') => Motion::BufferEnd,
+            KeyCode::Char('g') => {
+                self.pending.g = true;
+                return Parsed::Pending;
+            }
+            _ => return Parsed::Other,
+        };
+
+        Parsed::Motion(motion)
+    }
+
+    /// Move the cursor by a motion. `for_op` lets the cursor reach the end of
+    /// the line, as an operator needs to cover the last char
+    fn motion(&self, b: &mut Buffer, motion: Motion, count: Option<usize>, for_op: bool) {
+        let times = count.unwrap_or(1);
+
+        match motion {
+            Motion::Left => {
+                for _ in 0..times {
+                    if b.get_cursor().col == 0 {
+                        break;
+                    }
+                    b.move_left();
+                }
+            }
+            Motion::Right => {
+                for _ in 0..times {
+                    let row = b.get_cursor().row;
+                    let len = b.line_len(row);
+                    let limit = if for_op {
+                        len
+                    } else {
+                        text::prev_grapheme_boundary(b.text().line(row), len)
+                    };
+
+                    if b.get_cursor().col >= limit {
+                        break;
+                    }
+                    b.move_right();
+                }
+            }
+            Motion::Up => b.move_up(times),
+            Motion::Down => b.move_down(times),
+            Motion::ScreenUp | Motion::ScreenDown => {
+                let up = motion == Motion::ScreenUp;
+                let rows = isize::try_from(times).unwrap_or(isize::MAX);
+
+                match self.wrap {
+                    Some(wrap) if up => b.move_screen_rows(-rows, wrap),
+                    Some(wrap) => b.move_screen_rows(rows, wrap),
+                    None if up => b.move_up(times),
+                    None => b.move_down(times),
+                }
+            }
+            Motion::WordStart => {
+                let mut pos = b.cursor_char();
+                let mut step_from = pos;
+
+                for _ in 0..times {
+                    let next = text::next_word_start(b.text().slice(..), pos);
+                    if next == pos {
+                        break;
+                    }
+                    step_from = pos;
+                    pos = next;
+                }
+
+                // `dw` on the last word of a line stops at the line end
+                let row = b.text().char_to_line(step_from);
+                if for_op && b.text().char_to_line(pos) > row {
+                    pos = b.text().line_to_char(row) + b.line_len(row);
+                }
+
+                b.set_cursor_char(pos);
+            }
+            Motion::WordBack => {
+                let mut pos = b.cursor_char();
+                for _ in 0..times {
+                    let next = text::prev_word_start(b.text().slice(..), pos);
+                    if next == pos {
+                        break;
+                    }
+                    pos = next;
+                }
+                b.set_cursor_char(pos);
+            }
+            Motion::WordEnd => {
+                let mut pos = b.cursor_char();
+                for _ in 0..times {
+                    let next = text::next_word_end(b.text().slice(..), pos);
+                    if next == pos {
+                        break;
+                    }
+                    pos = next;
+                }
+                b.set_cursor_char(pos);
+            }
+            Motion::LineStart => b.line_start(),
+            Motion::FirstNonBlank => {
+                let row = b.get_cursor().row;
+                b.set_cursor_char(b.text().line_to_char(row) + b.first_non_blank(row));
+            }
+            Motion::LineEnd => {
+                b.move_down(times - 1);
+                b.line_end();
+            }
+            Motion::BufferStart | Motion::BufferEnd => {
+                let row = match (motion, count) {
+                    (_, Some(line)) => line.saturating_sub(1).min(b.last_line()),
+                    (Motion::BufferStart, None) => 0,
+                    _ => b.last_line(),
+                };
+                b.set_cursor_char(b.text().line_to_char(row) + b.first_non_blank(row));
+            }
+        }
+    }
+
+    /// apply an operator to the text between the cursor and where a motion
+    /// would take it
+    fn operate_motion(
+        &mut self,
+        op: Operator,
+        b: &mut Buffer,
+        motion: Motion,
+        count: Option<usize>,
+    ) -> Option<EditorInput> {
+        let start = b.cursor_char();
+        let start_row = b.get_cursor().row;
+
+        // `cw` on a word changes to the end of it, leaving following spaces
+        let on_word = b.text().get_char(start).is_some_and(|c| !c.is_whitespace());
+        let motion = match (op, motion) {
+            (Operator::Change, Motion::WordStart) if on_word => Motion::WordEnd,
+            _ => motion,
+        };
+
+        self.motion(b, motion, count, true);
+
+        let end = b.cursor_char();
+        let end_row = b.get_cursor().row;
+
+        let region = match motion.kind() {
+            MotionKind::Linewise => Region::Lines(start_row.min(end_row), start_row.max(end_row)),
+            MotionKind::Exclusive => Region::Chars(start.min(end)..start.max(end)),
+            MotionKind::Inclusive => {
+                let last = start.max(end);
+                let last = if last < b.text().len_chars() {
+                    text::next_grapheme_boundary(b.text().slice(..), last)
+                } else {
+                    last
+                };
+                Region::Chars(start.min(end)..last)
+            }
+        };
+
+        b.set_cursor_char(start);
+
+        if region == Region::Chars(start..start) {
+            return None;
+        }
+
+        self.apply(op, b, region)
+    }
+
+    /// run an operator over a region, returning the mode to switch to
+    fn apply(&mut self, op: Operator, b: &mut Buffer, region: Region) -> Option<EditorInput> {
+        match op {
+            Operator::Yank => {
+                self.register = yank(b, &region);
+                match region {
+                    Region::Chars(range) => b.set_cursor_char(range.start),
+                    Region::Lines(first, _) | Region::Block(first, _, _, _) => {
+                        let row = b.get_cursor().row;
+                        if row > first {
+                            b.move_up(row - first);
+                        }
+                    }
+                }
+                None
+            }
+            Operator::Delete => {
+                self.register = yank(b, &region);
+                delete(b, &region);
+                Some(EditorInput::Escape)
+            }
+            Operator::Change => {
+                self.register = yank(b, &region);
+
+                match region {
+                    // keep an empty line to type into
+                    Region::Lines(first, last) => {
+                        let start = b.text().line_to_char(first);
+                        let end = b.text().line_to_char(last) + b.line_len(last);
+                        b.remove(start..end);
+                        b.set_cursor_char(start);
+                    }
+                    region => delete(b, &region),
+                }
+
+                Some(EditorInput::Insert)
+            }
+            Operator::Indent | Operator::Dedent => {
+                let (first, last) = match region {
+                    Region::Chars(range) => (
+                        b.text().char_to_line(range.start),
+                        b.text()
+                            .char_to_line(range.end.saturating_sub(1).max(range.start)),
+                    ),
+                    Region::Lines(first, last) | Region::Block(first, last, _, _) => (first, last),
+                };
+
+                for row in first..=last {
+                    let start = b.text().line_to_char(row);
+
+                    if op == Operator::Indent {
+                        if b.line_len(row) > 0 {
+                            let indent = b.options().indent();
+                            b.insert_at(start, &indent);
+                        }
+                    } else {
+                        let line = b.line_text(row);
+                        let remove = match line.strip_prefix('\t') {
+                            Some(_) => 1,
+                            None => line
+                                .chars()
+                                .take(b.options().shiftwidth)
+                                .take_while(|c| *c == ' ')
+                                .count(),
+                        };
+                        b.remove(start..start + remove);
+                    }
+                }
+
+                b.set_cursor_char(b.text().line_to_char(first) + b.first_non_blank(first));
+                Some(EditorInput::Escape)
+            }
+        }
+    }
+
+    fn paste(&mut self, b: &mut Buffer, after: bool, times: usize) {
+        if self.register.text.is_empty() {
+            return;
+        }
+
+        let len = self.register.text.len();
+        let content = match len.checked_mul(times).filter(|size| *size <= MAX_PASTE) {
+            Some(_) => self.register.text.repeat(times),
+            None => {
+                self.error = Some(format!("Count too large to paste: {}", times));
+                return;
+            }
+        };
+
+        if self.register.linewise {
+            let row = b.get_cursor().row + after as usize;
+
+            if row >= b.text().len_lines() {
+                // below a last line that has no line break of its own
+                let end = b.text().len_chars();
+                b.insert_at(end, &format!("\n{}", content.trim_end_matches('\n')));
+            } else {
+                b.insert_at(b.text().line_to_char(row), &content);
+            }
+
+            b.set_cursor_char(b.text().line_to_char(row) + b.first_non_blank(row));
+        } else {
+            let pos = b.cursor_char();
+            let at = if after && b.line_len(b.get_cursor().row) > 0 {
+                text::next_grapheme_boundary(b.text().slice(..), pos)
+            } else {
+                pos
+            };
+
+            b.insert_at(at, &content);
+
+            let end = at + content.chars().count();
+            b.set_cursor_char(text::prev_grapheme_boundary(b.text().slice(..), end));
+        }
+    }
+}
+
+/// region covered by the buffer's visual selection
+fn selection_region(b: &Buffer) -> Option<Region> {
+    let Selection { anchor, kind } = *b.selection()?;
+    let cursor = b.cursor_char();
+
+    let anchor_row = b.text().char_to_line(anchor);
+    let row = b.get_cursor().row;
+    let (first, last) = (anchor_row.min(row), anchor_row.max(row));
+
+    let region = match kind {
+        SelectionKind::Line => Region::Lines(first, last),
+        SelectionKind::Char => {
+            let end = anchor.max(cursor);
+            let end = if end < b.text().len_chars() {
+                text::next_grapheme_boundary(b.text().slice(..), end)
+            } else {
+                end
+            };
+            Region::Chars(anchor.min(cursor)..end)
+        }
+        SelectionKind::Block => {
+            let anchor_width = b.width_at(anchor_row, anchor - b.text().line_to_char(anchor_row));
+            let cursor_width = b.cursor_width();
+            Region::Block(
+                first,
+                last,
+                anchor_width.min(cursor_width),
+                anchor_width.max(cursor_width),
+            )
+        }
+    };
+
+    Some(region)
+}
+
+/// char range of whole lines, taking the line break before them when they
+/// run to the end of the buffer so no empty line is left behind
+fn lines_range(b: &Buffer, first: usize, last: usize) -> std::ops::Range<usize> {
+    let text = b.text();
+    let start = text.line_to_char(first);
+
+    if last + 1 < text.len_lines() {
+        start..text.line_to_char(last + 1)
+    } else {
+        start.saturating_sub(1)..text.len_chars()
+    }
+}
+
+fn yank(b: &Buffer, region: &Region) -> Register {
+    match region {
+        Region::Chars(range) => Register {
+            text: b.text().slice(range.clone()).to_string(),
+            linewise: false,
+        },
+        Region::Lines(first, last) => {
+            let mut text = String::new();
+            for row in *first..=*last {
+                text.push_str(&b.line_text(row));
+                text.push('\n');
+            }
+            Register {
+                text,
+                linewise: true,
+            }
+        }
+        Region::Block(first, last, left, right) => {
+            let lines: Vec<String> = (*first..=*last)
+                .map(|row| {
+                    let cols = b.block_cols(row, *left, *right);
+                    b.text().line(row).slice(cols).to_string()
+                })
+                .collect();
+            Register {
+                text: lines.join("\n"),
+                linewise: false,
+            }
+        }
+    }
+}
+
+fn delete(b: &mut Buffer, region: &Region) {
+    match region {
+        Region::Chars(range) => {
+            b.remove(range.clone());
+            b.set_cursor_char(range.start);
+        }
+        Region::Lines(first, last) => {
+            b.remove(lines_range(b, *first, *last));
+            let row = (*first).min(b.last_line());
+            b.set_cursor_char(b.text().line_to_char(row) + b.first_non_blank(row));
+        }
+        Region::Block(first, last, left, right) => {
+            // bottom up so earlier char indexes stay valid
+            for row in (*first..=*last).rev() {
+                let cols = b.block_cols(row, *left, *right);
+                let start = b.text().line_to_char(row);
+                b.remove(start + cols.start..start + cols.end);
+            }
+            let col = b.col_at_width(*first, *left);
+            b.set_cursor_char(b.text().line_to_char(*first) + col);
+        }
+    }
+}
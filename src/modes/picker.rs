@@ -0,0 +1,175 @@
+//! A list to choose an item from, such as the open buffers. A picker can
+//! also be searched, typing narrows the list to the items matching what was
+//! typed, best match first
+
+use std::cmp::Reverse;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// what a key did to the picker
+#[derive(Debug, PartialEq, Eq)]
+pub enum Picked {
+    Picking,
+    Cancel,
+    /// the item with this id was chosen
+    Select(usize),
+    /// the item with this id should be removed
+    Delete(usize),
+}
+
+#[derive(Debug)]
+pub struct Picker {
+    title: String,
+    /// id given back when an item is picked, and its label
+    items: Vec<(usize, String)>,
+    selected: usize,
+    /// text typed to search the items, when searching
+    query: Option<String>,
+    /// every item, of which `items` are those matching the query
+    choices: Vec<(usize, String)>,
+}
+
+impl Picker {
+    /// list `items`, starting on the one with id `selected`
+    pub fn new(title: String, items: Vec<(usize, String)>, selected: usize) -> Self {
+        let selected = items
+            .iter()
+            .position(|(id, _)| *id == selected)
+            .unwrap_or_default();
+
+        Self {
+            title,
+            items,
+            selected,
+            query: None,
+            choices: Vec::new(),
+        }
+    }
+
+    /// list `items` to search by typing
+    pub fn searching(title: String, items: Vec<(usize, String)>) -> Self {
+        Self {
+            title,
+            items: items.clone(),
+            selected: 0,
+            query: Some(String::new()),
+            choices: items,
+        }
+    }
+
+    /// text typed to search the items, when searching
+    pub fn query(&self) -> Option<&str> {
+        self.query.as_deref()
+    }
+
+    fn search(&mut self, query: String) {
+        let mut found: Vec<(usize, &(usize, String))> = self
+            .choices
+            .iter()
+            .filter_map(|item| Some((fuzzy_score(&query, &item.1)?, item)))
+            .collect();
+        found.sort_by_key(|(score, _)| Reverse(*score));
+
+        self.items = found.into_iter().map(|(_, item)| item.clone()).collect();
+        self.selected = 0;
+        self.query = Some(query);
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn labels(&self) -> impl Iterator<Item = &str> {
+        self.items.iter().map(|(_, label)| label.as_str())
+    }
+
+    /// index of the highlighted item
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    fn selected_id(&self) -> Option<usize> {
+        self.items.get(self.selected).map(|(id, _)| *id)
+    }
+
+    pub fn handle(&mut self, KeyEvent { code, modifiers }: KeyEvent) -> Picked {
+        let ctrl = modifiers.contains(KeyModifiers::CONTROL);
+        let last = self.items.len().saturating_sub(1);
+
+        // while searching, chars are typed into the query
+        if let Some(query) = &self.query {
+            let mut query = query.clone();
+            let typed = match code {
+                KeyCode::Char(c) if !ctrl => {
+                    query.push(c);
+                    true
+                }
+                KeyCode::Backspace => query.pop().is_some(),
+                _ => false,
+            };
+
+            if typed {
+                self.search(query);
+                return Picked::Picking;
+            }
+        }
+
+        match code {
+            KeyCode::Esc | KeyCode::Char('q') => return Picked::Cancel,
+            KeyCode::Enter => {
+                return self.selected_id().map_or(Picked::Cancel, Picked::Select);
+            }
+            KeyCode::Char('d') if !ctrl => {
+                if let Some(id) = self.selected_id() {
+                    return Picked::Delete(id);
+                }
+            }
+            KeyCode::Down | KeyCode::Char('j') | KeyCode::Tab => {
+                self.selected = (self.selected + 1).min(last)
+            }
+            KeyCode::Char('n') if ctrl => self.selected = (self.selected + 1).min(last),
+            KeyCode::Up | KeyCode::Char('k') | KeyCode::BackTab => {
+                self.selected = self.selected.saturating_sub(1)
+            }
+            KeyCode::Char('p') if ctrl => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Home | KeyCode::Char('g') => self.selected = 0,
+            KeyCode::End | KeyCode::Char('G') => self.selected = last,
+            _ => (),
+        }
+
+        Picked::Picking
+    }
+}
+
+/// How well `query` matches `text`, none unless its chars are all found in
+/// order. Matches of several chars in a row, or at the start of a word,
+/// count for more. Case is ignored
+fn fuzzy_score(query: &str, text: &str) -> Option<usize> {
+    let mut chars = text.chars().map(|c| c.to_ascii_lowercase()).enumerate();
+    let mut score = 0;
+    let mut last = None;
+    let mut prev = ' ';
+
+    for q in query.chars().map(|c| c.to_ascii_lowercase()) {
+        let (i, _) = chars.by_ref().find(|(_, c)| {
+            let found = *c == q;
+            if !found {
+                prev = *c;
+            }
+            found
+        })?;
+
+        score += 1;
+        if last.is_some_and(|last| last + 1 == i) {
+            score += 2;
+        }
+        if !prev.is_alphanumeric() {
+            score += 3;
+        }
+
+        last = Some(i);
+        prev = q;
+    }
+
+    Some(score)
+}
@@ -0,0 +1,199 @@
+//! State of `/` and `?` searches: the pattern `n` and `N` repeat and, while
+//! a search is typed, where it started so it can be undone
+
+use regex::Regex;
+use ropey::Rope;
+use std::ops::Range;
+
+use crate::text::search;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchDirection {
+    Forward,
+    Backward,
+}
+
+impl SearchDirection {
+    /// char the search line is shown after
+    pub fn prompt(&self) -> char {
+        match self {
+            SearchDirection::Forward => '/',
+            SearchDirection::Backward => '?',
+        }
+    }
+
+    fn reverse(self) -> Self {
+        match self {
+            SearchDirection::Forward => SearchDirection::Backward,
+            SearchDirection::Backward => SearchDirection::Forward,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Search {
+    /// last pattern searched for
+    pattern: Option<Regex>,
+    direction: SearchDirection,
+    /// matches of the pattern are drawn, until turned off with `:noh`
+    highlight: bool,
+    /// cursor char a search being typed started from, and the pattern
+    /// before it
+    typing: Option<(usize, Option<Regex>)>,
+}
+
+impl Default for Search {
+    fn default() -> Self {
+        Self {
+            pattern: None,
+            direction: SearchDirection::Forward,
+            highlight: true,
+            typing: None,
+        }
+    }
+}
+
+impl Search {
+    /// pattern to draw matches of
+    pub fn highlight(&self) -> Option<&Regex> {
+        self.pattern.as_ref().filter(|_| self.highlight)
+    }
+
+    pub fn pattern(&self) -> Option<&Regex> {
+        self.pattern.as_ref()
+    }
+
+    /// search for `pattern` from now on, as `:s` does
+    pub fn set_pattern(&mut self, pattern: Regex) {
+        self.pattern = Some(pattern);
+        self.highlight = true;
+    }
+
+    pub fn clear_highlight(&mut self) {
+        self.highlight = false;
+    }
+
+    /// start typing a search from cursor char `from`
+    pub fn begin(&mut self, from: usize) {
+        self.typing = Some((from, self.pattern.clone()));
+    }
+
+    /// cursor char the search being typed started from
+    pub fn origin(&self) -> usize {
+        self.typing.as_ref().map_or(0, |(from, _)| *from)
+    }
+
+    /// Show the matches of `text` while it is typed, giving the first one
+    /// in `direction` from where the search started
+    pub fn preview(
+        &mut self,
+        rope: &Rope,
+        text: &str,
+        direction: SearchDirection,
+    ) -> Option<Range<usize>> {
+        let before = self.typing.as_ref().and_then(|(_, before)| before.clone());
+
+        match Regex::new(text).ok().filter(|_| !text.is_empty()) {
+            Some(re) => {
+                let found = self.find(rope, &re, self.origin(), direction);
+                self.pattern = Some(re);
+                self.highlight = true;
+                found
+            }
+            None => {
+                self.pattern = before;
+                None
+            }
+        }
+    }
+
+    /// give up the search being typed, bringing back the pattern from before
+    /// it. Gives the cursor char it started from
+    pub fn cancel(&mut self) -> usize {
+        let from = self.origin();
+
+        if let Some((_, before)) = self.typing.take() {
+            self.pattern = before;
+        }
+
+        from
+    }
+
+    /// Search for the entered `text`, or the last pattern again when it is
+    /// empty, giving the match from where the search started
+    pub fn confirm(
+        &mut self,
+        rope: &Rope,
+        text: &str,
+        direction: SearchDirection,
+    ) -> Result<Range<usize>, String> {
+        let from = self.cancel();
+
+        let pattern = match text.is_empty() {
+            true => self
+                .pattern
+                .clone()
+                .ok_or_else(|| "No previous regular expression".to_string())?,
+            false => Regex::new(text).map_err(|e| format!("Invalid pattern: {}", e))?,
+        };
+
+        self.set_pattern(pattern.clone());
+        self.direction = direction;
+
+        self.find(rope, &pattern, from, direction)
+            .ok_or_else(|| format!("Pattern not found: {}", pattern))
+    }
+
+    /// Match of the last pattern `count` matches on from char `from`, in
+    /// the direction last searched or the other way for `reverse`
+    pub fn repeat(
+        &mut self,
+        rope: &Rope,
+        from: usize,
+        count: usize,
+        reverse: bool,
+    ) -> Result<Range<usize>, String> {
+        let pattern = self
+            .pattern
+            .clone()
+            .ok_or_else(|| "No previous regular expression".to_string())?;
+        let direction = match reverse {
+            true => self.direction.reverse(),
+            false => self.direction,
+        };
+
+        self.highlight = true;
+
+        let next = |at| {
+            self.find(rope, &pattern, at, direction)
+                .ok_or_else(|| format!("Pattern not found: {}", pattern))
+        };
+
+        let first = next(from)?;
+        let mut found = first.clone();
+        let mut steps = count.max(1) - 1;
+        let mut taken = 0;
+
+        while taken < steps {
+            found = next(found.start)?;
+            taken += 1;
+
+            // back at the first match, so every lap after this ends there
+            if found.start == first.start {
+                steps = taken + (steps - taken) % taken;
+            }
+        }
+
+        Ok(found)
+    }
+
+    fn find(
+        &self,
+        rope: &Rope,
+        re: &Regex,
+        from: usize,
+        direction: SearchDirection,
+    ) -> Option<Range<usize>> {
+        search::find(rope, re, from, direction == SearchDirection::Forward)
+    }
+}
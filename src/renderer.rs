@@ -1,100 +1,124 @@
-use crossterm::cursor::{Hide, MoveTo, MoveToNextLine, RestorePosition, SavePosition, Show};
-use crossterm::style::{
-    Attribute, Color, Print, SetAttribute, SetBackgroundColor, SetForegroundColor,
-};
-use crossterm::terminal::{
-    self, disable_raw_mode, enable_raw_mode, Clear, ClearType, DisableLineWrap,
-};
-use crossterm::{ExecutableCommand, QueueableCommand};
+use crossterm::terminal;
 use log::info;
-use std::fmt::Display;
-use std::io::{self, Error, Stdout, Write};
 
-use crate::modes::Cursor;
+use regex::Regex;
+use std::ops::Range;
+use std::rc::Rc;
+
+use crate::config::WindowOptions;
+use crate::modes::{Cursor, Pos};
+use crate::text::{self, search, Wrap};
 
 pub type RendResult = Result<(), std::io::Error>;
 
+/// Rows and columns of the terminal. Where it cannot be read, such as when
+/// output is not a terminal, a common default size is assumed
+pub fn terminal_size() -> (usize, usize) {
+    match terminal::size() {
+        Ok((cols, rows)) => (rows.into(), cols.into()),
+        Err(e) => {
+            info!("could not get terminal size: {}", e);
+            (24, 80)
+        }
+    }
+}
+
+mod backend;
+mod grid;
+mod layout;
+mod modeline;
+mod theme;
 mod widgets;
 
+#[cfg(test)]
+pub use self::backend::TestBackend;
+pub use self::backend::{Backend, CrosstermBackend};
+use self::grid::{Grid, Style};
+use self::layout::Layout;
+pub use self::layout::{Direction, Rect};
+pub use self::modeline::{Segment, Segments, Status};
+pub use self::theme::{available as available_themes, ColorSupport, Theme};
+pub use self::widgets::Popup;
 use self::widgets::*;
 
 #[derive(Debug)]
-pub struct Renderer {
-    stdout: Stdout,
+pub struct Renderer<B: Backend = CrosstermBackend> {
+    backend: B,
+    /// what is on screen now
+    front: Grid,
+    /// the frame being drawn
+    back: Grid,
 }
 
-impl Renderer {
-    pub fn new() -> Self {
-        let mut stdout = io::stdout();
-
-        enable_raw_mode().unwrap();
+impl<B: Backend> Renderer<B> {
+    pub fn new(backend: B, rows: usize, cols: usize) -> Self {
+        let front = Grid::new(rows, cols);
 
-        #[inline]
-        fn clear(stdout: &mut Stdout) -> RendResult {
-            stdout
-                // .queue(EnterAlternateScreen)?
-                .queue(DisableLineWrap)?
-                .queue(Hide)?
-                .queue(Clear(ClearType::All))?
-                .queue(MoveTo(0, 0))?
-                .flush()?;
-
-            Ok(())
+        Self {
+            backend,
+            back: front.clone(),
+            front,
         }
+    }
 
-        clear(&mut stdout).unwrap();
-
-        let (columns, rows) = terminal::size().expect("could not get terminal size");
-
-        Self { stdout }
+    #[cfg(test)]
+    pub fn backend(&self) -> &B {
+        &self.backend
     }
 
+    /// Draw `screen` into the back buffer and send the cells that changed
+    /// since the last frame to the backend
     pub fn render<T: Widget>(&mut self, screen: &mut T) -> RendResult {
-        let (columns, rows) = terminal::size().expect("could not get terminal size");
+        let (rows, cols) = (self.back.rows(), self.back.cols());
+
+        self.back.clear();
 
         let mut ctx = UiCtx {
-            stdout: &mut self.stdout,
+            grid: &mut self.back,
             row_start: 0,
-            row_end: 1,
+            row_end: rows as u16,
             col_start: 0,
-            col_end: columns,
+            col_end: cols as u16,
         };
 
         screen.render(&mut ctx)?;
 
-        ctx.stdout.flush()?;
+        self.backend.draw(self.back.changes(&self.front))?;
+        self.backend.flush()?;
+
+        std::mem::swap(&mut self.front, &mut self.back);
 
         Ok(())
     }
 
+    /// Start over at a new screen size, the screen is cleared so the next
+    /// frame is drawn in full
+    pub fn resize(&mut self, rows: usize, cols: usize) -> RendResult {
+        self.front = Grid::new(rows, cols);
+        self.back = Grid::new(rows, cols);
+
+        self.backend.clear(rows, cols)
+    }
+
+    /// place the cursor, hidden when off screen
     pub fn cursor(&mut self, cursor: &Cursor) -> RendResult {
-        let (r, c) = cursor.as_u16().unwrap();
-        self.stdout
-            .queue(SavePosition)?
-            .queue(MoveTo(c, r + 1))?
-            .queue(Print("_"))?
-            .queue(RestorePosition)?
-            .flush()
+        let on_screen = cursor.row < self.front.rows() && cursor.col < self.front.cols();
+
+        let cursor = match on_screen {
+            true => cursor.as_u16(),
+            false => None,
+        };
+
+        self.backend.set_cursor(cursor)?;
+        self.backend.flush()
     }
 }
 
 impl Default for Renderer {
     fn default() -> Self {
-        Self::new()
-    }
-}
+        let (rows, cols) = terminal_size();
 
-impl Drop for Renderer {
-    fn drop(&mut self) {
-        self.stdout
-            // .execute(LeaveAlternateScreen)
-            // .unwrap()
-            .execute(Show)
-            .unwrap();
-
-        info!("App dropped\n\n");
-
-        disable_raw_mode().unwrap();
+        Self::new(CrosstermBackend::new(), rows, cols)
     }
 }
 
@@ -102,81 +126,666 @@ impl Drop for Renderer {
 pub struct UI {
     widgets: List,
     windows: Vec<Window>,
+    layout: Layout,
+    separators: Vec<(Direction, Rect)>,
     active_window: usize,
+    /// drawn over the windows, such as the buffer picker
+    popup: Option<Popup>,
+    /// bottom row of the screen
+    modeline: Line,
+    theme: Rc<Theme>,
+    /// colours themes are fitted to
+    colors: ColorSupport,
+    rows: usize,
+    cols: usize,
 }
 
 impl Default for UI {
     fn default() -> Self {
-        let mut main_window = Window::default();
-        let (cols, rows) = terminal::size().expect("could not get terminal size");
+        let (rows, cols) = terminal_size();
 
-        let cols = cols.try_into().unwrap();
-        let rows = rows.try_into().unwrap();
+        Self::new(rows, cols)
+    }
+}
 
-        main_window.resize(rows, cols);
-        Self {
+impl UI {
+    pub fn new(rows: usize, cols: usize) -> Self {
+        let mut ui = Self {
             widgets: Default::default(),
-            windows: vec![main_window],
+            windows: vec![Window::default()],
+            layout: Default::default(),
+            separators: Default::default(),
             active_window: 0,
-        }
+            popup: None,
+            modeline: Default::default(),
+            theme: Default::default(),
+            colors: ColorSupport::detect(),
+            rows,
+            cols,
+        };
+
+        ui.arrange();
+        ui
+    }
+
+    /// fit the windows to a new screen size
+    pub fn resize(&mut self, rows: usize, cols: usize) {
+        self.rows = rows;
+        self.cols = cols;
+        self.arrange();
     }
-}
 
-impl UI {
     pub fn get_active_window(&mut self) -> &mut Window {
         self.windows
             .get_mut(self.active_window)
             .expect("could not get active window")
     }
 
-    // temporary
-    pub fn screen(&mut self) -> &mut Window {
-        self.get_active_window()
+    pub fn windows_mut(&mut self) -> impl Iterator<Item = &mut Window> {
+        self.windows.iter_mut()
+    }
+
+    /// screen above the modeline, shared between the windows
+    fn area(&self) -> Rect {
+        Rect {
+            row: 0,
+            col: 0,
+            rows: self.rows.saturating_sub(1),
+            cols: self.cols,
+        }
+    }
+
+    /// size and place every window from the layout
+    fn arrange(&mut self) {
+        let (windows, separators) = self.layout.arrange(self.area());
+
+        for (id, rect) in windows {
+            self.windows[id].place(rect);
+        }
+
+        self.separators = separators;
+    }
+
+    /// Split the active window in two, both showing its buffer, and move to
+    /// the new one
+    pub fn split(&mut self, direction: Direction) {
+        let new = self.windows.len();
+        let window = self.get_active_window().split_off();
+
+        self.windows.push(window);
+        self.layout.split(self.active_window, new, direction);
+        self.active_window = new;
+        self.arrange();
+    }
+
+    /// Close the active window, the last window is never closed. Returns
+    /// whether it was
+    pub fn close(&mut self) -> bool {
+        if self.windows.len() == 1 {
+            return false;
+        }
+
+        let closed = self.active_window;
+        let (order, _) = self.layout.arrange(self.area());
+        let pos = order.iter().position(|(id, _)| *id == closed).unwrap_or(0);
+
+        self.windows.remove(closed);
+        self.layout.remove(closed);
+
+        // focus moves to the window before the closed one, as with `:close`
+        let (order, _) = self.layout.arrange(self.area());
+        self.active_window = order[pos.saturating_sub(1).min(order.len() - 1)].0;
+        self.arrange();
+
+        true
+    }
+
+    /// close every window but the active one
+    pub fn only(&mut self) {
+        let window = self.windows.swap_remove(self.active_window);
+
+        self.windows = vec![window];
+        self.layout = Layout::Window(0);
+        self.active_window = 0;
+        self.arrange();
+    }
+
+    pub fn window_count(&self) -> usize {
+        self.windows.len()
+    }
+
+    /// move to the next or previous window, from the top left
+    pub fn cycle(&mut self, forward: bool) {
+        let (order, _) = self.layout.arrange(self.area());
+        let len = order.len();
+        let pos = order
+            .iter()
+            .position(|(id, _)| *id == self.active_window)
+            .unwrap_or(0);
+
+        let next = match forward {
+            true => (pos + 1) % len,
+            false => (pos + len - 1) % len,
+        };
+        self.active_window = order[next].0;
+    }
+
+    /// Move to the window next to the active one, `rows` and `cols` being
+    /// -1, 0 or 1. Of several, the one beside the cursor is picked
+    pub fn focus(&mut self, rows: isize, cols: isize) {
+        let (windows, _) = self.layout.arrange(self.area());
+        let active = self.windows[self.active_window].rect;
+        let cursor = self.cursor();
+
+        let beside = |rect: &Rect| match (rows, cols) {
+            (-1, _) => rect.bottom() + 1 == active.row,
+            (1, _) => active.bottom() + 1 == rect.row,
+            (_, -1) => rect.right() + 1 == active.col,
+            _ => active.right() + 1 == rect.col,
+        };
+
+        // distance from the cursor along the shared edge
+        let distance = |rect: &Rect| match rows {
+            0 if cursor.row < rect.row => rect.row - cursor.row,
+            0 => cursor.row.saturating_sub(rect.bottom().saturating_sub(1)),
+            _ if cursor.col < rect.col => rect.col - cursor.col,
+            _ => cursor.col.saturating_sub(rect.right().saturating_sub(1)),
+        };
+
+        if let Some((id, _)) = windows
+            .iter()
+            .filter(|(_, rect)| beside(rect))
+            .min_by_key(|(_, rect)| distance(rect))
+        {
+            self.active_window = *id;
+        }
+    }
+
+    /// grow or shrink the active window by `delta` rows or columns
+    pub fn resize_window(&mut self, direction: Direction, delta: isize) {
+        let area = self.area();
+
+        self.layout
+            .resize(self.active_window, direction, delta, area);
+        self.arrange();
+    }
+
+    /// give windows side by side the same space
+    pub fn equalize(&mut self) {
+        self.layout.equalize();
+        self.arrange();
+    }
+
+    /// cursor of the active window on screen
+    pub fn cursor(&self) -> Cursor {
+        let window = &self.windows[self.active_window];
+        let Cursor { row, col } = *window.cursor();
+
+        Cursor {
+            row: window.rect.row + row,
+            col: window.rect.col + col,
+        }
+    }
+
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    pub fn color_support(&self) -> ColorSupport {
+        self.colors
+    }
+
+    /// Style everything with `theme`. Windows show it once their buffer is
+    /// set again
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = Rc::new(theme);
+
+        for window in self.windows.iter_mut() {
+            window.theme = Rc::clone(&self.theme);
+        }
+    }
+
+    /// show the segments of the modeline, see [`modeline`]
+    pub fn set_modeline(&mut self, status: &Status, segments: &Segments) {
+        self.modeline = modeline::line(status, segments, &self.theme, self.cols);
+    }
+
+    pub fn set_popup(&mut self, popup: Option<Popup>) {
+        self.popup = popup.map(|popup| {
+            popup.styled(
+                self.theme.get("ui.popup"),
+                self.theme.get("ui.popup.selected"),
+            )
+        });
+    }
+
+    /// start of the selected line of the popup, when one is shown with a
+    /// line selected
+    pub fn popup_cursor(&self) -> Option<Cursor> {
+        let popup = self.popup.as_ref()?;
+        let rect = popup.rect(self.area());
+
+        Some(Cursor {
+            row: rect.row + popup.selected_row(rect.rows)?,
+            col: rect.col + 1,
+        })
+    }
+
+    /// Show the line being typed in command or search mode after `prompt`
+    /// in place of the modeline, scrolled so the cursor, `cursor` columns
+    /// into `text`, stays on screen. Gives the screen column of the cursor
+    pub fn set_command_line(&mut self, prompt: char, text: &str, cursor: usize) -> usize {
+        let cursor = cursor + 1;
+        let left = (cursor + 1).saturating_sub(self.cols);
+        let line = Span::new(format!("{}{}", prompt, text));
+
+        self.modeline = Line::clipped(vec![line], left, self.cols);
+        // with no columns there is nowhere to put the cursor but the left
+        cursor.saturating_sub(left)
+    }
+
+    /// show a message in place of the modeline, styled as an error or not
+    pub fn set_message(&mut self, text: &str, error: bool) {
+        let style = match error {
+            true => self.theme.get("ui.message.error"),
+            false => self.theme.get("ui.message"),
+        };
+        let span = Span::styled(text.to_string(), style);
+
+        self.modeline = Line::clipped(vec![span], 0, self.cols);
+    }
+
+    /// screen row the modeline is drawn on
+    pub fn modeline_row(&self) -> usize {
+        self.rows.saturating_sub(1)
+    }
+}
+
+impl Widget for UI {
+    fn render(&mut self, ui_ctx: &mut UiCtx) -> RendResult {
+        for window in self.windows.iter_mut() {
+            let Rect {
+                row,
+                col,
+                rows,
+                cols,
+            } = window.rect;
+
+            ui_ctx.row_start = row as u16;
+            ui_ctx.row_end = (row + rows) as u16;
+            ui_ctx.col_start = col as u16;
+            ui_ctx.col_end = (col + cols) as u16;
+            window.render(ui_ctx)?;
+        }
+
+        let style = self.theme.get("ui.separator");
+        for (direction, rect) in &self.separators {
+            let fill = match direction {
+                Direction::Horizontal => "─".repeat(rect.cols),
+                Direction::Vertical => "│".to_string(),
+            };
+
+            for row in rect.row..rect.bottom() {
+                ui_ctx
+                    .grid
+                    .put_str(row, rect.col, rect.right(), &fill, style);
+            }
+        }
+
+        let area = self.area();
+        if let Some(popup) = &mut self.popup {
+            let rect = popup.rect(area);
+
+            ui_ctx.row_start = rect.row as u16;
+            ui_ctx.row_end = rect.bottom() as u16;
+            ui_ctx.col_start = rect.col as u16;
+            ui_ctx.col_end = rect.right() as u16;
+            popup.render(ui_ctx)?;
+        }
+
+        ui_ctx.row_start = self.modeline_row() as u16;
+        ui_ctx.row_end = ui_ctx.row_start + 1;
+        ui_ctx.col_start = 0;
+        ui_ctx.col_end = self.cols as u16;
+        self.modeline.render(ui_ctx)
     }
 }
 
-#[derive(Default, Debug)]
+#[derive(Debug)]
 pub struct Window {
     buffer: usize,
     contents: List,
-    did_update: bool,
     rows: usize,
     cols: usize,
+    /// where the window is on screen
+    rect: Rect,
+    /// top line and left column of the buffer shown in the window
+    view: Pos,
+    /// wrapped rows of the top line scrolled out of view
+    top_skip: usize,
+    scroll_off: usize,
+    side_scroll_off: usize,
+    /// soft wrap long lines instead of scrolling sideways
+    wrap: bool,
+    wrap_marker: String,
+    /// pattern whose matches are highlighted
+    highlight: Option<Regex>,
+    theme: Rc<Theme>,
+    /// buffer cursor relative to the top left of the window
+    cursor: Cursor,
+}
+
+impl Default for Window {
+    fn default() -> Self {
+        let options = WindowOptions::default();
+
+        Self {
+            buffer: 0,
+            contents: Default::default(),
+            rows: 0,
+            cols: 0,
+            rect: Default::default(),
+            view: Default::default(),
+            top_skip: 0,
+            scroll_off: options.scrolloff,
+            side_scroll_off: options.sidescrolloff,
+            wrap: options.wrap,
+            wrap_marker: options.showbreak,
+            highlight: None,
+            theme: Default::default(),
+            cursor: Default::default(),
+        }
+    }
 }
 
 use crate::app::buffer::Buffer;
 
 impl Window {
+    /// Show `buffer` in the window, scrolling to keep its cursor in view
     pub fn set_buffer(&mut self, buff_id: usize, buffer: &Buffer) {
         self.buffer = buff_id;
 
-        // start simple and set row as start of view
-        let Cursor { row, .. } = buffer.get_cursor();
+        let mut list = List::new(Vec::with_capacity(self.rows));
+
+        match self.wrap() {
+            Some(wrap) => self.draw_wrapped(buffer, wrap, &mut list.lines),
+            None => self.draw_clipped(buffer, &mut list.lines),
+        }
 
-        let lines = buffer.get_lines_range(*row, row + self.rows);
-        let mut list = List::from(lines);
-        list.expand(self.rows);
+        list.expand(self.rows, self.theme.get("ui.tilde"));
         self.contents = list;
     }
 
+    /// one screen row per line, cut to the columns in view
+    fn draw_clipped(&mut self, buffer: &Buffer, lines: &mut Vec<Line>) {
+        let cursor = Cursor {
+            row: buffer.get_cursor().row,
+            col: buffer.cursor_width(),
+        };
+        self.top_skip = 0;
+        self.scroll_to(&cursor, buffer.last_line());
+
+        let Pos { x: left, y: top } = self.view;
+
+        let range = buffer.get_lines_range(top, top + self.rows);
+
+        for (i, line) in range.lines.lines().take(range.len).enumerate() {
+            let row = top + i;
+            let text = line.to_string();
+            let text = text.trim_end_matches('\n');
+
+            let spans = Span::highlight(text, &self.highlights(buffer, row));
+            lines.push(Line::clipped(spans, left, self.cols));
+        }
+
+        self.cursor = Cursor::from_cursor_and_scroll_offset(&cursor, &self.view);
+    }
+
+    /// lines broken over as many screen rows as they need
+    fn draw_wrapped(&mut self, buffer: &Buffer, wrap: Wrap, lines: &mut Vec<Line>) {
+        let Cursor { row, col } = *buffer.get_cursor();
+        let rows = buffer.wrapped_rows(row, wrap);
+        let (sub, screen_col) = text::wrap_pos(buffer.line_slice(row), wrap, &rows, col);
+
+        self.view.x = 0;
+        self.scroll_wrapped(buffer, wrap, row, sub);
+
+        let mut line_row = self.view.y;
+        let mut skip = self.top_skip;
+
+        while lines.len() < self.rows && line_row <= buffer.last_line() {
+            if line_row == row {
+                self.cursor = Cursor {
+                    row: (lines.len() + sub).saturating_sub(skip),
+                    col: screen_col,
+                };
+            }
+
+            let line = buffer.line_slice(line_row);
+            let highlights = self.highlights(buffer, line_row);
+            let segments = buffer.wrapped_rows(line_row, wrap);
+            let last = segments.len() - 1;
+
+            for (i, segment) in segments.into_iter().enumerate().skip(skip) {
+                if lines.len() >= self.rows {
+                    break;
+                }
+
+                let mut spans = Vec::new();
+                if i > 0 {
+                    let style = self.theme.get("ui.wrap");
+                    spans.push(Span::styled(self.wrap_marker.clone(), style));
+                }
+
+                let text = line.slice(segment.clone()).to_string();
+
+                // the selection may cover the line break, after the last row
+                let end = if i == last {
+                    segment.end + 1
+                } else {
+                    segment.end
+                };
+
+                let in_segment: Vec<_> = highlights
+                    .iter()
+                    .filter(|(cols, _)| cols.start < end && cols.end > segment.start)
+                    .map(|(cols, style)| {
+                        let start = cols.start.max(segment.start) - segment.start;
+                        (start..cols.end.min(end) - segment.start, *style)
+                    })
+                    .collect();
+                spans.extend(Span::highlight(&text, &in_segment));
+
+                lines.push(Line::clipped(spans, 0, self.cols));
+            }
+
+            skip = 0;
+            line_row += 1;
+        }
+    }
+
+    /// styled char ranges of line `row`, the matches of the search pattern
+    /// and then the selection
+    fn highlights(&self, buffer: &Buffer, row: usize) -> Vec<(Range<usize>, Style)> {
+        let theme = &self.theme;
+        let mut highlights: Vec<_> = buffer
+            .syntax_highlights(row)
+            .into_iter()
+            .map(|(cols, name)| (cols, theme.get(&format!("syntax.{}", name))))
+            .collect();
+
+        if let Some(re) = &self.highlight {
+            let style = theme.get("search.match");
+            let matches = search::line_matches(buffer.line_slice(row), re);
+            highlights.extend(matches.into_iter().map(|cols| (cols, style)));
+        }
+
+        if let Some(cols) = buffer.selection_on_line(row) {
+            highlights.push((cols, theme.get("ui.selection")));
+        }
+
+        highlights
+    }
+
+    /// Move the top of a wrapped view so screen row `sub` of line `row` is
+    /// inside it, outside of the scroll margins where the buffer allows
+    fn scroll_wrapped(&mut self, buffer: &Buffer, wrap: Wrap, row: usize, sub: usize) {
+        if self.rows == 0 {
+            return;
+        }
+
+        let off = self.scroll_off.min((self.rows - 1) / 2);
+        let rows_of = |line: usize| buffer.wrapped_rows(line, wrap).len();
+
+        // the line and wrapped row `n` screen rows above the cursor
+        let above = |n: usize| {
+            let (mut line, mut sub) = (row, sub);
+
+            for _ in 0..n {
+                if sub > 0 {
+                    sub -= 1;
+                } else if line > 0 {
+                    line -= 1;
+                    sub = rows_of(line) - 1;
+                } else {
+                    break;
+                }
+            }
+
+            (line, sub)
+        };
+
+        // the margin below stops at the end of the buffer
+        let mut below = rows_of(row).saturating_sub(sub + 1);
+        let mut line = row;
+        while below < off && line < buffer.last_line() {
+            line += 1;
+            below += rows_of(line);
+        }
+
+        let top_line = self.view.y.min(buffer.last_line());
+        let top = (top_line, self.top_skip.min(rows_of(top_line) - 1));
+        let (line, skip) = top
+            .min(above(off))
+            .max(above(self.rows - 1 - below.min(off)));
+
+        self.view.y = line;
+        self.top_skip = skip;
+    }
+
+    /// move the view so `cursor` is inside it, outside of the scroll margins
+    /// where the buffer allows
+    fn scroll_to(&mut self, cursor: &Cursor, last_line: usize) {
+        let Cursor { row, col } = *cursor;
+
+        if self.rows > 0 {
+            let off = self.scroll_off.min((self.rows - 1) / 2);
+
+            // the margin below stops at the end of the buffer
+            let below = (row + off).min(last_line);
+
+            if row < self.view.y + off {
+                self.view.y = row.saturating_sub(off);
+            } else if below >= self.view.y + self.rows {
+                self.view.y = below + 1 - self.rows;
+            }
+        }
+
+        if self.cols > 0 {
+            let off = self.side_scroll_off.min((self.cols - 1) / 2);
+
+            if col < self.view.x + off {
+                self.view.x = col.saturating_sub(off);
+            } else if col + off >= self.view.x + self.cols {
+                self.view.x = col + off + 1 - self.cols;
+            }
+        }
+    }
+
+    /// scroll by `delta` lines, keeping the top line within the buffer
+    pub fn scroll_by(&mut self, delta: isize, last_line: usize) {
+        let top = self.view.y as isize + delta;
+        self.view.y = top.clamp(0, last_line as isize) as usize;
+        self.top_skip = 0;
+    }
+
+    /// how lines break when soft wrapped, `None` when they scroll sideways
+    pub fn wrap(&self) -> Option<Wrap> {
+        self.wrap.then(|| Wrap {
+            cols: self.cols,
+            marker: text::width(self.wrap_marker.as_str().into()),
+        })
+    }
+
+    pub fn options(&self) -> WindowOptions {
+        WindowOptions {
+            wrap: self.wrap,
+            scrolloff: self.scroll_off,
+            sidescrolloff: self.side_scroll_off,
+            showbreak: self.wrap_marker.clone(),
+        }
+    }
+
+    /// take on `options`, shown once the buffer is set again
+    pub fn set_options(&mut self, options: &WindowOptions) {
+        self.wrap = options.wrap;
+        self.scroll_off = options.scrolloff;
+        self.side_scroll_off = options.sidescrolloff;
+        self.wrap_marker = options.showbreak.clone();
+    }
+
     pub fn resize(&mut self, rows: usize, cols: usize) {
         self.rows = rows;
         self.cols = cols;
     }
-}
 
-impl Widget for Window {
-    type Components = List;
+    /// move the window to `rect` on screen, taking its size
+    fn place(&mut self, rect: Rect) {
+        self.rect = rect;
+        self.resize(rect.rows, rect.cols);
+    }
 
-    fn render(&mut self, ui_ctx: &mut UiCtx) -> RendResult {
-        self.contents.render(ui_ctx)
+    /// a new window on the same buffer and view, with the same options
+    fn split_off(&self) -> Window {
+        Window {
+            buffer: self.buffer,
+            view: self.view,
+            top_skip: self.top_skip,
+            scroll_off: self.scroll_off,
+            side_scroll_off: self.side_scroll_off,
+            wrap: self.wrap,
+            wrap_marker: self.wrap_marker.clone(),
+            highlight: self.highlight.clone(),
+            theme: Rc::clone(&self.theme),
+            ..Default::default()
+        }
     }
 
-    fn did_update(&self) -> bool {
-        self.did_update
+    pub fn highlight(&self) -> Option<&Regex> {
+        self.highlight.as_ref()
     }
 
-    fn update(&mut self, cb: impl FnMut(&mut Self::Components)) {
-        todo!()
+    /// highlight the matches of `pattern`, shown once the buffer is set again
+    pub fn set_highlight(&mut self, pattern: Option<Regex>) {
+        self.highlight = pattern;
+    }
+
+    /// id of the buffer shown in the window
+    pub fn buffer(&self) -> usize {
+        self.buffer
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cursor(&self) -> &Cursor {
+        &self.cursor
+    }
+}
+
+impl Widget for Window {
+    fn render(&mut self, ui_ctx: &mut UiCtx) -> RendResult {
+        self.contents.render(ui_ctx)
     }
 }
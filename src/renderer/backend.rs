@@ -0,0 +1,232 @@
+//! Where rendered frames end up. The renderer works out which cells changed
+//! and a backend puts them on screen, a real terminal through crossterm or an
+//! in memory grid for tests
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::style::{
+    Attribute, Color, Print, SetAttribute, SetBackgroundColor, SetForegroundColor,
+};
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, Clear, ClearType, DisableLineWrap};
+use crossterm::{ExecutableCommand, QueueableCommand};
+use log::info;
+use std::fmt::Debug;
+use std::io::{self, Stdout, Write};
+
+#[cfg(test)]
+use super::grid::Grid;
+use super::grid::{Cell, Style};
+use super::RendResult;
+use crate::text;
+
+pub trait Backend: Debug + 'static {
+    /// put the cells that changed since the last frame on screen, in order
+    /// row by row
+    fn draw<'a>(&mut self, cells: impl Iterator<Item = (usize, usize, &'a Cell)>) -> RendResult;
+
+    /// blank the whole screen, which is now `rows` by `cols`
+    fn clear(&mut self, rows: usize, cols: usize) -> RendResult;
+
+    /// show the cursor at a row and column, or hide it
+    fn set_cursor(&mut self, cursor: Option<(u16, u16)>) -> RendResult;
+
+    fn flush(&mut self) -> RendResult;
+}
+
+/// The terminal on stdout, held in raw mode while the backend lives
+#[derive(Debug)]
+pub struct CrosstermBackend {
+    stdout: Stdout,
+}
+
+impl CrosstermBackend {
+    pub fn new() -> Self {
+        let mut stdout = io::stdout();
+
+        enable_raw_mode().unwrap();
+
+        #[inline]
+        fn clear(stdout: &mut Stdout) -> RendResult {
+            stdout
+                // .queue(EnterAlternateScreen)?
+                .queue(DisableLineWrap)?
+                .queue(Hide)?
+                .queue(Clear(ClearType::All))?
+                .queue(MoveTo(0, 0))?
+                .flush()?;
+
+            Ok(())
+        }
+
+        clear(&mut stdout).unwrap();
+
+        Self { stdout }
+    }
+}
+
+impl Default for CrosstermBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Backend for CrosstermBackend {
+    /// moves the cursor and changes style only where needed
+    fn draw<'a>(&mut self, cells: impl Iterator<Item = (usize, usize, &'a Cell)>) -> RendResult {
+        let mut at = None;
+        let mut style = Style::default();
+
+        self.stdout
+            .queue(Hide)?
+            .queue(SetAttribute(Attribute::Reset))?;
+
+        for (row, col, cell) in cells {
+            if cell.is_continuation() {
+                continue;
+            }
+
+            if at != Some((row, col)) {
+                self.stdout.queue(MoveTo(col as u16, row as u16))?;
+            }
+
+            if cell.style != style {
+                set_style(&mut self.stdout, &style, &cell.style)?;
+                style = cell.style;
+            }
+
+            let Cell { symbol, .. } = cell;
+            self.stdout.queue(Print(symbol))?;
+
+            at = Some((row, col + text::grapheme_width(symbol)));
+        }
+
+        self.stdout.queue(SetAttribute(Attribute::Reset))?;
+
+        Ok(())
+    }
+
+    fn clear(&mut self, _rows: usize, _cols: usize) -> RendResult {
+        self.stdout.queue(Clear(ClearType::All))?;
+
+        Ok(())
+    }
+
+    fn set_cursor(&mut self, cursor: Option<(u16, u16)>) -> RendResult {
+        match cursor {
+            Some((row, col)) => self.stdout.queue(MoveTo(col, row))?.queue(Show)?,
+            None => self.stdout.queue(Hide)?,
+        };
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> RendResult {
+        self.stdout.flush()
+    }
+}
+
+impl Drop for CrosstermBackend {
+    fn drop(&mut self) {
+        self.stdout
+            // .execute(LeaveAlternateScreen)
+            // .unwrap()
+            .execute(Show)
+            .unwrap();
+
+        info!("App dropped\n\n");
+
+        disable_raw_mode().unwrap();
+    }
+}
+
+/// switch the terminal from style `from` to `to`
+fn set_style(stdout: &mut Stdout, from: &Style, to: &Style) -> RendResult {
+    // resetting an attribute resets the colours too
+    if from.attr != to.attr {
+        stdout.queue(SetAttribute(Attribute::Reset))?;
+
+        if let Some(attr) = to.attr {
+            stdout.queue(SetAttribute(attr))?;
+        }
+    }
+
+    if from.fg != to.fg || from.attr != to.attr {
+        stdout.queue(SetForegroundColor(to.fg.unwrap_or(Color::Reset)))?;
+    }
+
+    if from.bg != to.bg || from.attr != to.attr {
+        stdout.queue(SetBackgroundColor(to.bg.unwrap_or(Color::Reset)))?;
+    }
+
+    Ok(())
+}
+
+/// Screen kept in memory, for asserting on what would be drawn
+#[cfg(test)]
+#[derive(Debug)]
+pub struct TestBackend {
+    grid: Grid,
+    cursor: Option<(usize, usize)>,
+}
+
+#[cfg(test)]
+impl TestBackend {
+    pub fn new(rows: usize, cols: usize) -> Self {
+        Self {
+            grid: Grid::new(rows, cols),
+            cursor: None,
+        }
+    }
+
+    /// the screen as text, one line per row with trailing spaces trimmed
+    pub fn screen(&self) -> String {
+        let mut screen = String::new();
+
+        for row in 0..self.grid.rows() {
+            let line: String = (0..self.grid.cols())
+                .filter_map(|col| self.grid.cell(row, col))
+                .map(|cell| cell.symbol.as_str())
+                .collect();
+
+            screen.push_str(line.trim_end());
+            screen.push('\n');
+        }
+
+        screen
+    }
+
+    pub fn cell(&self, row: usize, col: usize) -> Option<&Cell> {
+        self.grid.cell(row, col)
+    }
+
+    /// row and column of the cursor, `None` when hidden
+    pub fn cursor(&self) -> Option<(usize, usize)> {
+        self.cursor
+    }
+}
+
+#[cfg(test)]
+impl Backend for TestBackend {
+    fn draw<'a>(&mut self, cells: impl Iterator<Item = (usize, usize, &'a Cell)>) -> RendResult {
+        for (row, col, cell) in cells {
+            self.grid.set_cell(row, col, cell.clone());
+        }
+
+        Ok(())
+    }
+
+    fn clear(&mut self, rows: usize, cols: usize) -> RendResult {
+        self.grid = Grid::new(rows, cols);
+
+        Ok(())
+    }
+
+    fn set_cursor(&mut self, cursor: Option<(u16, u16)>) -> RendResult {
+        self.cursor = cursor.map(|(row, col)| (row.into(), col.into()));
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> RendResult {
+        Ok(())
+    }
+}
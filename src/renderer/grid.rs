@@ -0,0 +1,148 @@
+//! In memory grid of styled cells. Widgets draw a whole frame into a grid,
+//! which is compared with the grid of the last frame so only the cells that
+//! changed are sent to the terminal
+
+use crossterm::style::{Attribute, Color};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::text::grapheme_width;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub attr: Option<Attribute>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cell {
+    /// grapheme shown in the cell, empty for the cell covered by the right
+    /// half of a wide grapheme
+    pub symbol: String,
+    pub style: Style,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            symbol: " ".to_string(),
+            style: Default::default(),
+        }
+    }
+}
+
+impl Cell {
+    /// right half of a wide grapheme, drawn along with the cell before it
+    pub fn is_continuation(&self) -> bool {
+        self.symbol.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Grid {
+    rows: usize,
+    cols: usize,
+    cells: Vec<Cell>,
+}
+
+impl Grid {
+    pub fn new(rows: usize, cols: usize) -> Self {
+        Self {
+            rows,
+            cols,
+            cells: vec![Cell::default(); rows * cols],
+        }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// blank every cell
+    pub fn clear(&mut self) {
+        self.cells.fill(Cell::default());
+    }
+
+    #[cfg(test)]
+    pub fn cell(&self, row: usize, col: usize) -> Option<&Cell> {
+        if row >= self.rows || col >= self.cols {
+            return None;
+        }
+
+        self.cells.get(row * self.cols + col)
+    }
+
+    #[cfg(test)]
+    pub fn set_cell(&mut self, row: usize, col: usize, cell: Cell) {
+        if row < self.rows && col < self.cols {
+            self.cells[row * self.cols + col] = cell;
+        }
+    }
+
+    fn set(&mut self, row: usize, col: usize, symbol: &str, style: Style) {
+        if let Some(cell) = self.cells.get_mut(row * self.cols + col) {
+            cell.symbol.clear();
+            cell.symbol.push_str(symbol);
+            cell.style = style;
+        }
+    }
+
+    /// Write `text` on `row` from column `col`, stopping before column
+    /// `right`. Tabs become spaces and a wide grapheme that would cross
+    /// `right` is replaced by spaces. Returns the column after the text
+    pub fn put_str(
+        &mut self,
+        row: usize,
+        col: usize,
+        right: usize,
+        text: &str,
+        style: Style,
+    ) -> usize {
+        let right = right.min(self.cols);
+        let mut col = col;
+
+        if row >= self.rows {
+            return col;
+        }
+
+        for g in text.graphemes(true) {
+            if col >= right {
+                break;
+            }
+
+            let width = grapheme_width(g);
+
+            if g == "\t" || col + width > right || g.chars().any(char::is_control) {
+                for _ in 0..width.min(right - col) {
+                    self.set(row, col, " ", style);
+                    col += 1;
+                }
+                continue;
+            }
+
+            self.set(row, col, g, style);
+            for i in 1..width {
+                self.set(row, col + i, "", style);
+            }
+            col += width;
+        }
+
+        col
+    }
+
+    /// Cells that differ from `prev`, row by row. Every cell differs when the
+    /// grids are not the same size
+    pub fn changes<'a>(&'a self, prev: &'a Grid) -> impl Iterator<Item = (usize, usize, &'a Cell)> {
+        let resized = self.rows != prev.rows || self.cols != prev.cols;
+
+        self.cells
+            .iter()
+            .enumerate()
+            .filter(move |(i, cell)| resized || prev.cells[*i] != **cell)
+            .map(move |(i, cell)| (i / self.cols, i % self.cols, cell))
+    }
+}
@@ -0,0 +1,282 @@
+//! Arrangement of windows on screen as a tree of splits. Each leaf is the id
+//! of a window, each split divides its area between two subtrees with a one
+//! cell separator between them
+
+/// Area of the screen, in cells
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Rect {
+    pub row: usize,
+    pub col: usize,
+    pub rows: usize,
+    pub cols: usize,
+}
+
+impl Rect {
+    pub fn bottom(&self) -> usize {
+        self.row + self.rows
+    }
+
+    pub fn right(&self) -> usize {
+        self.col + self.cols
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// one above the other, as `:split`
+    Horizontal,
+    /// side by side, as `:vsplit`
+    Vertical,
+}
+
+/// windows with their area on screen, and the separators between them
+pub type Arrangement = (Vec<(usize, Rect)>, Vec<(Direction, Rect)>);
+
+#[derive(Debug)]
+pub enum Layout {
+    Window(usize),
+    Split {
+        direction: Direction,
+        /// share of the space given to `first`
+        ratio: f32,
+        first: Box<Layout>,
+        second: Box<Layout>,
+    },
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout::Window(0)
+    }
+}
+
+/// cells given to the first side of a split of `total` cells, leaving one for
+/// the separator and at least one for each side where there is room
+fn first_size(total: usize, ratio: f32) -> usize {
+    let avail = total.saturating_sub(1);
+    let size = (avail as f32 * ratio).round() as usize;
+
+    match avail {
+        0 | 1 => avail,
+        _ => size.clamp(1, avail - 1),
+    }
+}
+
+fn divide(area: Rect, direction: Direction, ratio: f32) -> (Rect, Rect, Rect) {
+    match direction {
+        Direction::Horizontal => {
+            let size = first_size(area.rows, ratio);
+            let first = Rect { rows: size, ..area };
+            let separator = Rect {
+                row: area.row + size,
+                rows: area.rows.min(1),
+                ..area
+            };
+            let second = Rect {
+                row: separator.bottom(),
+                rows: area.bottom().saturating_sub(separator.bottom()),
+                ..area
+            };
+            (first, separator, second)
+        }
+        Direction::Vertical => {
+            let size = first_size(area.cols, ratio);
+            let first = Rect { cols: size, ..area };
+            let separator = Rect {
+                col: area.col + size,
+                cols: area.cols.min(1),
+                ..area
+            };
+            let second = Rect {
+                col: separator.right(),
+                cols: area.right().saturating_sub(separator.right()),
+                ..area
+            };
+            (first, separator, second)
+        }
+    }
+}
+
+impl Layout {
+    /// Area of each window within `area`, in order from the top left, and
+    /// the separators between them
+    pub fn arrange(&self, area: Rect) -> Arrangement {
+        let mut windows = Vec::new();
+        let mut separators = Vec::new();
+
+        self.arrange_into(area, &mut windows, &mut separators);
+
+        (windows, separators)
+    }
+
+    fn arrange_into(
+        &self,
+        area: Rect,
+        windows: &mut Vec<(usize, Rect)>,
+        separators: &mut Vec<(Direction, Rect)>,
+    ) {
+        match self {
+            Layout::Window(id) => windows.push((*id, area)),
+            Layout::Split {
+                direction,
+                ratio,
+                first,
+                second,
+            } => {
+                let (a, separator, b) = divide(area, *direction, *ratio);
+
+                first.arrange_into(a, windows, separators);
+                separators.push((*direction, separator));
+                second.arrange_into(b, windows, separators);
+            }
+        }
+    }
+
+    fn contains(&self, id: usize) -> bool {
+        match self {
+            Layout::Window(window) => *window == id,
+            Layout::Split { first, second, .. } => first.contains(id) || second.contains(id),
+        }
+    }
+
+    /// Split window `id` in two, putting window `new` above or left of it
+    pub fn split(&mut self, id: usize, new: usize, direction: Direction) {
+        match self {
+            Layout::Window(window) if *window == id => {
+                *self = Layout::Split {
+                    direction,
+                    ratio: 0.5,
+                    first: Box::new(Layout::Window(new)),
+                    second: Box::new(Layout::Window(id)),
+                };
+            }
+            Layout::Window(_) => (),
+            Layout::Split { first, second, .. } => {
+                first.split(id, new, direction);
+                second.split(id, new, direction);
+            }
+        }
+    }
+
+    /// Remove window `id`, giving its space to its sibling. Ids above it move
+    /// down by one to stay in step with the list of windows
+    pub fn remove(&mut self, id: usize) {
+        self.remove_window(id);
+        self.renumber(id);
+    }
+
+    fn remove_window(&mut self, id: usize) {
+        if let Layout::Split { first, second, .. } = self {
+            let sibling = match (first.as_ref(), second.as_ref()) {
+                (Layout::Window(w), _) if *w == id => std::mem::take(second),
+                (_, Layout::Window(w)) if *w == id => std::mem::take(first),
+                _ => {
+                    first.remove_window(id);
+                    second.remove_window(id);
+                    return;
+                }
+            };
+
+            *self = *sibling;
+        }
+    }
+
+    fn renumber(&mut self, removed: usize) {
+        match self {
+            Layout::Window(window) if *window > removed => *window -= 1,
+            Layout::Window(_) => (),
+            Layout::Split { first, second, .. } => {
+                first.renumber(removed);
+                second.renumber(removed);
+            }
+        }
+    }
+
+    /// Grow window `id` by `delta` cells in `direction`, taking them from
+    /// its neighbour in the nearest split that way
+    pub fn resize(&mut self, id: usize, direction: Direction, delta: isize, area: Rect) {
+        if let Layout::Split {
+            direction: dir,
+            ratio,
+            first,
+            second,
+        } = self
+        {
+            let (a, _, b) = divide(area, *dir, *ratio);
+            let in_first = first.contains(id);
+
+            // a nested split the same way is closer to the window
+            let nested = match in_first {
+                true => first.has_split(id, direction),
+                false => second.has_split(id, direction),
+            };
+
+            if *dir == direction && !nested {
+                let (size, total) = match direction {
+                    Direction::Horizontal => (a.rows, area.rows),
+                    Direction::Vertical => (a.cols, area.cols),
+                };
+                let delta = if in_first { delta } else { -delta };
+                let size = (size as isize).saturating_add(delta).max(0) as usize;
+                let avail = total.saturating_sub(1).max(1);
+
+                *ratio = (size as f32 / avail as f32).clamp(0.0, 1.0);
+            } else if in_first {
+                first.resize(id, direction, delta, a);
+            } else {
+                second.resize(id, direction, delta, b);
+            }
+        }
+    }
+
+    /// whether there is a split in `direction` above window `id`
+    fn has_split(&self, id: usize, direction: Direction) -> bool {
+        match self {
+            Layout::Window(_) => false,
+            Layout::Split {
+                direction: dir,
+                first,
+                second,
+                ..
+            } => {
+                (*dir == direction && self.contains(id))
+                    || first.has_split(id, direction)
+                    || second.has_split(id, direction)
+            }
+        }
+    }
+
+    /// windows side by side in `direction`, splits the other way count as one
+    fn count(&self, direction: Direction) -> usize {
+        match self {
+            Layout::Window(_) => 1,
+            Layout::Split {
+                direction: dir,
+                first,
+                second,
+                ..
+            } => match *dir == direction {
+                true => first.count(direction) + second.count(direction),
+                false => first.count(direction).max(second.count(direction)),
+            },
+        }
+    }
+
+    /// give every window in a row or column the same space
+    pub fn equalize(&mut self) {
+        if let Layout::Split {
+            direction,
+            ratio,
+            first,
+            second,
+        } = self
+        {
+            let a = first.count(*direction) as f32;
+            let b = second.count(*direction) as f32;
+            *ratio = a / (a + b);
+
+            first.equalize();
+            second.equalize();
+        }
+    }
+}
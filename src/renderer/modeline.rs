@@ -0,0 +1,241 @@
+//! The modeline, a row of segments such as the mode, file name or cursor
+//! position on the left, centre and right of the bottom row. Which segments
+//! go where is set in the `[modeline]` table of the config file:
+//!
+//! ```toml
+//! [modeline]
+//! left = ["mode", "filename", "modified"]
+//! centre = ["branch"]
+//! right = ["diagnostics", "filetype", "position", "percentage"]
+//! ```
+//!
+//! Segments with nothing to show, such as `modified` for a saved buffer,
+//! are left out. Where they do not fit the centre goes first, then the
+//! right segments from the first, then the start of the file name or all
+//! of it. Each segment is styled by the `ui.modeline.{segment}` scope of
+//! the theme
+
+use ropey::RopeSlice;
+
+use super::grid::Style;
+use super::theme::Theme;
+use super::widgets::{Line, Span};
+use crate::modes::Cursor;
+use crate::text;
+
+/// drawn at either end of the modeline
+const LEFT_END: &str = "|> ";
+const RIGHT_END: &str = " <|";
+
+/// Something the modeline can show
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Segment {
+    Mode,
+    FileName,
+    /// `[+]` while the buffer has unsaved changes
+    Modified,
+    /// line and column of the cursor
+    Position,
+    /// how far through the buffer the cursor is
+    Percentage,
+    FileType,
+    Encoding,
+    LineEnding,
+    /// git branch of the directory of the file
+    Branch,
+    /// syntax errors in the buffer
+    Diagnostics,
+}
+
+impl Segment {
+    const ALL: [Segment; 10] = [
+        Segment::Mode,
+        Segment::FileName,
+        Segment::Modified,
+        Segment::Position,
+        Segment::Percentage,
+        Segment::FileType,
+        Segment::Encoding,
+        Segment::LineEnding,
+        Segment::Branch,
+        Segment::Diagnostics,
+    ];
+
+    /// name in the config file and of the theme scope
+    pub fn name(&self) -> &'static str {
+        match self {
+            Segment::Mode => "mode",
+            Segment::FileName => "filename",
+            Segment::Modified => "modified",
+            Segment::Position => "position",
+            Segment::Percentage => "percentage",
+            Segment::FileType => "filetype",
+            Segment::Encoding => "encoding",
+            Segment::LineEnding => "lineending",
+            Segment::Branch => "branch",
+            Segment::Diagnostics => "diagnostics",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|segment| segment.name() == name)
+    }
+
+    /// text of the segment, empty when there is nothing to show
+    fn text(&self, status: &Status) -> String {
+        let Cursor { row, col } = status.cursor;
+
+        match self {
+            Segment::Mode => status.mode.to_string(),
+            Segment::FileName => status.name.clone(),
+            Segment::Modified if status.modified => "[+]".to_string(),
+            Segment::Modified => String::new(),
+            Segment::Position => format!("{}:{}", row + 1, col + 1),
+            Segment::Percentage => format!("{}%", (row + 1) * 100 / status.lines.max(1)),
+            Segment::FileType => status.filetype.unwrap_or_default().to_string(),
+            Segment::Encoding => status.encoding.to_string(),
+            Segment::LineEnding => status.line_ending.to_string(),
+            Segment::Branch => status.branch.clone().unwrap_or_default(),
+            Segment::Diagnostics if status.errors > 0 => format!("E:{}", status.errors),
+            Segment::Diagnostics => String::new(),
+        }
+    }
+}
+
+/// Segments shown on each side of the modeline
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Segments {
+    pub left: Vec<Segment>,
+    pub centre: Vec<Segment>,
+    pub right: Vec<Segment>,
+}
+
+impl Default for Segments {
+    fn default() -> Self {
+        Self {
+            left: vec![Segment::Mode, Segment::Modified],
+            centre: Vec::new(),
+            right: vec![Segment::Position],
+        }
+    }
+}
+
+/// What the segments show, for the active buffer
+#[derive(Clone, Debug, Default)]
+pub struct Status {
+    pub mode: &'static str,
+    pub name: String,
+    pub modified: bool,
+    pub cursor: Cursor,
+    pub lines: usize,
+    pub filetype: Option<&'static str>,
+    pub encoding: &'static str,
+    pub line_ending: &'static str,
+    pub branch: Option<String>,
+    /// syntax errors in the buffer
+    pub errors: usize,
+}
+
+/// A segment's text and style
+struct Part {
+    segment: Segment,
+    text: String,
+    style: Style,
+}
+
+impl Part {
+    fn width(&self) -> usize {
+        text::width(RopeSlice::from(self.text.as_str()))
+    }
+}
+
+/// The modeline for `status`, `width` columns wide
+pub fn line(status: &Status, segments: &Segments, theme: &Theme, width: usize) -> Line {
+    let base = theme.get("ui.modeline");
+    let parts = |segments: &[Segment]| -> Vec<Part> {
+        segments
+            .iter()
+            .map(|segment| Part {
+                segment: *segment,
+                text: segment.text(status),
+                style: theme.get(&format!("ui.modeline.{}", segment.name())),
+            })
+            .filter(|part| !part.text.is_empty())
+            .collect()
+    };
+
+    let mut left = parts(&segments.left);
+    let mut centre = parts(&segments.centre);
+    let mut right = parts(&segments.right);
+
+    let ends = LEFT_END.len() + RIGHT_END.len();
+    let used = |parts: &[Part]| -> usize {
+        let gaps = parts.len().saturating_sub(1);
+        parts.iter().map(Part::width).sum::<usize>() + gaps
+    };
+    let fits = |left: &[Part], centre: &[Part], right: &[Part]| {
+        let gaps = match centre.is_empty() {
+            true => 1,
+            false => 2,
+        };
+        ends + used(left) + used(centre) + used(right) + gaps <= width
+    };
+
+    if !fits(&left, &centre, &right) {
+        centre.clear();
+    }
+    while right.len() > 1 && !fits(&left, &centre, &right) {
+        right.remove(0);
+    }
+    if !fits(&left, &centre, &right) {
+        let over = (ends + used(&left) + used(&right) + 1).saturating_sub(width);
+        let name = left.iter().position(|p| p.segment == Segment::FileName);
+
+        match name {
+            // too little of the name would be left to be of use
+            Some(i) if left[i].width() <= over + 1 => {
+                left.remove(i);
+            }
+            Some(i) => left[i].text = shorten(&left[i].text, over),
+            None => (),
+        }
+    }
+
+    let mut spans = vec![Span::styled(LEFT_END.to_string(), base)];
+    let mut col = LEFT_END.len();
+    let push = |spans: &mut Vec<Span>, parts: Vec<Part>, col: &mut usize| {
+        for (i, part) in parts.into_iter().enumerate() {
+            if i > 0 {
+                spans.push(Span::styled(" ".to_string(), base));
+                *col += 1;
+            }
+            *col += part.width();
+            spans.push(Span::styled(part.text, part.style));
+        }
+    };
+
+    let right_width = used(&right) + RIGHT_END.len();
+    push(&mut spans, left, &mut col);
+
+    if !centre.is_empty() {
+        // centred on the screen, unless that would run into the left side
+        let start = (width.saturating_sub(used(&centre)) / 2).max(col + 1);
+        spans.push(Span::styled(" ".repeat(start - col), base));
+        col = start;
+        push(&mut spans, centre, &mut col);
+    }
+
+    let gap = width.saturating_sub(col + right_width).max(1);
+    spans.push(Span::styled(" ".repeat(gap), base));
+    push(&mut spans, right, &mut col);
+    spans.push(Span::styled(RIGHT_END.to_string(), base));
+
+    Line::clipped(spans, 0, width)
+}
+
+/// `text` less `over` chars from its start, marked with a `<`
+fn shorten(text: &str, over: usize) -> String {
+    let rest: String = text.chars().skip(over + 1).collect();
+
+    format!("<{}", rest)
+}
@@ -0,0 +1,397 @@
+//! Colour schemes, giving a style to named scopes such as `ui.modeline` or
+//! `syntax.keyword`. A scope a theme leaves out takes the style of the scope
+//! before its last dot, `syntax.function.method` that of `syntax.function`,
+//! and no style at all past the first part.
+//!
+//! Themes are TOML files, looked for in `themes` under the config directory
+//! and then among those bundled with the editor:
+//!
+//! ```toml
+//! inherits = "default"
+//!
+//! [palette]
+//! orange = "#fe8019"
+//!
+//! [ui]
+//! modeline = { fg = "black", bg = "orange", attr = "bold" }
+//!
+//! [syntax]
+//! keyword = "orange"
+//! ```
+//!
+//! A table sets the style of its own scope with `fg`, `bg` and `attr`, other
+//! keys are scopes inside it and a plain colour sets the foreground. Colours
+//! are names, `#rrggbb`, 256 colour indices or palette entries. Those the
+//! terminal can not show are swapped for the nearest it can
+
+use crossterm::style::{Attribute, Color};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use super::grid::Style;
+use crate::config::config_dir;
+
+/// themes built into the editor, by name
+const BUNDLED: &[(&str, &str)] = &[
+    ("default", include_str!("../../themes/default.toml")),
+    ("gruvbox", include_str!("../../themes/gruvbox.toml")),
+];
+
+/// Colours a terminal can show
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorSupport {
+    TrueColor,
+    Ansi256,
+    Ansi16,
+}
+
+impl ColorSupport {
+    /// what the terminal claims through `COLORTERM` and `TERM`
+    pub fn detect() -> Self {
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        let term = env::var("TERM").unwrap_or_default();
+
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            ColorSupport::TrueColor
+        } else if term.contains("256") {
+            ColorSupport::Ansi256
+        } else {
+            ColorSupport::Ansi16
+        }
+    }
+
+    /// the nearest colour to `color` the terminal can show
+    pub fn fit(self, color: Color) -> Color {
+        match (self, color) {
+            (ColorSupport::TrueColor, _) => color,
+            (ColorSupport::Ansi256, Color::Rgb { r, g, b }) => {
+                Color::AnsiValue(nearest_256(r, g, b))
+            }
+            (ColorSupport::Ansi16, Color::Rgb { r, g, b }) => nearest_16(r, g, b),
+            (ColorSupport::Ansi16, Color::AnsiValue(n)) => {
+                let (r, g, b) = ansi_rgb(n);
+                nearest_16(r, g, b)
+            }
+            _ => color,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Theme {
+    name: String,
+    scopes: HashMap<String, Style>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::load("default", ColorSupport::TrueColor).expect("bundled default theme is valid")
+    }
+}
+
+impl Theme {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// style of `scope`, or of the nearest scope around it the theme sets
+    pub fn get(&self, scope: &str) -> Style {
+        let mut scope = scope;
+
+        loop {
+            if let Some(style) = self.scopes.get(scope) {
+                return *style;
+            }
+
+            match scope.rfind('.') {
+                Some(i) => scope = &scope[..i],
+                None => return Style::default(),
+            }
+        }
+    }
+
+    /// Find the theme called `name`, in the config directory first and then
+    /// among the bundled themes
+    pub fn load(name: &str, colors: ColorSupport) -> Result<Self, String> {
+        Self::load_inheriting(name, colors, &mut Vec::new())
+    }
+
+    fn load_inheriting(
+        name: &str,
+        colors: ColorSupport,
+        seen: &mut Vec<String>,
+    ) -> Result<Self, String> {
+        if seen.iter().any(|s| s == name) {
+            return Err(format!("Theme {} inherits from itself", name));
+        }
+        seen.push(name.to_string());
+
+        let user = themes_dir()
+            .and_then(|dir| fs::read_to_string(dir.join(format!("{}.toml", name))).ok());
+        let bundled = BUNDLED
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, text)| text.to_string());
+
+        let text = user
+            .or(bundled)
+            .ok_or_else(|| format!("Cannot find color scheme '{}'", name))?;
+
+        Self::parse(name, &text, colors, seen)
+    }
+
+    /// Read a theme from the text of its file, with the styles it inherits
+    /// loaded first
+    fn parse(
+        name: &str,
+        text: &str,
+        colors: ColorSupport,
+        seen: &mut Vec<String>,
+    ) -> Result<Self, String> {
+        let error = |e: String| format!("Error in theme {}: {}", name, e);
+
+        let mut table: toml::Table = text
+            .parse()
+            .map_err(|e: toml::de::Error| error(e.message().to_string()))?;
+
+        let mut theme = match table.remove("inherits") {
+            Some(toml::Value::String(parent)) => Self::load_inheriting(&parent, colors, seen)?,
+            Some(_) => return Err(error("inherits must be a theme name".to_string())),
+            None => Self {
+                name: String::new(),
+                scopes: HashMap::new(),
+            },
+        };
+        theme.name = name.to_string();
+
+        let palette = match table.remove("palette") {
+            Some(toml::Value::Table(palette)) => palette
+                .into_iter()
+                .map(|(key, value)| match value {
+                    toml::Value::String(color) => Ok((key, parse_color(&color, &HashMap::new())?)),
+                    _ => Err(format!("palette entry {} must be a colour", key)),
+                })
+                .collect::<Result<HashMap<_, _>, _>>()
+                .map_err(error)?,
+            Some(_) => return Err(error("palette must be a table".to_string())),
+            None => HashMap::new(),
+        };
+
+        let mut styles = Vec::new();
+        for (key, value) in &table {
+            read_scope(key, value, &palette, &mut styles).map_err(error)?;
+        }
+
+        for (scope, style) in styles {
+            let fit = |color: Option<Color>| color.map(|color| colors.fit(color));
+            let style = Style {
+                fg: fit(style.fg),
+                bg: fit(style.bg),
+                attr: style.attr,
+            };
+            theme.scopes.insert(scope, style);
+        }
+
+        Ok(theme)
+    }
+}
+
+/// names of the themes that can be loaded, for completion
+pub fn available() -> Vec<String> {
+    let mut names: Vec<String> = BUNDLED.iter().map(|(name, _)| name.to_string()).collect();
+
+    if let Some(entries) = themes_dir().and_then(|dir| fs::read_dir(dir).ok()) {
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "toml") {
+                if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+    }
+
+    names.sort();
+    names.dedup();
+    names
+}
+
+fn themes_dir() -> Option<PathBuf> {
+    Some(config_dir()?.join("themes"))
+}
+
+/// Collect the styles set by `value` for `scope` and the scopes inside it
+fn read_scope(
+    scope: &str,
+    value: &toml::Value,
+    palette: &HashMap<String, Color>,
+    styles: &mut Vec<(String, Style)>,
+) -> Result<(), String> {
+    let table = match value {
+        toml::Value::String(color) => {
+            let style = Style {
+                fg: Some(parse_color(color, palette)?),
+                ..Default::default()
+            };
+            styles.push((scope.to_string(), style));
+            return Ok(());
+        }
+        toml::Value::Table(table) => table,
+        _ => return Err(format!("{} must be a colour or a table", scope)),
+    };
+
+    let color = |key: &str| match table.get(key) {
+        Some(toml::Value::String(color)) => parse_color(color, palette).map(Some),
+        Some(_) => Err(format!("{}.{} must be a colour", scope, key)),
+        None => Ok(None),
+    };
+
+    if ["fg", "bg", "attr"]
+        .iter()
+        .any(|key| table.contains_key(*key))
+    {
+        let attr = match table.get("attr") {
+            Some(toml::Value::String(attr)) => Some(parse_attr(attr)?),
+            Some(_) => return Err(format!("{}.attr must be a string", scope)),
+            None => None,
+        };
+
+        let style = Style {
+            fg: color("fg")?,
+            bg: color("bg")?,
+            attr,
+        };
+        styles.push((scope.to_string(), style));
+    }
+
+    for (key, value) in table {
+        if !matches!(key.as_str(), "fg" | "bg" | "attr") {
+            read_scope(&format!("{}.{}", scope, key), value, palette, styles)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_color(text: &str, palette: &HashMap<String, Color>) -> Result<Color, String> {
+    if let Some(color) = palette.get(text) {
+        return Ok(*color);
+    }
+
+    if let Some(hex) = text.strip_prefix('#') {
+        let channel = |i: usize| {
+            hex.get(i..i + 2)
+                .and_then(|c| u8::from_str_radix(c, 16).ok())
+        };
+
+        return match (hex.len(), channel(0), channel(2), channel(4)) {
+            (6, Some(r), Some(g), Some(b)) => Ok(Color::Rgb { r, g, b }),
+            _ => Err(format!("Invalid colour: {}", text)),
+        };
+    }
+
+    if let Ok(index) = text.parse::<u8>() {
+        return Ok(Color::AnsiValue(index));
+    }
+
+    match text {
+        "reset" | "default" => Ok(Color::Reset),
+        _ => Color::try_from(text).map_err(|_| format!("Unknown colour: {}", text)),
+    }
+}
+
+fn parse_attr(text: &str) -> Result<Attribute, String> {
+    match text {
+        "bold" => Ok(Attribute::Bold),
+        "dim" => Ok(Attribute::Dim),
+        "italic" => Ok(Attribute::Italic),
+        "underline" | "underlined" => Ok(Attribute::Underlined),
+        "reverse" | "reversed" => Ok(Attribute::Reverse),
+        "crossed_out" => Ok(Attribute::CrossedOut),
+        _ => Err(format!("Unknown attribute: {}", text)),
+    }
+}
+
+/// levels of each channel in the 6x6x6 colour cube of the 256 colours
+const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// usual values of the 16 colours, which terminals are free to change
+const ANSI_16: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (128, 0, 0),
+    (0, 128, 0),
+    (128, 128, 0),
+    (0, 0, 128),
+    (128, 0, 128),
+    (0, 128, 128),
+    (192, 192, 192),
+    (128, 128, 128),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (0, 0, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// the 16 colours by index, in the order of [`ANSI_16`]
+const NAMED: [Color; 16] = [
+    Color::Black,
+    Color::DarkRed,
+    Color::DarkGreen,
+    Color::DarkYellow,
+    Color::DarkBlue,
+    Color::DarkMagenta,
+    Color::DarkCyan,
+    Color::Grey,
+    Color::DarkGrey,
+    Color::Red,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::White,
+];
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let d = |a: u8, b: u8| (i32::from(a) - i32::from(b)).unsigned_abs().pow(2);
+    d(r1, r2) + d(g1, g2) + d(b1, b2)
+}
+
+/// value of a 256 colour index
+fn ansi_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => ANSI_16[usize::from(index)],
+        16..=231 => {
+            let i = index - 16;
+            (
+                CUBE[usize::from(i / 36)],
+                CUBE[usize::from(i / 6 % 6)],
+                CUBE[usize::from(i % 6)],
+            )
+        }
+        _ => {
+            let level = 8 + (index - 232) * 10;
+            (level, level, level)
+        }
+    }
+}
+
+/// nearest of the colour cube and grey ramp of the 256 colours, leaving
+/// out the first 16 as terminals change them
+fn nearest_256(r: u8, g: u8, b: u8) -> u8 {
+    (16..=255)
+        .min_by_key(|i| distance(ansi_rgb(*i), (r, g, b)))
+        .unwrap_or(16)
+}
+
+fn nearest_16(r: u8, g: u8, b: u8) -> Color {
+    let i = (0..16)
+        .min_by_key(|i| distance(ANSI_16[*i], (r, g, b)))
+        .unwrap_or(0);
+    NAMED[i]
+}
@@ -1,17 +1,19 @@
-use crossterm::cursor::MoveTo;
-use crossterm::style::{
-    Attribute, Color, Colors, Print, SetAttribute, SetBackgroundColor, SetForegroundColor,
-};
+use crossterm::style::{Attribute, Color};
 use log::info;
-use std::io::{self, Error, Stdout, Write};
+use std::ops::Range;
 use std::str::FromStr;
 
+use super::grid::{Grid, Style};
+use super::layout::Rect;
 use super::RendResult;
-use crossterm::{ExecutableCommand, QueueableCommand};
+use crate::text::{grapheme_width, width};
+use unicode_segmentation::UnicodeSegmentation;
 
+/// Where a widget draws, the frame being built and the area of it the
+/// widget owns
 #[derive(Debug)]
 pub struct UiCtx<'a> {
-    pub stdout: &'a mut Stdout,
+    pub grid: &'a mut Grid,
     pub row_start: u16,
     pub row_end: u16,
     pub col_start: u16,
@@ -19,13 +21,7 @@ pub struct UiCtx<'a> {
 }
 
 pub trait Widget {
-    type Components;
-
     fn render(&mut self, ui_ctx: &mut UiCtx) -> RendResult;
-
-    fn did_update(&self) -> bool;
-
-    fn update(&mut self, cb: impl FnMut(&mut Self::Components));
 }
 
 #[derive(Debug)]
@@ -52,14 +48,64 @@ impl Span {
         }
     }
 
-    pub fn style(mut self, atrr: Attribute) -> Self {
-        self.style = Some(atrr);
-        self
+    fn cell_style(&self) -> Style {
+        Style {
+            fg: self.fg,
+            bg: self.bg,
+            attr: self.style,
+        }
+    }
+
+    pub fn styled(msg: String, style: Style) -> Self {
+        Self {
+            msg,
+            style: style.attr,
+            fg: style.fg,
+            bg: style.bg,
+        }
     }
 
-    pub fn color(mut self, col: Color) -> Self {
-        self.fg = Some(col);
-        self
+    /// Split text into spans styled by `highlights`, char ranges paired with
+    /// a style where the parts of the style set by later ranges are drawn
+    /// over earlier ones. A range past the end of the text styles an extra
+    /// cell for the line break
+    pub fn highlight(text: &str, highlights: &[(Range<usize>, Style)]) -> Vec<Span> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut styles = vec![Style::default(); chars.len() + 1];
+
+        for (range, style) in highlights {
+            let end = range.end.min(styles.len());
+            let start = range.start.min(end);
+            styles[start..end].iter_mut().for_each(|s| {
+                *s = Style {
+                    fg: style.fg.or(s.fg),
+                    bg: style.bg.or(s.bg),
+                    attr: style.attr.or(s.attr),
+                }
+            });
+        }
+
+        let mut spans = Vec::new();
+        let mut msg = String::new();
+        let mut current = Style::default();
+
+        for (i, style) in styles.into_iter().enumerate() {
+            let c = match chars.get(i) {
+                Some(c) => *c,
+                None if style != Style::default() => ' ',
+                None => break,
+            };
+
+            if style != current && !msg.is_empty() {
+                spans.push(Span::styled(std::mem::take(&mut msg), current));
+            }
+
+            current = style;
+            msg.push(c);
+        }
+
+        spans.push(Span::styled(msg, current));
+        spans
     }
 }
 
@@ -75,72 +121,74 @@ impl FromStr for Span {
 }
 
 impl Widget for Line {
-    type Components = Vec<Span>;
-
-    fn did_update(&self) -> bool {
-        self.did_update
-    }
-
     fn render(&mut self, ui_ctx: &mut UiCtx) -> RendResult {
-        if !self.did_update() {
-            return Ok(());
+        let row = ui_ctx.row_start as usize;
+        let right = ui_ctx.col_end as usize;
+        let mut col = ui_ctx.col_start as usize;
+
+        for span in &self.spans {
+            col = ui_ctx
+                .grid
+                .put_str(row, col, right, &span.msg, span.cell_style());
         }
 
-        let r = &mut ui_ctx.stdout;
-
-        // TODO simple clear for now
-        // r.queue(MoveTo(ui_ctx.row_start, ui_ctx.col_start))?;
-        r.queue(MoveTo(ui_ctx.col_start, ui_ctx.row_start))?;
-        // r.queue(Print(format!(
-        //     "{:>width$}",
-        //     " ",
-        //     width = (ui_ctx.col_end - ui_ctx.col_start) as usize
-        // )))?;
-
-        self.spans.iter().for_each(|Span { fg, bg, msg, style }| {
-            fg.map(|c| r.queue(SetForegroundColor(c)).unwrap());
-            bg.map(|c| r.queue(SetBackgroundColor(c)).unwrap());
-            style.map(|c| r.queue(SetAttribute(c)).unwrap());
-
-            r.queue(Print(msg)).unwrap();
-
-            fg.map(|_| r.queue(SetForegroundColor(Color::Black)).unwrap());
-            bg.map(|_| r.queue(SetBackgroundColor(Color::Reset)).unwrap());
-            style.map(|_| r.queue(SetAttribute(Attribute::Reset)).unwrap());
-        });
-
-        self.did_update = false;
-
         Ok(())
     }
-
-    fn update(&mut self, mut cb: impl FnMut(&mut Self::Components)) {
-        cb(&mut self.spans);
-        self.did_update = true;
-    }
 }
 
 #[derive(Default, Debug)]
 pub struct Line {
     pub spans: Vec<Span>,
-    did_update: bool,
 }
 
 impl Line {
     pub fn new(spans: Vec<Span>) -> Self {
-        Self {
-            spans,
-            did_update: true,
-        }
+        Self { spans }
     }
 
-    fn empty() -> Line {
-        let tilde = Span::new("~".to_string()).color(Color::Cyan);
+    /// Build a line from the display columns `left..left + cols` of `spans`,
+    /// with tabs expanded. A wide grapheme cut by either edge is replaced
+    /// with spaces so columns stay aligned
+    pub fn clipped(spans: Vec<Span>, left: usize, cols: usize) -> Line {
+        let right = left + cols;
+        let mut col = 0;
+        let mut clipped = Vec::with_capacity(spans.len());
+
+        for span in spans {
+            let mut msg = String::new();
+
+            for g in span.msg.graphemes(true) {
+                let width = grapheme_width(g);
+                let start = col;
+                col += width;
+
+                if col <= left || start >= right {
+                    continue;
+                }
+
+                if start < left || col > right {
+                    let visible = col.min(right) - start.max(left);
+                    msg.push_str(&" ".repeat(visible));
+                } else if g == "\t" {
+                    msg.push_str(&" ".repeat(width));
+                } else {
+                    msg.push_str(g);
+                }
+            }
 
-        Self {
-            did_update: true,
-            spans: vec![tilde],
+            if !msg.is_empty() {
+                clipped.push(Span { msg, ..span });
+            }
         }
+
+        Self::new(clipped)
+    }
+
+    /// row past the end of the buffer, marked with a `~` in `style`
+    fn empty(style: Style) -> Line {
+        let tilde = Span::styled("~".to_string(), style);
+
+        Self::new(vec![tilde])
     }
 }
 
@@ -156,66 +204,155 @@ impl FromStr for Line {
 #[derive(Default, Debug)]
 pub struct List {
     pub lines: Vec<Line>,
-    did_update: bool,
 }
 
 impl List {
     pub fn new(lines: Vec<Line>) -> Self {
-        Self {
-            lines,
-            did_update: true,
-        }
+        Self { lines }
     }
 
     // expand current list to specified number of rows
-    pub fn expand(&mut self, size: usize) {
+    pub fn expand(&mut self, size: usize, tilde: Style) {
         let current_size = self.lines.len();
         info!("expanding from {} to {}", current_size, size);
 
         if current_size < size {
             for _ in 0..(size - current_size) {
-                self.lines.push(Line::empty())
+                self.lines.push(Line::empty(tilde))
             }
         }
     }
 }
 
 impl Widget for List {
-    type Components = Vec<Line>;
-
     fn render(&mut self, ui_ctx: &mut UiCtx) -> RendResult {
+        let top = ui_ctx.row_start;
+
         for line in self.lines.iter_mut() {
-            line.render(ui_ctx).unwrap();
+            if ui_ctx.row_start >= ui_ctx.row_end {
+                break;
+            }
+
+            line.render(ui_ctx)?;
             ui_ctx.row_start += 1;
-            ui_ctx.row_end += 1;
         }
 
+        ui_ctx.row_start = top;
+
         Ok(())
     }
+}
 
-    fn did_update(&self) -> bool {
-        self.did_update
+/// Box drawn over the windows with a title in its top border and a list of
+/// lines, one of them highlighted when `selected`
+#[derive(Debug, PartialEq, Eq)]
+pub struct Popup {
+    title: String,
+    lines: Vec<String>,
+    selected: Option<usize>,
+    style: Style,
+    selected_style: Style,
+}
+
+impl Popup {
+    pub fn new(title: String, lines: Vec<String>, selected: Option<usize>) -> Self {
+        Self {
+            title,
+            lines,
+            selected,
+            style: Style::default(),
+            selected_style: Style {
+                attr: Some(Attribute::Reverse),
+                ..Default::default()
+            },
+        }
     }
 
-    fn update(&mut self, mut cb: impl FnMut(&mut Self::Components)) {
-        cb(&mut self.lines);
-        self.did_update = true;
+    /// draw the popup in `style` with the selected line in `selected`
+    pub fn styled(self, style: Style, selected: Style) -> Self {
+        Self {
+            style,
+            selected_style: selected,
+            ..self
+        }
+    }
+
+    /// Centre the popup in `area`, sized to fit its lines with a border and
+    /// a column of padding either side, but no bigger than `area`
+    pub fn rect(&self, area: Rect) -> Rect {
+        let widest = self
+            .lines
+            .iter()
+            .chain(std::iter::once(&self.title))
+            .map(|line| width(line.as_str().into()))
+            .max()
+            .unwrap_or_default();
+
+        let rows = (self.lines.len() + 2).min(area.rows);
+        let cols = (widest + 4).min(area.cols);
+
+        Rect {
+            row: area.row + (area.rows - rows) / 2,
+            col: area.col + (area.cols - cols) / 2,
+            rows,
+            cols,
+        }
+    }
+
+    /// index of the first line shown when `rows` fit, keeping the selected
+    /// line in view
+    fn top(&self, rows: usize) -> usize {
+        self.selected
+            .map_or(0, |selected| (selected + 1).saturating_sub(rows))
     }
-}
 
-use crate::app::buffer::Lines;
+    /// row of the selected line inside a popup of `rows` rows, borders
+    /// included
+    pub fn selected_row(&self, rows: usize) -> Option<usize> {
+        let inner = rows.saturating_sub(2);
+        Some(1 + self.selected? - self.top(inner))
+    }
+}
 
-impl From<Lines<'_>> for List {
-    fn from(lines: Lines) -> Self {
-        let Lines { lines, empty } = lines;
+impl Widget for Popup {
+    fn render(&mut self, ui_ctx: &mut UiCtx) -> RendResult {
+        let (top, bottom) = (ui_ctx.row_start as usize, ui_ctx.row_end as usize);
+        let (left, right) = (ui_ctx.col_start as usize, ui_ctx.col_end as usize);
 
-        let mut list: Vec<Line> = Vec::new();
+        if bottom < top + 2 || right < left + 2 {
+            return Ok(());
+        }
 
-        for line in lines.lines() {
-            let l = Line::from_str(line.as_str().unwrap()).unwrap();
-            list.push(l);
+        let inner = right - left - 2;
+        let grid = &mut *ui_ctx.grid;
+        let style = self.style;
+
+        let title = format!(" {} ", self.title);
+        let col = grid.put_str(top, left, right, "┌", style);
+        let col = grid.put_str(top, col, right - 1, &title, style);
+        grid.put_str(top, col, right - 1, &"─".repeat(inner), style);
+        grid.put_str(top, right - 1, right, "┐", style);
+
+        let rows = bottom - top - 2;
+        let first = self.top(rows);
+
+        for row in 0..rows {
+            let screen_row = top + 1 + row;
+            let line = self.lines.get(first + row).map_or("", String::as_str);
+            let line_style = match Some(first + row) == self.selected {
+                true => self.selected_style,
+                false => style,
+            };
+
+            grid.put_str(screen_row, left, right, "│", style);
+            let padded = format!(" {:inner$}", line, inner = inner.saturating_sub(1));
+            grid.put_str(screen_row, left + 1, right - 1, &padded, line_style);
+            grid.put_str(screen_row, right - 1, right, "│", style);
         }
 
-        Self::new(list)
+        let bottom_border = format!("└{}┘", "─".repeat(inner));
+        grid.put_str(bottom - 1, left, right, &bottom_border, style);
+
+        Ok(())
     }
 }
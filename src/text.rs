@@ -0,0 +1,306 @@
+//! Helpers for working with rope text as user perceived characters
+//! (extended grapheme clusters) rather than chars or bytes
+
+use ropey::{str_utils::byte_to_char_idx, RopeSlice};
+use std::borrow::Cow;
+use std::ops::Range;
+use unicode_segmentation::{GraphemeCursor, GraphemeIncomplete};
+use unicode_width::UnicodeWidthStr;
+
+/// columns a tab is drawn across
+pub const TAB_WIDTH: usize = 4;
+
+/// char index of the grapheme boundary before `char_idx`
+pub fn prev_grapheme_boundary(slice: RopeSlice, char_idx: usize) -> usize {
+    let byte_idx = slice.char_to_byte(char_idx);
+
+    let (mut chunk, mut chunk_byte_idx, mut chunk_char_idx, _) = slice.chunk_at_byte(byte_idx);
+    let mut gc = GraphemeCursor::new(byte_idx, slice.len_bytes(), true);
+
+    loop {
+        match gc.prev_boundary(chunk, chunk_byte_idx) {
+            Ok(None) => return 0,
+            Ok(Some(n)) => return chunk_char_idx + byte_to_char_idx(chunk, n - chunk_byte_idx),
+            Err(GraphemeIncomplete::PrevChunk) => {
+                let (a, b, c, _) = slice.chunk_at_byte(chunk_byte_idx - 1);
+                chunk = a;
+                chunk_byte_idx = b;
+                chunk_char_idx = c;
+            }
+            Err(GraphemeIncomplete::PreContext(n)) => {
+                let ctx_chunk = slice.chunk_at_byte(n - 1).0;
+                gc.provide_context(ctx_chunk, n - ctx_chunk.len());
+            }
+            Err(e) => unreachable!("unexpected grapheme state {:?}", e),
+        }
+    }
+}
+
+/// char index of the grapheme boundary after `char_idx`
+pub fn next_grapheme_boundary(slice: RopeSlice, char_idx: usize) -> usize {
+    let byte_idx = slice.char_to_byte(char_idx);
+
+    let (mut chunk, mut chunk_byte_idx, mut chunk_char_idx, _) = slice.chunk_at_byte(byte_idx);
+    let mut gc = GraphemeCursor::new(byte_idx, slice.len_bytes(), true);
+
+    loop {
+        match gc.next_boundary(chunk, chunk_byte_idx) {
+            Ok(None) => return slice.len_chars(),
+            Ok(Some(n)) => return chunk_char_idx + byte_to_char_idx(chunk, n - chunk_byte_idx),
+            Err(GraphemeIncomplete::NextChunk) => {
+                chunk_byte_idx += chunk.len();
+                let (a, _, c, _) = slice.chunk_at_byte(chunk_byte_idx);
+                chunk = a;
+                chunk_char_idx = c;
+            }
+            Err(GraphemeIncomplete::PreContext(n)) => {
+                let ctx_chunk = slice.chunk_at_byte(n - 1).0;
+                gc.provide_context(ctx_chunk, n - ctx_chunk.len());
+            }
+            Err(e) => unreachable!("unexpected grapheme state {:?}", e),
+        }
+    }
+}
+
+/// Columns a single grapheme takes up in the terminal. Clusters are at most
+/// two columns wide, which is how terminals draw emoji sequences
+pub fn grapheme_width(g: &str) -> usize {
+    match g.as_bytes().first() {
+        None => 0,
+        Some(b'\t') => TAB_WIDTH,
+        Some(b) if b.is_ascii() => 1,
+        Some(_) => g.width().clamp(1, 2),
+    }
+}
+
+/// columns taken up by all graphemes in `slice`
+pub fn width(slice: RopeSlice) -> usize {
+    RopeGraphemes::new(slice).map(|g| grapheme_width(&g)).sum()
+}
+
+/// Iterator over the graphemes of a rope, borrowing from the rope where a
+/// grapheme sits inside a single chunk
+pub struct RopeGraphemes<'a> {
+    slice: RopeSlice<'a>,
+    pos: usize,
+}
+
+impl<'a> RopeGraphemes<'a> {
+    pub fn new(slice: RopeSlice<'a>) -> Self {
+        Self { slice, pos: 0 }
+    }
+}
+
+impl<'a> Iterator for RopeGraphemes<'a> {
+    type Item = Cow<'a, str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.slice.len_chars() {
+            return None;
+        }
+
+        let start = self.pos;
+        self.pos = next_grapheme_boundary(self.slice, start);
+
+        let g = self.slice.slice(start..self.pos);
+        Some(match g.as_str() {
+            Some(s) => Cow::Borrowed(s),
+            None => Cow::Owned(g.to_string()),
+        })
+    }
+}
+
+/// Room for text on soft wrapped rows. Rows after the first of a line start
+/// with a continuation marker and so have less of it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Wrap {
+    pub cols: usize,
+    /// columns taken by the continuation marker
+    pub marker: usize,
+}
+
+impl Wrap {
+    fn row_width(&self, row: usize) -> usize {
+        match row {
+            0 => self.cols,
+            _ => self.cols.saturating_sub(self.marker),
+        }
+        .max(1)
+    }
+}
+
+/// Char ranges of `line` (without its line break) shown on each screen row
+/// when soft wrapped. Rows break after whitespace where a word would not fit,
+/// words longer than a row are broken between graphemes
+pub fn wrap(line: RopeSlice, wrap: Wrap) -> Vec<Range<usize>> {
+    let mut rows = Vec::new();
+    let mut start = 0;
+    let mut pos = 0;
+    let mut used = 0;
+    // char index after the last whitespace on the current row
+    let mut brk = None;
+
+    for g in RopeGraphemes::new(line) {
+        let w = grapheme_width(&g);
+
+        while used + w > wrap.row_width(rows.len()) && pos > start {
+            let end = match brk {
+                Some(brk) if brk > start => brk,
+                _ => pos,
+            };
+
+            rows.push(start..end);
+            start = end;
+            used = width(line.slice(start..pos));
+            brk = None;
+        }
+
+        used += w;
+        pos += g.chars().count();
+
+        if g.chars().all(char::is_whitespace) {
+            brk = Some(pos);
+        }
+    }
+
+    rows.push(start..pos);
+    rows
+}
+
+/// Screen row within the wrapped `rows` of `line` and screen column of char
+/// column `col`. A cursor past the end of a full row goes on to the next
+pub fn wrap_pos(line: RopeSlice, wrap: Wrap, rows: &[Range<usize>], col: usize) -> (usize, usize) {
+    let row = rows.iter().rposition(|r| r.start <= col).unwrap_or(0);
+    let marker = if row > 0 { wrap.marker } else { 0 };
+    let screen_col = marker + width(line.slice(rows[row].start..col));
+
+    if wrap.cols > 0 && screen_col >= wrap.cols {
+        return (row + 1, wrap.marker);
+    }
+
+    (row, screen_col)
+}
+
+/// char column of the grapheme shown at `screen_col` on wrapped row `row`,
+/// the inverse of [`wrap_pos`]
+pub fn wrap_col(
+    line: RopeSlice,
+    wrap: Wrap,
+    rows: &[Range<usize>],
+    row: usize,
+    screen_col: usize,
+) -> usize {
+    let Range { start, end } = rows[row].clone();
+    let marker = if row > 0 { wrap.marker } else { 0 };
+    let target = screen_col.saturating_sub(marker);
+
+    let mut col = start;
+    let mut seen = 0;
+
+    for g in RopeGraphemes::new(line.slice(start..end)) {
+        seen += grapheme_width(&g);
+
+        if seen > target {
+            break;
+        }
+
+        col += g.chars().count();
+    }
+
+    // the end of a row that is not the last is the start of the next one
+    if col == end && row + 1 < rows.len() {
+        col = prev_grapheme_boundary(line, end);
+    }
+
+    col
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CharClass {
+    Whitespace,
+    Word,
+    Punctuation,
+}
+
+fn char_class(c: char) -> CharClass {
+    if c.is_whitespace() {
+        CharClass::Whitespace
+    } else if c.is_alphanumeric() || c == '_' {
+        CharClass::Word
+    } else {
+        CharClass::Punctuation
+    }
+}
+
+/// Char index of the start of the next word, as vi's `w`. An empty line
+/// counts as a word of its own
+pub fn next_word_start(slice: RopeSlice, char_idx: usize) -> usize {
+    let len = slice.len_chars();
+    let mut i = char_idx;
+
+    if i >= len {
+        return len;
+    }
+
+    let class = char_class(slice.char(i));
+
+    if class != CharClass::Whitespace {
+        while i < len && char_class(slice.char(i)) == class {
+            i += 1;
+        }
+    }
+
+    while i < len && slice.char(i).is_whitespace() {
+        if slice.char(i) == '\n' && i + 1 < len && slice.char(i + 1) == '\n' {
+            return i + 1;
+        }
+        i += 1;
+    }
+
+    i
+}
+
+/// Char index of the start of the word before `char_idx`, as vi's `b`
+pub fn prev_word_start(slice: RopeSlice, char_idx: usize) -> usize {
+    if char_idx == 0 {
+        return 0;
+    }
+
+    let mut i = char_idx - 1;
+
+    while i > 0 && slice.char(i).is_whitespace() {
+        i -= 1;
+    }
+
+    let class = char_class(slice.char(i));
+
+    while i > 0 && char_class(slice.char(i - 1)) == class {
+        i -= 1;
+    }
+
+    i
+}
+
+/// Char index of the last char of the next word end, as vi's `e`
+pub fn next_word_end(slice: RopeSlice, char_idx: usize) -> usize {
+    let len = slice.len_chars();
+    let mut i = char_idx + 1;
+
+    while i < len && slice.char(i).is_whitespace() {
+        i += 1;
+    }
+
+    if i >= len {
+        return len.saturating_sub(1);
+    }
+
+    let class = char_class(slice.char(i));
+
+    while i + 1 < len && char_class(slice.char(i + 1)) == class {
+        i += 1;
+    }
+
+    i
+}
+
+pub mod search;
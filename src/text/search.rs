@@ -0,0 +1,85 @@
+//! Regex search over rope text. The regex crate wants contiguous text, so
+//! the rope is searched a line at a time, borrowing each line when it sits
+//! in a single chunk rather than copying the whole text into a `String`.
+//! Matches never span a line break
+
+use regex::Regex;
+use ropey::{Rope, RopeSlice};
+use std::borrow::Cow;
+use std::ops::Range;
+
+/// text of `line` without its line break, borrowed when it is contiguous
+pub fn line_str(line: RopeSlice) -> Cow<str> {
+    let len = match line.chars().last() {
+        Some('\n') => line.len_chars() - 1,
+        _ => line.len_chars(),
+    };
+    let line = line.slice(..len);
+
+    match line.as_str() {
+        Some(s) => Cow::Borrowed(s),
+        None => Cow::Owned(line.to_string()),
+    }
+}
+
+/// char columns of the matches of `re` in `line`
+pub fn line_matches(line: RopeSlice, re: &Regex) -> Vec<Range<usize>> {
+    let text = line_str(line);
+
+    // matches come in order, so chars are counted once along the line
+    let (mut byte, mut col) = (0, 0);
+    let mut to_col = |at: usize| {
+        col += text[byte..at].chars().count();
+        byte = at;
+        col
+    };
+
+    re.find_iter(&text)
+        .map(|m| to_col(m.start())..to_col(m.end()))
+        .collect()
+}
+
+/// Char range of the next match of `re` after char `from`, or the one
+/// before it going backward, wrapping around the ends of the text
+pub fn find(text: &Rope, re: &Regex, from: usize, forward: bool) -> Option<Range<usize>> {
+    let from = from.min(text.len_chars());
+    let row = text.char_to_line(from);
+    let col = from - text.line_to_char(row);
+    let lines = text.len_lines();
+
+    let in_line = |row: usize, pick: &dyn Fn(&Range<usize>) -> bool| {
+        let start = text.line_to_char(row);
+        let mut matches = line_matches(text.line(row), re).into_iter().filter(pick);
+        let found = match forward {
+            true => matches.next(),
+            false => matches.last(),
+        };
+
+        found.map(|m| start + m.start..start + m.end)
+    };
+
+    // the rest of the cursor line, the lines after it then, wrapping, the
+    // lines before it and the cursor line up to the cursor
+    let rows: Vec<usize> = match forward {
+        true => (row + 1..lines).chain(0..row).collect(),
+        false => (0..row).rev().chain((row + 1..lines).rev()).collect(),
+    };
+
+    let first = match forward {
+        true => in_line(row, &|m| m.start > col),
+        false => in_line(row, &|m| m.start < col),
+    };
+
+    if first.is_some() {
+        return first;
+    }
+
+    if let Some(found) = rows.into_iter().find_map(|r| in_line(r, &|_| true)) {
+        return Some(found);
+    }
+
+    match forward {
+        true => in_line(row, &|m| m.start <= col),
+        false => in_line(row, &|m| m.start >= col),
+    }
+}